@@ -47,7 +47,7 @@ async fn main() {
     discv5.start(listen_addr);
 
     // search for the ENR
-    match discv5.request_enr(multiaddr).await {
+    match discv5.request_enr(multiaddr, None).await {
         Ok(Some(enr)) => {
             println!("ENR Found:");
             println!("Base64:{}", enr.to_base64());
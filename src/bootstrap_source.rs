@@ -0,0 +1,355 @@
+//! Sources of ENRs used to bootstrap the routing table when the server is started.
+//!
+//! Sources are configured via [`Discv5ConfigBuilder::bootstrap_sources`]. The sources are
+//! resolved concurrently on a background thread once the server is started, the resulting ENRs
+//! are merged (keeping the ENR with the highest sequence number for each node) and added to the
+//! routing table as bootnodes. The outcome of each source is available from [`Discv5::bootstrap_source_results`].
+//!
+//! [`Discv5ConfigBuilder::bootstrap_sources`]: crate::Discv5ConfigBuilder::bootstrap_sources
+//! [`Discv5::bootstrap_source_results`]: crate::Discv5::bootstrap_source_results
+use crate::Enr;
+use data_encoding::{BASE32_NOPAD, BASE64URL_NOPAD};
+use enr::NodeId;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tiny_keccak::{Hasher, Keccak};
+
+/// The maximum number of entries visited when resolving an ENR tree from DNS.
+const MAX_TREE_ENTRIES: usize = 1000;
+
+/// Looks up the TXT records of a DNS name. Each returned string is one record, with the
+/// character strings of the record concatenated.
+pub type TxtResolver = Arc<dyn Fn(&str) -> Result<Vec<String>, String> + Send + Sync>;
+
+/// A source of bootstrap ENRs.
+#[derive(Clone)]
+pub enum BootstrapSource {
+    /// A static list of ENRs.
+    Enrs(Vec<Enr>),
+    /// A file containing one base64 encoded ENR per line. Empty lines and lines starting with `#`
+    /// are ignored.
+    File(PathBuf),
+    /// An EIP-1459 ENR tree published in DNS, given as an `enrtree://<public key>@<domain>` URL.
+    ///
+    /// The TXT records are looked up with `resolver`, as this crate does not include a DNS
+    /// client. The signature of the tree root and the hash of every entry are verified. Links to
+    /// other trees are not followed.
+    Dns { url: String, resolver: TxtResolver },
+}
+
+impl BootstrapSource {
+    /// Resolves the source into a list of ENRs.
+    pub fn resolve(&self) -> Result<Vec<Enr>, String> {
+        match self {
+            BootstrapSource::Enrs(enrs) => Ok(enrs.clone()),
+            BootstrapSource::File(path) => {
+                let contents = std::fs::read_to_string(path)
+                    .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(|line| line.parse::<Enr>())
+                    .collect()
+            }
+            BootstrapSource::Dns { url, resolver } => resolve_tree(url, resolver.as_ref()),
+        }
+    }
+}
+
+impl std::fmt::Debug for BootstrapSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BootstrapSource::Enrs(enrs) => f.debug_tuple("Enrs").field(enrs).finish(),
+            BootstrapSource::File(path) => f.debug_tuple("File").field(path).finish(),
+            BootstrapSource::Dns { url, .. } => f.debug_struct("Dns").field("url", url).finish(),
+        }
+    }
+}
+
+impl std::fmt::Display for BootstrapSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BootstrapSource::Enrs(enrs) => write!(f, "ENR list ({} entries)", enrs.len()),
+            BootstrapSource::File(path) => write!(f, "file: {}", path.display()),
+            BootstrapSource::Dns { url, .. } => write!(f, "DNS: {}", url),
+        }
+    }
+}
+
+/// Resolves all sources, returning the merged ENRs and the per-source result. The result of each
+/// source is the number of ENRs it provided, or the reason it failed to resolve.
+///
+/// ENRs are deduplicated by `NodeId`, keeping the ENR with the highest sequence number.
+pub(crate) fn resolve_sources(
+    sources: &[BootstrapSource],
+) -> (Vec<Enr>, Vec<Result<usize, String>>) {
+    let mut merged: HashMap<NodeId, Enr> = HashMap::new();
+    let mut results = Vec::with_capacity(sources.len());
+
+    // each source may block on the file system or DNS, so they are resolved in parallel
+    let handles: Vec<_> = sources
+        .iter()
+        .cloned()
+        .map(|source| std::thread::spawn(move || source.resolve()))
+        .collect();
+
+    for handle in handles {
+        let result = handle
+            .join()
+            .unwrap_or_else(|_| Err("Resolving the source panicked".into()));
+        match result {
+            Ok(enrs) => {
+                results.push(Ok(enrs.len()));
+                for enr in enrs {
+                    match merged.get(&enr.node_id()) {
+                        Some(known) if known.seq() >= enr.seq() => {}
+                        _ => {
+                            merged.insert(enr.node_id(), enr);
+                        }
+                    }
+                }
+            }
+            Err(e) => results.push(Err(e)),
+        }
+    }
+    (merged.into_values().collect(), results)
+}
+
+/// Resolves the ENRs of the EIP-1459 tree at `url`.
+fn resolve_tree(
+    url: &str,
+    resolver: &(dyn Fn(&str) -> Result<Vec<String>, String> + Send + Sync),
+) -> Result<Vec<Enr>, String> {
+    let (public_key, domain) = parse_tree_url(url)?;
+    let root = lookup(resolver, domain, "enrtree-root:")?;
+    let enr_root = verify_root(&root, &public_key)?;
+
+    let mut enrs = Vec::new();
+    let mut pending = vec![enr_root];
+    let mut visited = 0;
+    while let Some(hash) = pending.pop() {
+        visited += 1;
+        if visited > MAX_TREE_ENTRIES {
+            return Err(format!(
+                "ENR tree has more than {} entries",
+                MAX_TREE_ENTRIES
+            ));
+        }
+        let entry = lookup(resolver, &format!("{}.{}", hash, domain), "enr")?;
+        if !entry_hash(&entry).eq_ignore_ascii_case(&hash) {
+            return Err(format!("ENR tree entry {} does not match its hash", hash));
+        }
+        if let Some(children) = entry.strip_prefix("enrtree-branch:") {
+            pending.extend(
+                children
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|child| !child.is_empty())
+                    .map(String::from),
+            );
+        } else if entry.starts_with("enr:") {
+            enrs.push(entry.parse::<Enr>()?);
+        } else if !entry.starts_with("enrtree://") {
+            return Err(format!("Invalid ENR tree entry: {}", entry));
+        }
+    }
+    Ok(enrs)
+}
+
+/// Splits an `enrtree://<public key>@<domain>` URL into the public key and the domain.
+fn parse_tree_url(url: &str) -> Result<(secp256k1::PublicKey, &str), String> {
+    let (key, domain) = url
+        .strip_prefix("enrtree://")
+        .and_then(|rest| rest.split_once('@'))
+        .ok_or_else(|| format!("Invalid ENR tree URL: {}", url))?;
+    let key = BASE32_NOPAD
+        .decode(key.to_ascii_uppercase().as_bytes())
+        .map_err(|e| format!("Invalid ENR tree public key: {}", e))?;
+    if key.len() != 33 {
+        return Err("Invalid ENR tree public key length".into());
+    }
+    let mut compressed = [0u8; 33];
+    compressed.copy_from_slice(&key);
+    let public_key = secp256k1::PublicKey::parse_compressed(&compressed)
+        .map_err(|e| format!("Invalid ENR tree public key: {:?}", e))?;
+    Ok((public_key, domain))
+}
+
+/// Returns the TXT record of `name` that starts with `prefix`.
+fn lookup(
+    resolver: &(dyn Fn(&str) -> Result<Vec<String>, String> + Send + Sync),
+    name: &str,
+    prefix: &str,
+) -> Result<String, String> {
+    resolver(name)?
+        .into_iter()
+        .find(|record| record.starts_with(prefix))
+        .ok_or_else(|| format!("No ENR tree record at {}", name))
+}
+
+/// Verifies the signature of an `enrtree-root:v1` record, returning the hash of the root of the
+/// ENR subtree.
+fn verify_root(root: &str, public_key: &secp256k1::PublicKey) -> Result<String, String> {
+    let sig_start = root
+        .find(" sig=")
+        .ok_or_else(|| "ENR tree root is not signed".to_string())?;
+    let (signed, sig) = root.split_at(sig_start);
+    let sig = BASE64URL_NOPAD
+        .decode(sig[" sig=".len()..].trim().as_bytes())
+        .map_err(|e| format!("Invalid ENR tree root signature: {}", e))?;
+    if sig.len() != 65 {
+        return Err("Invalid ENR tree root signature length".into());
+    }
+    let mut sig_bytes = [0u8; 64];
+    sig_bytes.copy_from_slice(&sig[..64]);
+    let signature = secp256k1::Signature::parse(&sig_bytes);
+    let message = secp256k1::Message::parse(&keccak256(signed.as_bytes()));
+    if !secp256k1::verify(&message, &signature, public_key) {
+        return Err("Invalid ENR tree root signature".into());
+    }
+
+    let mut fields = signed
+        .strip_prefix("enrtree-root:v1")
+        .ok_or_else(|| format!("Unsupported ENR tree root: {}", root))?
+        .split_whitespace();
+    fields
+        .find_map(|field| field.strip_prefix("e="))
+        .map(String::from)
+        .ok_or_else(|| "ENR tree root has no ENR subtree".to_string())
+}
+
+/// The name of a tree entry: the base32 encoding of the first 16 bytes of its keccak256 hash.
+fn entry_hash(entry: &str) -> String {
+    BASE32_NOPAD.encode(&keccak256(entry.as_bytes())[..16])
+}
+
+fn keccak256(input: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    let mut output = [0u8; 32];
+    hasher.update(input);
+    hasher.finalize(&mut output);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enr::{CombinedKey, EnrBuilder};
+
+    #[test]
+    fn test_resolve_sources_keeps_highest_seq() {
+        let key = CombinedKey::generate_secp256k1();
+        let old_enr = EnrBuilder::new("v4").build(&key).unwrap();
+        let mut new_enr = old_enr.clone();
+        new_enr.insert("test", vec![1], &key).unwrap();
+        let other_enr = EnrBuilder::new("v4")
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!("discv5-bootstrap-{}", NodeId::random()));
+        std::fs::write(
+            &path,
+            format!(
+                "# bootnodes\n{}\n\n{}\n",
+                new_enr.to_base64(),
+                other_enr.to_base64()
+            ),
+        )
+        .unwrap();
+
+        let sources = vec![
+            BootstrapSource::Enrs(vec![old_enr]),
+            BootstrapSource::File(path.clone()),
+            BootstrapSource::File(path.with_extension("missing")),
+        ];
+        let (enrs, results) = resolve_sources(&sources);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(results[0], Ok(1));
+        assert_eq!(results[1], Ok(2));
+        assert!(results[2].is_err());
+
+        assert_eq!(enrs.len(), 2);
+        assert!(enrs.contains(&new_enr));
+        assert!(enrs.contains(&other_enr));
+    }
+
+    #[test]
+    fn test_resolve_dns_tree() {
+        let tree_key = secp256k1::SecretKey::random(&mut rand::thread_rng());
+        let public_key = secp256k1::PublicKey::from_secret_key(&tree_key);
+        let url = format!(
+            "enrtree://{}@nodes.example.org",
+            BASE32_NOPAD.encode(&public_key.serialize_compressed())
+        );
+
+        let enr_entries: Vec<_> = (0..3)
+            .map(|_| {
+                EnrBuilder::new("v4")
+                    .build(&CombinedKey::generate_secp256k1())
+                    .unwrap()
+                    .to_base64()
+            })
+            .collect();
+        let mut records = HashMap::new();
+        for entry in &enr_entries {
+            records.insert(
+                format!("{}.nodes.example.org", entry_hash(entry)),
+                entry.clone(),
+            );
+        }
+        let branch = format!(
+            "enrtree-branch:{}",
+            enr_entries
+                .iter()
+                .map(|entry| entry_hash(entry))
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        records.insert(
+            format!("{}.nodes.example.org", entry_hash(&branch)),
+            branch.clone(),
+        );
+
+        let signed = format!(
+            "enrtree-root:v1 e={} l={} seq=1",
+            entry_hash(&branch),
+            "A".repeat(26)
+        );
+        let message = secp256k1::Message::parse(&keccak256(signed.as_bytes()));
+        let (signature, recovery_id) = secp256k1::sign(&message, &tree_key);
+        let mut sig = signature.serialize().to_vec();
+        sig.push(recovery_id.serialize());
+        let root = format!("{} sig={}", signed, BASE64URL_NOPAD.encode(&sig));
+        records.insert("nodes.example.org".into(), root.clone());
+
+        let records = Arc::new(records);
+        let resolver_records = records.clone();
+        let resolver: TxtResolver = Arc::new(move |name: &str| {
+            Ok(resolver_records.get(name).cloned().into_iter().collect())
+        });
+        let source = BootstrapSource::Dns {
+            url: url.clone(),
+            resolver,
+        };
+        let mut enrs: Vec<_> = source
+            .resolve()
+            .unwrap()
+            .iter()
+            .map(Enr::to_base64)
+            .collect();
+        enrs.sort();
+        let mut expected = enr_entries.clone();
+        expected.sort();
+        assert_eq!(enrs, expected);
+
+        // a root modified after it was signed is rejected
+        let mut forged = (*records).clone();
+        forged.insert("nodes.example.org".into(), root.replace("seq=1", "seq=2"));
+        let resolver: TxtResolver =
+            Arc::new(move |name: &str| Ok(forged.get(name).cloned().into_iter().collect()));
+        assert!(BootstrapSource::Dns { url, resolver }.resolve().is_err());
+    }
+}
@@ -1,7 +1,12 @@
-use crate::{Enr, Executor, FilterConfig, PermitBanList};
+use crate::{BootstrapSource, Enr, Executor, FilterConfig, PermitBanList};
 ///! A set of configuration parameters to tune the discovery protocol.
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 
+/// Decides whether to keep a newly established session with a peer at the given socket.
+type SessionFilter = Arc<dyn Fn(&Enr, &SocketAddr) -> bool + Send + Sync>;
+
 /// Configuration parameters that define the performance of the gossipsub network.
 #[derive(Clone)]
 pub struct Discv5Config {
@@ -16,6 +21,10 @@ pub struct Discv5Config {
     /// Hence, we may potentially end up making more requests to good peers. Default: 2 seconds.
     pub query_peer_timeout: Duration,
 
+    /// Overrides the `query_peer_timeout` for the background queries that refresh the routing
+    /// table and bootstrap it, which can tolerate slower peers than user queries. Default: None.
+    pub background_query_peer_timeout: Option<Duration>,
+
     /// The timeout for an entire query. Any peers discovered for this query are returned. Default 60 seconds.
     pub query_timeout: Duration,
 
@@ -25,39 +34,198 @@ pub struct Discv5Config {
     /// The session timeout for each node. Default: 1 day.
     pub session_timeout: Duration,
 
-    /// The maximum number of established sessions to maintain. Default: 100.
+    /// The maximum number of established sessions to maintain. Once full, the least recently
+    /// used session is evicted and reported with `Discv5Event::SessionEvicted`. Default: 100.
     pub session_cache_capacity: usize,
 
-    /// Updates the local ENR IP and port based on PONG responses from peers. Default: true.
+    /// Re-establishes the session with a connected peer when its session expires, by sending it a
+    /// PING that triggers a new handshake. This avoids the latency of a handshake on the next
+    /// request to the peer. Expired sessions are reported with `Discv5Event::SessionExpired`
+    /// regardless. Default: false.
+    pub reestablish_expired_sessions: bool,
+
+    /// Updates the local ENR IP and port based on PONG responses from peers. Disabling this
+    /// disables the updates of both address families. Default: true.
     pub enr_update: bool,
 
+    /// Updates the local ENR IPv4 address and port based on PONG responses from peers. Only
+    /// applies if `enr_update` is set. Default: true.
+    pub enr_update_v4: bool,
+
+    /// Updates the local ENR IPv6 address and port based on PONG responses from peers. Only
+    /// applies if `enr_update` is set. Default: true.
+    pub enr_update_v6: bool,
+
     /// The minimum number of peer's who agree on an external IP port before updating the
     /// local ENR. Default: 10.
     pub enr_peer_update_min: usize,
 
+    /// The minimum amount a peer's advertised ENR sequence number must exceed the known one by
+    /// before its ENR is requested. The ENR of each peer is requested at most once every 30
+    /// seconds. Default: 1.
+    pub enr_refresh_min_gap: u64,
+
     /// The number of peers to request in parallel in a single query. Default: 3.
     pub query_parallelism: usize,
 
+    /// The number of adjacent log2 distances requested from each peer in a `FINDNODE` query,
+    /// starting with the distance of the target from the peer. Each distance is a separate
+    /// request, so a larger spread costs more round-trips and larger responses per peer, but
+    /// makes it more likely that a peer returns the target. The probability that a peer returns
+    /// any given target peer is `1 - 0.5**findnode_distance_spread`. Default: 3.
+    pub findnode_distance_spread: u64,
+
+    /// The maximum number of queries that may be active at once. Further queries fail with
+    /// `QueryError::TooManyConcurrent` until an active query completes. Default: 100.
+    pub max_concurrent_queries: usize,
+
     /// Limits the number of IP addresses from the same
     /// /24 subnet in the kbuckets table. This is to mitigate eclipse attacks. Default: false.
     pub ip_limit: bool,
 
+    /// Only inserts nodes whose ENR contains a UDP socket into the routing table, such that the
+    /// table only holds peers that can be contacted. Stored ENRs are also not updated to records
+    /// without a UDP socket. ENRs added with `Discv5::add_enr` always require a UDP socket.
+    /// Default: false.
+    pub require_contactable: bool,
+
     /// A filter used to decide whether to insert nodes into our local routing table. Nodes can be
-    /// excluded if they do not pass this filter. The default is to accept all nodes.
-    pub table_filter: fn(&Enr) -> bool,
+    /// excluded if they do not pass this filter. The filter may capture state, such as an
+    /// allow-list that is updated at runtime. The default is to accept all nodes.
+    pub table_filter: Arc<dyn Fn(&Enr) -> bool + Send + Sync>,
+
+    /// A filter used to decide whether to keep a newly established session with a peer. Sessions
+    /// with peers that do not pass this filter are dropped and the peers are not added to the
+    /// routing table. The filter may capture state. The default is to accept all peers.
+    pub session_filter: SessionFilter,
 
     /// The time between pings to ensure connectivity amongst connected nodes. Default: 300
     /// seconds.
     pub ping_interval: Duration,
 
+    /// The number of consecutive PINGs a connected peer may fail before it is marked as
+    /// disconnected. After each failure the peer's ping interval is doubled, up to 8 times
+    /// `ping_interval`, and it is reset by any PONG. A value of 1 disconnects peers on the first
+    /// failure. Default: 3.
+    pub ping_failure_limit: u32,
+
+    /// If set, nodes that have been disconnected for longer than this are removed from the
+    /// routing table, rather than remaining until they are replaced by a new node. Disconnected
+    /// nodes are checked each `ping_interval`. Default: None.
+    pub disconnected_node_timeout: Option<Duration>,
+
+    /// The time a node waits to be inserted into a full bucket, while the bucket's least-recently
+    /// connected disconnected node is pinged. If that node does not respond within this time it
+    /// is evicted and the waiting node inserted. Default: 60 seconds.
+    pub pending_eviction_timeout: Duration,
+
+    /// The time between background refreshes of the routing table. Each refresh searches for a
+    /// random target in a bucket selected at random, biased towards the emptiest buckets.
+    /// Default: 300 seconds.
+    pub refresh_interval: Duration,
+
+    /// The time requests to the handler may wait for capacity in its channel before a
+    /// `Discv5Event::HandlerUnresponsive` is produced. Default: 10 seconds.
+    pub handler_unresponsive_timeout: Duration,
+
+    /// The maximum encoded size of an ENR. Updates to the local ENR that would exceed this size
+    /// are rejected, as are larger ENRs received from peers. Larger ENRs in the routing table are
+    /// not sent in NODES responses. Default: 300 bytes.
+    pub max_enr_size: usize,
+
+    /// Bans peers that send ENRs exceeding `max_enr_size` for `ban_duration`. Default: false.
+    pub ban_oversized_enrs: bool,
+
+    /// The maximum number of ENRs discovered during a query that are held to contact the
+    /// discovered peers. Once exceeded, only the ENRs closest to the query target are kept.
+    /// Default: 256.
+    pub max_untrusted_enrs_per_query: usize,
+
+    /// The number of consecutive failed requests to a bootnode before it is no longer contacted
+    /// for `bootnode_cooldown`. Default: 5.
+    pub bootnode_failure_threshold: usize,
+
+    /// The duration an unreachable bootnode is not contacted for, before a single request is
+    /// attempted again. Default: 10 minutes.
+    pub bootnode_cooldown: Duration,
+
+    /// Reports discovered nodes with the `Discv5Event::DiscoveredRaw` event, which includes the
+    /// encoded ENR, instead of `Discv5Event::Discovered`. Default: false.
+    pub report_discovered_raw: bool,
+
+    /// When a query finishes, the ENR of a resulting peer may no longer be known, for example if
+    /// the peer was removed from the routing table during the query. If set, the latest ENR of
+    /// such peers is requested before the results are returned, bounded by the request timeout.
+    /// Otherwise these peers are dropped from the results. Default: false.
+    pub fetch_missing_result_enrs: bool,
+
+    /// If set, as `(threshold, window)`, a peer that sends more than `threshold` identical
+    /// FINDNODE requests within `window` is sent an empty response. This dampens query loops
+    /// involving misconfigured peers and is mostly useful for bootnodes. Default: None.
+    pub findnode_dampening: Option<(usize, Duration)>,
+
+    /// Whether to answer FINDNODE requests for a distance at which we know of no nodes with an
+    /// empty NODES response. The specification requires a response, so disabling this is not
+    /// spec-compliant: requesting peers time out and retry, and may consider us unresponsive.
+    /// This avoids confirming liveness to scanners at these distances. Dampened responses are
+    /// still sent. Default: true.
+    pub answer_empty_findnode: bool,
+
+    /// Whether to run in passive mode, where every FINDNODE request is answered with an empty
+    /// NODES response and the contents of the routing table are never shared. PINGs and requests
+    /// for our own ENR are still answered, keeping sessions alive, and queries run as usual. This is
+    /// non-cooperative behaviour: the node takes part in discovery without contributing to the
+    /// routing tables of others, and is intended for crawlers. Default: false.
+    pub passive_mode: bool,
+
+    /// The time nodes must wait after being issued a ticket before they can register as an
+    /// advertiser of a topic with it. Default: 10 seconds.
+    pub ticket_wait_time: Duration,
+
+    /// The minimum number of peers the routing table must contain before a query is started.
+    /// Queries wait up to `query_seed_wait_timeout` for the table to be populated, for example by
+    /// bootstrapping. Default: 0.
+    pub query_min_seed_peers: usize,
+
+    /// The maximum duration a query waits for `query_min_seed_peers` peers to be known before
+    /// failing. Default: 10 seconds.
+    pub query_seed_wait_timeout: Duration,
+
+    /// If set, the packets of a NODES response that spans multiple packets are sent this far
+    /// apart, rather than back-to-back. This avoids bursts that may be dropped on rate-limited
+    /// paths. Default: None.
+    pub inter_packet_delay: Option<Duration>,
+
+    /// The maximum number of packets accepted for a single NODES response. Nodes in further
+    /// packets are ignored. Default: 5.
+    pub max_nodes_responses: usize,
+
+    /// The duration of the ban applied to peers that send invalid ENRs. If `None`, the ban is
+    /// permanent. Default: None.
+    pub ban_duration: Option<Duration>,
+
     /// A set of configuration parameters for the inbound packet filter. See `FilterConfig` for
-    /// default values.
+    /// default values. Its request limits are enforced on unsolicited packets unless
+    /// `FilterConfig::rate_limit_enabled` is unset, even if the packet filter is not enabled.
     pub filter_config: FilterConfig,
 
+    /// The size to request for the UDP socket's receive buffer (`SO_RCVBUF`). Busy nodes, such as
+    /// bootnodes, may drop packets with the default OS buffer. The OS may clamp the size, the
+    /// granted size is logged. If None, the OS default is kept. Default: None.
+    pub socket_recv_buffer_size: Option<usize>,
+
+    /// The size to request for the UDP socket's send buffer (`SO_SNDBUF`). The OS may clamp the
+    /// size, the granted size is logged. If None, the OS default is kept. Default: None.
+    pub socket_send_buffer_size: Option<usize>,
+
     /// A set of lists that permit or ban IP's or NodeIds from the server. See
     /// `crate::PermitBanList`.
     pub permit_ban_list: PermitBanList,
 
+    /// Sources of ENRs that are resolved in the background and added to the routing table as
+    /// bootnodes when the server is started. Default: empty.
+    pub bootstrap_sources: Vec<BootstrapSource>,
+
     /// A custom executor which can spawn the discv5 tasks. This must be a tokio runtime, with
     /// timing support. By default, the executor that created the discv5 struct will be used.
     pub executor: Option<Box<dyn Executor + Send + Sync>>,
@@ -69,18 +237,51 @@ impl Default for Discv5Config {
             enable_packet_filter: false,
             request_timeout: Duration::from_secs(4),
             query_peer_timeout: Duration::from_secs(2),
+            background_query_peer_timeout: None,
             query_timeout: Duration::from_secs(60),
             request_retries: 1,
             session_timeout: Duration::from_secs(86400),
             session_cache_capacity: 100,
+            reestablish_expired_sessions: false,
             enr_update: true,
+            enr_update_v4: true,
+            enr_update_v6: true,
             enr_peer_update_min: 10,
+            enr_refresh_min_gap: 1,
             query_parallelism: 3,
+            findnode_distance_spread: 3,
+            max_concurrent_queries: 100,
             ip_limit: false,
-            table_filter: |_| true,
+            require_contactable: false,
+            table_filter: Arc::new(|_| true),
+            session_filter: Arc::new(|_, _| true),
             ping_interval: Duration::from_secs(300),
+            ping_failure_limit: 3,
+            disconnected_node_timeout: None,
+            pending_eviction_timeout: Duration::from_secs(60),
+            refresh_interval: Duration::from_secs(300),
+            handler_unresponsive_timeout: Duration::from_secs(10),
+            max_enr_size: 300,
+            ban_oversized_enrs: false,
+            max_untrusted_enrs_per_query: 256,
+            bootnode_failure_threshold: 5,
+            bootnode_cooldown: Duration::from_secs(600),
+            report_discovered_raw: false,
+            fetch_missing_result_enrs: false,
+            findnode_dampening: None,
+            answer_empty_findnode: true,
+            passive_mode: false,
+            ticket_wait_time: Duration::from_secs(10),
+            query_min_seed_peers: 0,
+            query_seed_wait_timeout: Duration::from_secs(10),
+            inter_packet_delay: None,
+            max_nodes_responses: 5,
+            ban_duration: None,
             filter_config: FilterConfig::default(),
+            socket_recv_buffer_size: None,
+            socket_send_buffer_size: None,
             permit_ban_list: PermitBanList::default(),
+            bootstrap_sources: Vec::new(),
             executor: None,
         }
     }
@@ -132,6 +333,13 @@ impl Discv5ConfigBuilder {
         self
     }
 
+    /// Overrides the `query_peer_timeout` for the background queries that refresh the routing
+    /// table and bootstrap it.
+    pub fn background_query_peer_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.background_query_peer_timeout = Some(timeout);
+        self
+    }
+
     /// The number of retries for each UDP request.
     pub fn request_retries(&mut self, retries: u8) -> &mut Self {
         self.config.request_retries = retries;
@@ -150,12 +358,32 @@ impl Discv5ConfigBuilder {
         self
     }
 
+    /// Re-establishes the session with a connected peer when its session expires.
+    pub fn reestablish_expired_sessions(&mut self) -> &mut Self {
+        self.config.reestablish_expired_sessions = true;
+        self
+    }
+
     /// Disables the auto-update of the local ENR IP and port based on PONG responses from peers.
     pub fn disable_enr_update(&mut self) -> &mut Self {
         self.config.enr_update = false;
         self
     }
 
+    /// Disables the auto-update of the local ENR IPv4 address and port, for nodes with a static
+    /// IPv4 address.
+    pub fn disable_enr_update_v4(&mut self) -> &mut Self {
+        self.config.enr_update_v4 = false;
+        self
+    }
+
+    /// Disables the auto-update of the local ENR IPv6 address and port, for nodes with a static
+    /// IPv6 address.
+    pub fn disable_enr_update_v6(&mut self) -> &mut Self {
+        self.config.enr_update_v6 = false;
+        self
+    }
+
     /// The minimum number of peer's who agree on an external IP port before updating the
     /// local ENR.
     pub fn enr_peer_update_min(&mut self, min: usize) -> &mut Self {
@@ -166,12 +394,38 @@ impl Discv5ConfigBuilder {
         self
     }
 
+    /// The minimum amount a peer's advertised ENR sequence number must exceed the known one by
+    /// before its ENR is requested.
+    pub fn enr_refresh_min_gap(&mut self, gap: u64) -> &mut Self {
+        if gap == 0 {
+            panic!("Setting enr_refresh_min_gap to 0 would request ENRs that are already known");
+        }
+        self.config.enr_refresh_min_gap = gap;
+        self
+    }
+
     /// The number of peers to request in parallel in a single query.
     pub fn query_parallelism(&mut self, parallelism: usize) -> &mut Self {
         self.config.query_parallelism = parallelism;
         self
     }
 
+    /// The number of adjacent log2 distances requested from each peer in a `FINDNODE` query.
+    /// Must be between 1 and 127.
+    pub fn findnode_distance_spread(&mut self, spread: u64) -> &mut Self {
+        if spread == 0 || spread > 127 {
+            panic!("The FINDNODE distance spread must be between 1 and 127");
+        }
+        self.config.findnode_distance_spread = spread;
+        self
+    }
+
+    /// The maximum number of queries that may be active at once.
+    pub fn max_concurrent_queries(&mut self, max_queries: usize) -> &mut Self {
+        self.config.max_concurrent_queries = max_queries;
+        self
+    }
+
     /// Limits the number of IP addresses from the same
     /// /24 subnet in the kbuckets table. This is to mitigate eclipse attacks.
     pub fn ip_limit(&mut self) -> &mut Self {
@@ -179,10 +433,30 @@ impl Discv5ConfigBuilder {
         self
     }
 
+    /// Only inserts nodes whose ENR contains a UDP socket into the routing table.
+    pub fn require_contactable(&mut self) -> &mut Self {
+        self.config.require_contactable = true;
+        self
+    }
+
     /// A filter used to decide whether to insert nodes into our local routing table. Nodes can be
     /// excluded if they do not pass this filter.
-    pub fn table_filter(&mut self, filter: fn(&Enr) -> bool) -> &mut Self {
-        self.config.table_filter = filter;
+    pub fn table_filter<F>(&mut self, filter: F) -> &mut Self
+    where
+        F: Fn(&Enr) -> bool + Send + Sync + 'static,
+    {
+        self.config.table_filter = Arc::new(filter);
+        self
+    }
+
+    /// A filter used to decide whether to keep a newly established session with a peer. Peers
+    /// that do not pass this filter have their session dropped and are not added to the routing
+    /// table.
+    pub fn session_filter<F>(&mut self, filter: F) -> &mut Self
+    where
+        F: Fn(&Enr, &SocketAddr) -> bool + Send + Sync + 'static,
+    {
+        self.config.session_filter = Arc::new(filter);
         self
     }
 
@@ -192,12 +466,176 @@ impl Discv5ConfigBuilder {
         self
     }
 
-    /// A set of configuration parameters for the inbound packet filter.
+    /// The number of consecutive PINGs a connected peer may fail, with an increasing interval
+    /// between them, before it is marked as disconnected.
+    pub fn ping_failure_limit(&mut self, limit: u32) -> &mut Self {
+        self.config.ping_failure_limit = limit;
+        self
+    }
+
+    /// Removes nodes from the routing table that have been disconnected for longer than the
+    /// given duration.
+    pub fn disconnected_node_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.disconnected_node_timeout = Some(timeout);
+        self
+    }
+
+    /// The time a node waits to be inserted into a full bucket before the disconnected node it
+    /// would replace is evicted.
+    pub fn pending_eviction_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.pending_eviction_timeout = timeout;
+        self
+    }
+
+    /// The time between background refreshes of the routing table.
+    pub fn refresh_interval(&mut self, interval: Duration) -> &mut Self {
+        self.config.refresh_interval = interval;
+        self
+    }
+
+    /// The time requests to the handler may wait for capacity in its channel before the handler
+    /// is reported as unresponsive.
+    pub fn handler_unresponsive_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.handler_unresponsive_timeout = timeout;
+        self
+    }
+
+    /// The maximum encoded size of an ENR. Larger updates to the local ENR and ENRs received
+    /// from peers are rejected.
+    pub fn max_enr_size(&mut self, size: usize) -> &mut Self {
+        self.config.max_enr_size = size;
+        self
+    }
+
+    /// Bans peers that send ENRs exceeding the maximum ENR size.
+    pub fn ban_oversized_enrs(&mut self) -> &mut Self {
+        self.config.ban_oversized_enrs = true;
+        self
+    }
+
+    /// The maximum number of ENRs discovered during a query that are held to contact the
+    /// discovered peers. Only the ENRs closest to the query target are kept.
+    pub fn max_untrusted_enrs_per_query(&mut self, max: usize) -> &mut Self {
+        if max == 0 {
+            panic!("The maximum number of untrusted ENRs per query must be at least 1");
+        }
+        self.config.max_untrusted_enrs_per_query = max;
+        self
+    }
+
+    /// The number of consecutive failed requests to a bootnode before it is no longer contacted
+    /// for the bootnode cooldown.
+    pub fn bootnode_failure_threshold(&mut self, threshold: usize) -> &mut Self {
+        if threshold == 0 {
+            panic!("Setting bootnode_failure_threshold to 0 would never contact bootnodes");
+        }
+        self.config.bootnode_failure_threshold = threshold;
+        self
+    }
+
+    /// The duration an unreachable bootnode is not contacted for, before a single request is
+    /// attempted again.
+    pub fn bootnode_cooldown(&mut self, cooldown: Duration) -> &mut Self {
+        self.config.bootnode_cooldown = cooldown;
+        self
+    }
+
+    /// Reports discovered nodes with the `Discv5Event::DiscoveredRaw` event, which includes the
+    /// encoded ENR, instead of `Discv5Event::Discovered`.
+    pub fn report_discovered_raw(&mut self) -> &mut Self {
+        self.config.report_discovered_raw = true;
+        self
+    }
+
+    /// Requests the ENRs of query results that are no longer known before returning the results
+    /// of a query, rather than dropping them.
+    pub fn fetch_missing_result_enrs(&mut self) -> &mut Self {
+        self.config.fetch_missing_result_enrs = true;
+        self
+    }
+
+    /// Sends empty responses to peers that send more than `threshold` identical FINDNODE requests
+    /// within `window`.
+    pub fn findnode_dampening(&mut self, threshold: usize, window: Duration) -> &mut Self {
+        self.config.findnode_dampening = Some((threshold, window));
+        self
+    }
+
+    /// Does not respond to FINDNODE requests for distances at which no nodes are known, rather
+    /// than sending an empty NODES response. This is not spec-compliant.
+    pub fn disable_empty_findnode_responses(&mut self) -> &mut Self {
+        self.config.answer_empty_findnode = false;
+        self
+    }
+
+    /// Answers all FINDNODE requests with empty NODES responses, never sharing the routing table.
+    /// This is non-cooperative and intended for crawlers.
+    pub fn enable_passive_mode(&mut self) -> &mut Self {
+        self.config.passive_mode = true;
+        self
+    }
+
+    /// The time nodes must wait after being issued a ticket before they can register as an
+    /// advertiser of a topic with it.
+    pub fn ticket_wait_time(&mut self, wait_time: Duration) -> &mut Self {
+        self.config.ticket_wait_time = wait_time;
+        self
+    }
+
+    /// The minimum number of peers the routing table must contain before a query is started.
+    pub fn query_min_seed_peers(&mut self, peers: usize) -> &mut Self {
+        self.config.query_min_seed_peers = peers;
+        self
+    }
+
+    /// The maximum duration a query waits for the minimum number of seed peers to be known.
+    pub fn query_seed_wait_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.query_seed_wait_timeout = timeout;
+        self
+    }
+
+    /// Spaces out the packets of multi-packet NODES responses by the given delay.
+    pub fn inter_packet_delay(&mut self, delay: Duration) -> &mut Self {
+        self.config.inter_packet_delay = Some(delay);
+        self
+    }
+
+    /// The maximum number of packets accepted for a single NODES response.
+    pub fn max_nodes_responses(&mut self, max: usize) -> &mut Self {
+        if max < 1 {
+            panic!("Setting max_nodes_responses to a value less than 1 will ignore all NODES responses");
+        }
+        self.config.max_nodes_responses = max;
+        self
+    }
+
+    /// The duration of the ban applied to peers that send invalid ENRs. Bans are permanent if
+    /// this is not set.
+    pub fn ban_duration(&mut self, duration: Duration) -> &mut Self {
+        self.config.ban_duration = Some(duration);
+        self
+    }
+
+    /// A set of configuration parameters for the inbound packet filter. The request limits it
+    /// sets are enforced on unsolicited packets unless `FilterConfig::rate_limit_enabled` is
+    /// unset.
     pub fn filter_config(&mut self, config: FilterConfig) -> &mut Self {
         self.config.filter_config = config;
         self
     }
 
+    /// The size to request for the UDP socket's receive buffer.
+    pub fn socket_recv_buffer_size(&mut self, size: usize) -> &mut Self {
+        self.config.socket_recv_buffer_size = Some(size);
+        self
+    }
+
+    /// The size to request for the UDP socket's send buffer.
+    pub fn socket_send_buffer_size(&mut self, size: usize) -> &mut Self {
+        self.config.socket_send_buffer_size = Some(size);
+        self
+    }
+
     /// A set of lists that permit or ban IP's or NodeIds from the server. See
     /// `crate::PermitBanList`.
     pub fn permit_ban_list(&mut self, list: PermitBanList) -> &mut Self {
@@ -205,6 +643,15 @@ impl Discv5ConfigBuilder {
         self
     }
 
+    /// Sources of ENRs that are resolved and added to the routing table as bootnodes when the
+    /// server is started. The sources are resolved in the background, so they do not delay
+    /// `Discv5::start`. ENRs from all sources are merged, keeping the ENR with the highest
+    /// sequence number for each node.
+    pub fn bootstrap_sources(&mut self, sources: Vec<BootstrapSource>) -> &mut Self {
+        self.config.bootstrap_sources = sources;
+        self
+    }
+
     /// A custom executor which can spawn the discv5 tasks. This must be a tokio runtime, with
     /// timing support.
     pub fn executor(&mut self, executor: Box<dyn Executor + Send + Sync>) -> &mut Self {
@@ -224,13 +671,60 @@ impl std::fmt::Debug for Discv5Config {
         let _ = builder.field("request_timeout", &self.request_timeout);
         let _ = builder.field("query_timeout", &self.query_timeout);
         let _ = builder.field("query_peer_timeout", &self.query_peer_timeout);
+        let _ = builder.field(
+            "background_query_peer_timeout",
+            &self.background_query_peer_timeout,
+        );
         let _ = builder.field("request_retries", &self.request_retries);
         let _ = builder.field("session_timeout", &self.session_timeout);
         let _ = builder.field("session_cache_capacity", &self.session_cache_capacity);
+        let _ = builder.field(
+            "reestablish_expired_sessions",
+            &self.reestablish_expired_sessions,
+        );
         let _ = builder.field("enr_update", &self.enr_update);
+        let _ = builder.field("enr_update_v4", &self.enr_update_v4);
+        let _ = builder.field("enr_update_v6", &self.enr_update_v6);
+        let _ = builder.field("enr_refresh_min_gap", &self.enr_refresh_min_gap);
         let _ = builder.field("query_parallelism", &self.query_parallelism);
+        let _ = builder.field("findnode_distance_spread", &self.findnode_distance_spread);
+        let _ = builder.field("max_concurrent_queries", &self.max_concurrent_queries);
         let _ = builder.field("ip_limit", &self.ip_limit);
+        let _ = builder.field("require_contactable", &self.require_contactable);
         let _ = builder.field("ping_interval", &self.ping_interval);
+        let _ = builder.field("ping_failure_limit", &self.ping_failure_limit);
+        let _ = builder.field("disconnected_node_timeout", &self.disconnected_node_timeout);
+        let _ = builder.field("pending_eviction_timeout", &self.pending_eviction_timeout);
+        let _ = builder.field("refresh_interval", &self.refresh_interval);
+        let _ = builder.field(
+            "handler_unresponsive_timeout",
+            &self.handler_unresponsive_timeout,
+        );
+        let _ = builder.field("max_enr_size", &self.max_enr_size);
+        let _ = builder.field("ban_oversized_enrs", &self.ban_oversized_enrs);
+        let _ = builder.field(
+            "max_untrusted_enrs_per_query",
+            &self.max_untrusted_enrs_per_query,
+        );
+        let _ = builder.field(
+            "bootnode_failure_threshold",
+            &self.bootnode_failure_threshold,
+        );
+        let _ = builder.field("bootnode_cooldown", &self.bootnode_cooldown);
+        let _ = builder.field("report_discovered_raw", &self.report_discovered_raw);
+        let _ = builder.field("fetch_missing_result_enrs", &self.fetch_missing_result_enrs);
+        let _ = builder.field("findnode_dampening", &self.findnode_dampening);
+        let _ = builder.field("answer_empty_findnode", &self.answer_empty_findnode);
+        let _ = builder.field("passive_mode", &self.passive_mode);
+        let _ = builder.field("ticket_wait_time", &self.ticket_wait_time);
+        let _ = builder.field("query_min_seed_peers", &self.query_min_seed_peers);
+        let _ = builder.field("query_seed_wait_timeout", &self.query_seed_wait_timeout);
+        let _ = builder.field("inter_packet_delay", &self.inter_packet_delay);
+        let _ = builder.field("max_nodes_responses", &self.max_nodes_responses);
+        let _ = builder.field("ban_duration", &self.ban_duration);
+        let _ = builder.field("socket_recv_buffer_size", &self.socket_recv_buffer_size);
+        let _ = builder.field("socket_send_buffer_size", &self.socket_send_buffer_size);
+        let _ = builder.field("bootstrap_sources", &self.bootstrap_sources);
         builder.finish()
     }
 }
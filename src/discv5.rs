@@ -61,22 +61,32 @@
 //!    });
 //! ```
 
-use crate::error::{Discv5Error, QueryError};
+use crate::error::{Discv5Error, HandshakeError, QueryError, RequestError};
+use crate::handler::SessionInfo;
 use crate::kbucket::{self, ip_limiter, KBucketsTable, NodeStatus};
-use crate::service::{QueryKind, Service, ServiceRequest};
+use crate::node_info::{NodeAddress, NodeContact};
+use crate::permit_ban::BanListExport;
+use crate::query_pool::QueryId;
+use crate::rpc::{Pong, RequestBody, Response, ResponseBody};
+use crate::service::{
+    IpVote, PeerScores, QueryCallback, QueryKind, QueryOptions, Service, ServiceRequest,
+    TalkHandler, TopicTable, TOPIC_AD_LIFETIME,
+};
 use crate::{Discv5Config, Enr};
-use enr::{CombinedKey, EnrError, EnrKey, NodeId};
-use log::warn;
-use parking_lot::RwLock;
+use enr::{CombinedKey, CombinedPublicKey, EnrError, EnrKey, NodeId};
+use log::{debug, info, warn};
+use parking_lot::{Mutex, RwLock};
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
-use std::{net::SocketAddr, sync::Arc, time::Duration};
-use tokio::sync::{mpsc, oneshot};
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, oneshot, Notify};
 
 #[cfg(feature = "libp2p")]
-use {
-    crate::error::RequestError, crate::node_info::NodeContact, libp2p_core::Multiaddr,
-    std::convert::TryFrom,
-};
+use {libp2p_core::Multiaddr, std::convert::TryFrom};
 
 // Create lazy static variable for the global permit/ban list
 use crate::metrics::{Metrics, METRICS};
@@ -87,8 +97,29 @@ lazy_static! {
 
 mod test;
 
+/// The outcome of each configured bootstrap source: the number of ENRs it yielded, or why it
+/// could not be resolved.
+type BootstrapSourceResults = Vec<Result<usize, String>>;
+
+/// The capacity of the channel ENRs discovered by a crawl are streamed to.
+const CRAWL_STREAM_CAPACITY: usize = 30;
+
+/// The interval at which the routing table is checked while waiting for enough seed peers to
+/// start a query.
+const SEED_PEER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The minimum number of nodes in the routing table for [`Discv5::estimate_network_size`] to
+/// return an estimate.
+const MIN_NETWORK_SIZE_ESTIMATE_PEERS: usize = 8;
+
+/// The maximum number of REGTOPIC requests sent to a peer when registering a topic.
+const MAX_REGTOPIC_ATTEMPTS: usize = 3;
+
+/// The longest ticket wait time that is waited for when registering a topic.
+const MAX_TICKET_WAIT_TIME: Duration = Duration::from_secs(60);
+
 /// Events that can be produced by the `Discv5` event stream.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Discv5Event {
     /// A node has been discovered from a FINDNODES request.
     ///
@@ -96,6 +127,14 @@ pub enum Discv5Event {
     /// This happen spontaneously through queries as nodes return ENR's. These ENR's are not
     /// guaranteed to be live or contactable.
     Discovered(Enr),
+    /// A node has been discovered from a FINDNODES request, along with the RLP encoding of its
+    /// ENR.
+    ///
+    /// This is produced in place of `Discovered` when `Discv5Config::report_discovered_raw` is
+    /// set. The raw bytes are obtained via `Enr::encode()`. ENR's have a single canonical RLP
+    /// encoding (keys are sorted and RLP is canonical), so these bytes are identical to the
+    /// signed record the remote node produced and can be stored or relayed without re-signing.
+    DiscoveredRaw { enr: Enr, raw: Vec<u8> },
     /// A new ENR was added to the routing table.
     EnrAdded { enr: Enr, replaced: Option<Enr> },
     /// A new node has been added to the routing table.
@@ -103,8 +142,156 @@ pub enum Discv5Event {
         node_id: NodeId,
         replaced: Option<NodeId>,
     },
+    /// A node could not be inserted into the routing table as its bucket is full. It is inserted
+    /// in place of the disconnected node `pending_on` if that node does not respond to a PING
+    /// within the `pending_eviction_timeout`, which is reported by `NodeInserted`.
+    NodePending {
+        inserting: NodeId,
+        pending_on: NodeId,
+    },
     /// Our local ENR IP address has been updated.
     SocketUpdated(SocketAddr),
+    /// A session has been established with a node, at the given socket address.
+    ///
+    /// This is produced once the handshake completes, regardless of whether the node is added to
+    /// the routing table.
+    SessionEstablished(Enr, SocketAddr),
+    /// A query has finished. This is produced in addition to the result returned to the caller
+    /// of the query.
+    FindNodeResult {
+        /// The target of the query.
+        key: NodeId,
+        /// The closest peers to the target that responded to the query.
+        closer_peers: Vec<NodeId>,
+        /// The id of the query, unique for the lifetime of the server.
+        query_id: QueryId,
+    },
+    /// A query has progressed as a peer responded with closer peers. This is produced at most once
+    /// a second per query.
+    QueryProgress {
+        /// The id of the query, unique for the lifetime of the server.
+        query_id: QueryId,
+        /// The number of peers contacted so far.
+        peers_contacted: usize,
+        /// The log2 distance to the target of the closest peer found so far.
+        closest_distance: Option<u64>,
+    },
+    /// The session with a node was evicted from the session cache to make room for a new
+    /// session. A new handshake is required to communicate with the node again. See
+    /// `Discv5Config::session_cache_capacity`.
+    SessionEvicted(NodeId),
+    /// The session with a node expired, as it was inactive for longer than
+    /// `Discv5Config::session_timeout`. If `Discv5Config::reestablish_expired_sessions` is set and
+    /// the node is connected, a new session is established immediately.
+    SessionExpired(NodeId),
+    /// A query of a bootstrap started with `Discv5::bootstrap` has finished.
+    BootstrapProgress {
+        /// The number of queries of the bootstrap that have finished.
+        completed: usize,
+        /// The total number of queries of the bootstrap.
+        total: usize,
+        /// The number of nodes in the routing table.
+        table_size: usize,
+    },
+    /// A node has been removed from the routing table or marked as disconnected.
+    NodeRemoved {
+        node_id: NodeId,
+        reason: RemovalReason,
+    },
+    /// The local ENR has been updated and re-signed with a new sequence number, either by the
+    /// application or from the external socket voted by peers. This carries the new record, for
+    /// example to be persisted.
+    EnrUpdated {
+        /// The updated local ENR.
+        enr: Enr,
+    },
+    /// An inbound packet could not be decoded, which may indicate a misbehaving peer or a port
+    /// scan. These events are rate-limited to at most one a second, while every such packet is
+    /// counted in `Metrics::dropped_packets_decode_failed`.
+    PacketDecodeFailed {
+        /// The source of the packet.
+        source: SocketAddr,
+    },
+    /// A session handshake with a node failed, which is otherwise only reported as a failed
+    /// request. A node that persistently fails with `HandshakeError::Timeout` may be behind a
+    /// NAT that drops our packets.
+    HandshakeFailed {
+        /// The address of the node.
+        node_address: NodeAddress,
+        /// The reason the handshake failed.
+        error: HandshakeError,
+    },
+    /// A response to a request was received. The round-trip time is measured from when the
+    /// request was sent to the first response, so it includes establishing a session with the
+    /// node if one did not exist, as well as any retries.
+    ResponseLatency {
+        /// The node that responded.
+        node_id: NodeId,
+        /// The time between sending the request and receiving the response.
+        rtt: Duration,
+    },
+    /// An ENR was discovered with the same sequence number as the ENR stored for the node, but
+    /// with different contents. Only one of them is kept in the routing table.
+    EnrConflict {
+        /// The node the ENRs belong to.
+        node_id: NodeId,
+        /// The node that sent the discovered ENR.
+        source: NodeId,
+        /// The ENR kept in the routing table.
+        kept: Enr,
+        /// The ENR that was discarded.
+        rejected: Enr,
+    },
+    /// Requests to the handler have been queued for longer than
+    /// `Discv5Config::handler_unresponsive_timeout`, as its channel stayed full. Requests and
+    /// queries are likely to time out until the handler catches up. This is produced once until
+    /// the queued requests have been sent.
+    HandlerUnresponsive {
+        /// The number of requests waiting to be sent to the handler.
+        queued: usize,
+        /// How long requests have been waiting to be sent to the handler.
+        stalled_for: Duration,
+    },
+    /// The connection status of a node in the routing table has changed, for example when a
+    /// disconnected node is contacted again. This is not produced for nodes newly inserted into
+    /// the routing table, which are reported by `NodeInserted`.
+    ConnectionStateChanged {
+        /// The node whose status changed.
+        node_id: NodeId,
+        /// The previous status of the node.
+        old: NodeStatus,
+        /// The new status of the node.
+        new: NodeStatus,
+    },
+}
+
+/// The reason a node was reported by a `Discv5Event::NodeRemoved` event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RemovalReason {
+    /// A request to the node failed or timed out and the node was marked as disconnected.
+    RequestFailed,
+    /// The node was removed via `Discv5::remove_node`.
+    Removed,
+    /// The node was banned.
+    Banned,
+    /// The node was evicted from its bucket to make room for a pending node.
+    Evicted,
+    /// The node was disconnected for longer than `Discv5Config::disconnected_node_timeout`.
+    Stale,
+}
+
+/// The outcome of adding an ENR to the routing table with `Discv5::add_enr`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AddEnrOutcome {
+    /// The node was inserted into the routing table.
+    Inserted,
+    /// The node was already in the routing table, or pending insertion, and its ENR was updated.
+    Updated,
+    /// The bucket of the node is full. The node is inserted in place of the disconnected node,
+    /// unless the disconnected node is reconnected first.
+    Pending { disconnected: NodeId },
+    /// The node was not inserted because its bucket is full.
+    Full,
 }
 
 /// The main Discv5 Service struct. This provides the user-level API for performing queries and
@@ -114,13 +301,29 @@ pub struct Discv5 {
     /// The channel to make requests from the main service.
     service_channel: Option<mpsc::Sender<ServiceRequest>>,
     /// The exit channel to shutdown the underlying service.
-    service_exit: Option<oneshot::Sender<()>>,
+    service_exit: Option<oneshot::Sender<oneshot::Sender<()>>>,
     /// The routing table of the discv5 service.
     kbuckets: Arc<RwLock<KBucketsTable<NodeId, Enr>>>,
     /// The local ENR of the server.
     local_enr: Arc<RwLock<Enr>>,
     /// The key associated with the local ENR, required for updating the local ENR.
     enr_key: Arc<RwLock<CombinedKey>>,
+    /// The node ids of the bootnodes that have been added.
+    bootnodes: Arc<RwLock<HashSet<NodeId>>>,
+    /// The votes peers have made about our external socket, if `enr_update` is enabled.
+    ip_votes: Option<Arc<RwLock<IpVote>>>,
+    /// The handlers of the registered TALKREQ protocols.
+    talk_protocols: Arc<Mutex<HashMap<Vec<u8>, TalkHandler>>>,
+    /// The advertisers of topics known to the local node.
+    topic_table: Arc<RwLock<TopicTable>>,
+    /// The scores of peers, accumulated from the outcomes of requests made to them.
+    peer_scores: Arc<RwLock<PeerScores>>,
+    /// The outcome of each configured bootstrap source, set once the sources started resolving
+    /// with the server have been resolved.
+    bootstrap_source_results: Arc<RwLock<Option<BootstrapSourceResults>>>,
+    /// Notifies the service that the local ENR has been updated. Repeated updates before the
+    /// service is woken are coalesced into one.
+    local_enr_updated: Arc<Notify>,
 }
 
 impl Discv5 {
@@ -143,12 +346,25 @@ impl Discv5 {
         let enr_key = Arc::new(RwLock::new(enr_key));
         let kbuckets = Arc::new(RwLock::new(KBucketsTable::new(
             local_enr.read().node_id().into(),
-            Duration::from_secs(60),
+            config.pending_eviction_timeout,
         )));
 
+        let ip_votes = if config.enr_update {
+            Some(Arc::new(RwLock::new(IpVote::new(
+                config.enr_peer_update_min,
+            ))))
+        } else {
+            None
+        };
+
         // Update the PermitBan list based on initial configuration
         *PERMIT_BAN_LIST.write() = config.permit_ban_list.clone();
 
+        let topic_table = Arc::new(RwLock::new(TopicTable::new(
+            TOPIC_AD_LIFETIME,
+            config.ticket_wait_time,
+        )));
+
         Ok(Discv5 {
             config,
             service_channel: None,
@@ -156,11 +372,60 @@ impl Discv5 {
             kbuckets,
             local_enr,
             enr_key,
+            bootnodes: Arc::new(RwLock::new(HashSet::new())),
+            ip_votes,
+            talk_protocols: Arc::new(Mutex::new(HashMap::new())),
+            topic_table,
+            peer_scores: Arc::new(RwLock::new(PeerScores::default())),
+            bootstrap_source_results: Arc::new(RwLock::new(None)),
+            local_enr_updated: Arc::new(Notify::new()),
         })
     }
 
+    /// Creates a server whose routing table is pre-seeded with the given ENRs, typically a
+    /// [`Discv5::table_snapshot`] persisted by a previous run.
+    ///
+    /// Each ENR is added as with [`Discv5::add_enr`], respecting `ip_limit` and `table_filter`.
+    /// ENRs that cannot be added are skipped. Reloaded nodes are inserted as disconnected and do
+    /// not count as connected peers until a session is re-established with them.
+    pub fn with_table(
+        local_enr: Enr,
+        enr_key: CombinedKey,
+        config: Discv5Config,
+        table: Vec<Enr>,
+    ) -> Result<Self, &'static str> {
+        let mut discv5 = Discv5::new(local_enr, enr_key, config)?;
+        let mut reloaded = 0;
+        for enr in table {
+            let node_id = enr.node_id();
+            match discv5.add_enr(enr) {
+                Ok(AddEnrOutcome::Full) => debug!("Could not reload node {}: Table full", node_id),
+                Ok(_) => reloaded += 1,
+                Err(e) => debug!("Could not reload node {}: {}", node_id, e),
+            }
+        }
+        info!("Reloaded {} nodes into the routing table", reloaded);
+        Ok(discv5)
+    }
+
     /// Starts the required tasks and begins listening on a given UDP SocketAddr.
     pub fn start(&mut self, listen_socket: SocketAddr) {
+        self.start_service(listen_socket, None);
+    }
+
+    /// Starts the required tasks and begins listening on an already bound UDP socket, for example
+    /// one handed over by a supervising process. Binding to an OS-assigned port and building the
+    /// local ENR from the bound address also avoids racing for a port.
+    pub fn start_with_socket(&mut self, socket: std::net::UdpSocket) -> Result<(), Discv5Error> {
+        let listen_socket = socket
+            .local_addr()
+            .map_err(|e| Discv5Error::Error(format!("Invalid UDP socket: {}", e)))?;
+        self.start_service(listen_socket, Some(socket));
+        Ok(())
+    }
+
+    /// Spawns the service, listening on `socket` if provided, otherwise on `listen_socket`.
+    fn start_service(&mut self, listen_socket: SocketAddr, socket: Option<std::net::UdpSocket>) {
         if self.service_channel.is_some() {
             warn!("Service is already started");
             return;
@@ -171,23 +436,85 @@ impl Discv5 {
             self.local_enr.clone(),
             self.enr_key.clone(),
             self.kbuckets.clone(),
+            self.bootnodes.clone(),
+            self.ip_votes.clone(),
+            self.talk_protocols.clone(),
+            self.topic_table.clone(),
+            self.peer_scores.clone(),
+            self.local_enr_updated.clone(),
             self.config.clone(),
             listen_socket,
+            socket,
         );
         self.service_exit = Some(service_exit);
         self.service_channel = Some(service_channel);
+
+        self.add_bootstrap_sources();
+    }
+
+    /// Resolves the configured bootstrap sources and adds the resulting ENRs as bootnodes.
+    ///
+    /// Sources may block on the file system or a DNS resolver, so they are resolved on a
+    /// separate thread rather than blocking the caller, which is typically a runtime thread.
+    fn add_bootstrap_sources(&mut self) {
+        if self.config.bootstrap_sources.is_empty() {
+            return;
+        }
+        let config = self.config.clone();
+        let kbuckets = self.kbuckets.clone();
+        let bootnodes = self.bootnodes.clone();
+        let bootstrap_source_results = self.bootstrap_source_results.clone();
+        std::thread::spawn(move || {
+            let (enrs, results) =
+                crate::bootstrap_source::resolve_sources(&config.bootstrap_sources);
+            for (source, result) in config.bootstrap_sources.iter().zip(&results) {
+                match result {
+                    Ok(count) => info!("Bootstrap source {} provided {} ENRs", source, count),
+                    Err(e) => warn!("Bootstrap source {} failed: {}", source, e),
+                }
+            }
+            for enr in enrs {
+                let node_id = enr.node_id();
+                match Self::insert_enr(&config, &mut kbuckets.write(), enr) {
+                    Ok(AddEnrOutcome::Full) => {}
+                    Ok(_) => {
+                        bootnodes.write().insert(node_id);
+                    }
+                    Err(e) => warn!("Could not add bootnode {}: {}", node_id, e),
+                }
+            }
+            *bootstrap_source_results.write() = Some(results);
+        });
+    }
+
+    /// Returns the outcome of each configured bootstrap source, in the order of
+    /// `Discv5Config::bootstrap_sources`: the number of ENRs the source provided, or the reason
+    /// it failed to resolve.
+    ///
+    /// The sources are resolved in the background once the server is started. This is `None`
+    /// until they have been resolved and their ENRs added to the routing table.
+    pub fn bootstrap_source_results(&self) -> Option<Vec<Result<usize, String>>> {
+        self.bootstrap_source_results.read().clone()
     }
 
     /// Terminates the service.
-    pub fn shutdown(&mut self) {
+    ///
+    /// The shutdown is started when this is called. The returned future completes once the
+    /// service has terminated, after any queued outbound responses have been sent.
+    pub fn shutdown(&mut self) -> impl Future<Output = ()> + 'static {
+        let (done_send, done) = oneshot::channel();
         if let Some(exit) = self.service_exit.take() {
-            if exit.send(()).is_err() {
+            if exit.send(done_send).is_err() {
                 log::debug!("Discv5 service already shutdown");
             }
             self.service_channel = None;
         } else {
             log::debug!("Service is already shutdown");
         }
+
+        async move {
+            let _ = done.await;
+        }
     }
 
     /// Adds a known ENR of a peer participating in Service to the
@@ -197,49 +524,103 @@ impl Discv5 {
     /// addresses, so that they can be used immediately in following DHT
     /// operations involving one of these peers, without having to dial
     /// them upfront.
-    pub fn add_enr(&mut self, enr: Enr) -> Result<(), &'static str> {
+    ///
+    /// The returned [`AddEnrOutcome`] reports whether the node was inserted, or whether its bucket
+    /// is full.
+    pub fn add_enr(&mut self, enr: Enr) -> Result<AddEnrOutcome, Discv5Error> {
+        Self::insert_enr(&self.config, &mut self.kbuckets.write(), enr)
+    }
+
+    /// Adds many known ENRs to the routing table, returning the outcome for each ENR in order.
+    ///
+    /// This is equivalent to calling [`Discv5::add_enr`] for each ENR, but takes the routing
+    /// table lock only once, which is considerably faster when seeding the table from a large
+    /// persisted set of ENRs. The IP limit accounts for the ENRs inserted earlier in the batch.
+    pub fn add_enrs(&mut self, enrs: Vec<Enr>) -> Vec<Result<AddEnrOutcome, Discv5Error>> {
+        let mut kbuckets = self.kbuckets.write();
+        enrs.into_iter()
+            .map(|enr| Self::insert_enr(&self.config, &mut kbuckets, enr))
+            .collect()
+    }
+
+    /// Inserts or updates an ENR in the routing table. See [`Discv5::add_enr`].
+    fn insert_enr(
+        config: &Discv5Config,
+        kbuckets: &mut KBucketsTable<NodeId, Enr>,
+        enr: Enr,
+    ) -> Result<AddEnrOutcome, Discv5Error> {
         // only add ENR's that have a valid udp socket.
         if enr.udp_socket().is_none() {
             warn!("ENR attempted to be added without a UDP socket has been ignored");
-            return Err("ENR has no UDP socket to connect to");
+            return Err(Discv5Error::Custom("ENR has no UDP socket to connect to"));
         }
 
-        if !(self.config.table_filter)(&enr) {
+        if !(config.table_filter)(&enr) {
             warn!("ENR attempted to be added which is banned by the configuration table filter.");
-            return Err("ENR banned by table filter");
+            return Err(Discv5Error::Custom("ENR banned by table filter"));
         }
 
         let key = kbucket::Key::from(enr.node_id());
 
         // should the ENR be inserted or updated to a value that would exceed the IP limit ban
-        let ip_limit_ban = self.config.ip_limit
-            && !self
-                .kbuckets
-                .read()
-                .check(&key, &enr, { |v, o, l| ip_limiter(v, &o, l) });
+        let ip_limit_ban =
+            config.ip_limit && !kbuckets.check(&key, &enr, |v, o, l| ip_limiter(v, &o, l));
 
-        match self.kbuckets.write().entry(&key) {
+        match kbuckets.entry(&key) {
             kbucket::Entry::Present(mut entry, _) => {
                 // still update an ENR, regardless of the IP limit ban
                 *entry.value() = enr;
+                Ok(AddEnrOutcome::Updated)
             }
             kbucket::Entry::Pending(mut entry, _) => {
                 *entry.value() = enr;
+                Ok(AddEnrOutcome::Updated)
             }
             kbucket::Entry::Absent(entry) => {
-                if !ip_limit_ban {
-                    match entry.insert(enr, NodeStatus::Disconnected) {
-                        kbucket::InsertResult::Inserted => {}
-                        kbucket::InsertResult::Full => {
-                            return Err("Table full");
-                        }
-                        kbucket::InsertResult::Pending { .. } => {}
-                    }
+                if ip_limit_ban {
+                    return Err(Discv5Error::Custom("ENR exceeds the IP limit"));
+                }
+                match entry.insert(enr, NodeStatus::Disconnected) {
+                    kbucket::InsertResult::Inserted => Ok(AddEnrOutcome::Inserted),
+                    kbucket::InsertResult::Full => Ok(AddEnrOutcome::Full),
+                    kbucket::InsertResult::Pending { disconnected } => Ok(AddEnrOutcome::Pending {
+                        disconnected: disconnected.into_preimage(),
+                    }),
                 }
             }
-            kbucket::Entry::SelfEntry => {}
-        };
-        Ok(())
+            kbucket::Entry::SelfEntry => Err(Discv5Error::Custom("ENR is the local node")),
+        }
+    }
+
+    /// Adds the ENR of a bootnode to the routing table.
+    ///
+    /// Bootnodes that repeatedly fail to respond are not contacted for a cooldown period. See
+    /// `Discv5Config::bootnode_failure_threshold` and `Discv5Config::bootnode_cooldown`.
+    pub fn add_bootnode(&mut self, enr: Enr) -> Result<AddEnrOutcome, Discv5Error> {
+        let node_id = enr.node_id();
+        let outcome = self.add_enr(enr)?;
+        if outcome != AddEnrOutcome::Full {
+            self.bootnodes.write().insert(node_id);
+        }
+        Ok(outcome)
+    }
+
+    /// Adds a list of bootnode ENRs to the routing table, such as one resolved from an EIP-1459
+    /// DNS node list, returning the outcome for each ENR in order. Each ENR is added as with
+    /// [`Discv5::add_bootnode`], taking the routing table lock only once.
+    pub fn add_enr_list(&mut self, enrs: Vec<Enr>) -> Vec<Result<AddEnrOutcome, Discv5Error>> {
+        let node_ids: Vec<NodeId> = enrs.iter().map(Enr::node_id).collect();
+        let outcomes = self.add_enrs(enrs);
+        let mut bootnodes = self.bootnodes.write();
+        for (node_id, outcome) in node_ids.into_iter().zip(outcomes.iter()) {
+            match outcome {
+                Ok(AddEnrOutcome::Full) | Err(_) => {}
+                Ok(_) => {
+                    bootnodes.insert(node_id);
+                }
+            }
+        }
+        outcomes
     }
 
     /// Removes a `node_id` from the routing table.
@@ -247,11 +628,30 @@ impl Discv5 {
     /// This allows applications, for whatever reason, to remove nodes from the local routing
     /// table. Returns `true` if the node was in the table and `false` otherwise.
     pub fn remove_node(&mut self, node_id: &NodeId) -> bool {
+        self.remove_node_with_reason(node_id, RemovalReason::Removed)
+    }
+
+    /// Removes a node from the routing table and informs the service, such that a
+    /// `Discv5Event::NodeRemoved` is sent to any event stream. If the service channel is full the
+    /// event is dropped.
+    fn remove_node_with_reason(&mut self, node_id: &NodeId, reason: RemovalReason) -> bool {
         let key = &kbucket::Key::from(*node_id);
-        self.kbuckets.write().remove(key)
+        let removed = self.kbuckets.write().remove(key);
+        if removed {
+            if let Some(channel) = self.service_channel.as_mut() {
+                let _ = channel.try_send(ServiceRequest::NodeRemoved(*node_id, reason));
+            }
+        }
+        removed
     }
 
     /// Returns the number of connected peers that exist in the routing table.
+    ///
+    /// A peer is connected if its routing table status is `NodeStatus::Connected`, which is set
+    /// once a session is established with it and cleared once a request to it fails. This may
+    /// diverge from the established sessions, as peers outside the routing table may hold a
+    /// session and connected peers may have their session evicted or expire. See
+    /// [`Discv5::sessions_info`] for the sessions held by the handler.
     pub fn connected_peers(&self) -> usize {
         self.kbuckets
             .write()
@@ -260,6 +660,19 @@ impl Discv5 {
             .count()
     }
 
+    /// Returns the number of established sessions and the capacity of the session cache. The
+    /// sessions are counted by the handler, as with [`Discv5::active_sessions`].
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn session_cache_stats(
+        &self,
+    ) -> impl Future<Output = Result<(usize, usize), Discv5Error>> + 'static {
+        let active_sessions = self.active_sessions();
+        let capacity = self.config.session_cache_capacity;
+        async move { Ok((active_sessions.await?, capacity)) }
+    }
+
     /// Gets the metrics associated with the Server
     pub fn metrics(&self) -> Metrics {
         Metrics::from(&METRICS)
@@ -275,6 +688,29 @@ impl Discv5 {
         self.local_enr.read().clone()
     }
 
+    /// Returns true if the local ENR advertises a UDP socket that peers can be expected to reach.
+    ///
+    /// If `enr_update` is enabled, the advertised socket must also match the majority of the
+    /// external sockets reported by peers in PONG responses.
+    pub fn local_enr_is_contactable(&self) -> bool {
+        let local_socket = match self.local_enr.read().udp_socket() {
+            Some(socket) => socket,
+            None => return false,
+        };
+        match self.ip_votes {
+            Some(ref ip_votes) => {
+                let (majority_v4, majority_v6) = ip_votes.write().majority();
+                let majority = if local_socket.is_ipv4() {
+                    majority_v4
+                } else {
+                    majority_v6
+                };
+                majority == Some(local_socket)
+            }
+            None => true,
+        }
+    }
+
     /// Returns an ENR if one is known for the given NodeId.
     pub fn find_enr(&mut self, node_id: &NodeId) -> Option<Enr> {
         // check if we know this node id in our routing table
@@ -285,11 +721,105 @@ impl Discv5 {
         None
     }
 
-    /// Bans a node from the server. This will remove the node from the routing table if it exists
-    /// and block all incoming packets from the node.
-    pub fn ban_node(&mut self, node_id: &NodeId) {
-        self.remove_node(node_id);
-        PERMIT_BAN_LIST.write().ban_nodes.insert(node_id.clone());
+    /// Returns the ENRs of up to `count` nodes in the routing table that are closest to `target`,
+    /// ordered by increasing distance. Unlike [`Discv5::find_node`], no query is run.
+    pub fn closest_local_nodes(&self, target: NodeId, count: usize) -> Vec<Enr> {
+        let target_key: kbucket::Key<NodeId> = target.into();
+        let mut kbuckets = self.kbuckets.write();
+        let closest_keys: Vec<_> = kbuckets.closest_keys(&target_key).take(count).collect();
+        closest_keys
+            .iter()
+            .filter_map(|key| match kbuckets.entry(key) {
+                kbucket::Entry::Present(mut entry, _) => Some(entry.value().clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Estimates the total number of nodes in the network from the density of the closest
+    /// occupied bucket of the routing table. Returns `None` if the routing table holds too few
+    /// nodes for a meaningful estimate.
+    pub fn estimate_network_size(&self) -> Option<f64> {
+        let kbuckets = self.kbuckets.read();
+        if kbuckets.iter_ref().count() < MIN_NETWORK_SIZE_ESTIMATE_PEERS {
+            return None;
+        }
+        kbuckets.estimate_network_size()
+    }
+
+    /// Returns an ENR for the given NodeId if one is already known locally, either from the
+    /// routing table or from the untrusted ENRs of an ongoing query. Unlike
+    /// [`Discv5::find_enr_async`], no requests are sent to the network.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn local_enr_of(&self, node_id: &NodeId) -> impl Future<Output = Option<Enr>> + 'static {
+        let channel = self.clone_channel();
+        let node_id = *node_id;
+
+        async move {
+            let mut channel = channel.ok()?;
+            let (callback_send, callback_recv) = oneshot::channel();
+
+            let event = ServiceRequest::LocalEnrOf(node_id, callback_send);
+            channel.send(event).await.ok()?;
+
+            callback_recv.await.ok()?
+        }
+    }
+
+    /// Returns an ENR for the given NodeId, consulting the network if required.
+    ///
+    /// If the node is not in the routing table but has been seen in an ongoing query, its ENR is
+    /// requested from the node directly. Otherwise a query is run towards the node's id and the
+    /// ENR is requested from the node once it is found. `None` is returned if the node is not
+    /// found, or the request fails or times out.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn find_enr_async(&self, node_id: NodeId) -> impl Future<Output = Option<Enr>> + 'static {
+        let channel = self.clone_channel();
+        // A request that requires a handshake is re-sent within the request timeout window, which
+        // may follow a query for the node.
+        let timeout = self.config.query_timeout
+            + self.config.request_timeout * (u32::from(self.config.request_retries) + 1) * 2;
+
+        async move {
+            let mut channel = channel.ok()?;
+            let (callback_send, callback_recv) = oneshot::channel();
+
+            let event = ServiceRequest::FindEnrById(node_id, callback_send);
+            channel.send(event).await.ok()?;
+
+            tokio::time::timeout(timeout, callback_recv)
+                .await
+                .ok()?
+                .ok()?
+        }
+    }
+
+    /// Bans a node and its IP from the server. This will remove the node from the routing table if
+    /// it exists and block all incoming packets from the node.
+    ///
+    /// The ban list is held in memory and bans do not persist across restarts. See
+    /// [`Discv5::export_ban_list`] to persist bans.
+    pub fn ban_node(&mut self, node_address: &NodeAddress) {
+        self.ban_until(node_address, None);
+    }
+
+    /// Bans a node and its IP from the server until `expiry`, or permanently if `expiry` is
+    /// `None`. As with [`Discv5::ban_node`], the node is removed from the routing table. Expired
+    /// bans are purged periodically.
+    pub fn ban_until(&mut self, node_address: &NodeAddress, expiry: Option<Instant>) {
+        self.remove_node_with_reason(&node_address.node_id, RemovalReason::Banned);
+        PERMIT_BAN_LIST
+            .write()
+            .ban_until(node_address.clone(), expiry);
+    }
+
+    /// Returns whether the node or its IP is currently banned.
+    pub fn is_banned(&self, node_address: &NodeAddress) -> bool {
+        PERMIT_BAN_LIST.read().is_banned(node_address)
     }
 
     /// Removes a banned node from the banned list.
@@ -297,9 +827,32 @@ impl Discv5 {
         PERMIT_BAN_LIST.write().ban_nodes.remove(node_id);
     }
 
-    /// Permits a node, allowing the node to bypass the packet filter.  
-    pub fn permit_node(&mut self, node_id: &NodeId) {
-        PERMIT_BAN_LIST.write().permit_nodes.insert(node_id.clone());
+    /// Returns the banned nodes and IPs along with the expiry of their ban, if any. The result can
+    /// be persisted and passed to [`Discv5::import_ban_list`] after a restart.
+    ///
+    /// Note: `Instant`s are only meaningful within the current process. Callers persisting the
+    /// list should store the remaining duration of each ban and convert it back on import.
+    pub fn export_ban_list(&self) -> BanListExport {
+        PERMIT_BAN_LIST.read().export_bans()
+    }
+
+    /// Imports the banned nodes and IPs, as produced by [`Discv5::export_ban_list`]. Bans that
+    /// have already expired are dropped. Returns the number of bans that were imported.
+    ///
+    /// This should be called after the server is created, as creating the server resets the ban
+    /// list to the configured `permit_ban_list`.
+    pub fn import_ban_list(&mut self, bans: BanListExport) -> usize {
+        let imported = PERMIT_BAN_LIST.write().import_bans(bans);
+        log::debug!("Imported {} bans", imported);
+        imported
+    }
+
+    /// Permits a node, lifting any ban of the node and its IP and allowing the node to bypass the
+    /// packet filter.
+    pub fn permit_node(&mut self, node_address: &NodeAddress) {
+        let mut permit_ban_list = PERMIT_BAN_LIST.write();
+        permit_ban_list.unban(node_address);
+        permit_ban_list.permit_nodes.insert(node_address.node_id);
     }
 
     /// Removes a node from the permit list.
@@ -309,7 +862,7 @@ impl Discv5 {
 
     /// Bans an IP from the server.  This will block all incoming packets from the IP.
     pub fn ban_ip(&mut self, ip: std::net::IpAddr) {
-        PERMIT_BAN_LIST.write().ban_ips.insert(ip);
+        PERMIT_BAN_LIST.write().ban_ips.insert(ip, None);
     }
 
     /// Removes a banned IP from the banned list.
@@ -331,27 +884,90 @@ impl Discv5 {
     pub fn update_local_enr_socket(&mut self, socket_addr: SocketAddr, is_tcp: bool) -> bool {
         let local_socket = self.local_enr.read().udp_socket();
         if local_socket != Some(socket_addr) {
-            if is_tcp {
-                self.local_enr
-                    .write()
-                    .set_tcp_socket(socket_addr, &self.enr_key.read())
-                    .is_ok()
-            } else {
-                self.local_enr
-                    .write()
-                    .set_udp_socket(socket_addr, &self.enr_key.read())
-                    .is_ok()
-            }
+            self.update_local_enr(|enr, key| {
+                if is_tcp {
+                    enr.set_tcp_socket(socket_addr, key)
+                } else {
+                    enr.set_udp_socket(socket_addr, key)
+                }
+            })
+            .is_ok()
         } else {
             false
         }
     }
 
+    /// Sets the TCP socket advertised in the local ENR, for example once an application's TCP
+    /// listener has bound. Connected peers are pinged such that they request the updated ENR and
+    /// a `Discv5Event::EnrUpdated` is sent.
+    ///
+    /// The ENR holds a single IP per address family, which is shared with the UDP socket. An
+    /// unspecified IP, such as a listener's bind address of `0.0.0.0`, only sets the TCP port.
+    /// A socket whose IP differs from the advertised IP is rejected.
+    ///
+    /// Returns whether the local ENR was changed, which is not the case if it already advertises
+    /// the socket, the IP does not match or the updated ENR would exceed the configured maximum
+    /// ENR size.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn set_tcp_socket(
+        &self,
+        socket_addr: SocketAddr,
+    ) -> impl Future<Output = Result<bool, Discv5Error>> + 'static {
+        let channel = self.clone_channel();
+
+        async move {
+            let mut channel = channel?;
+
+            let (callback_send, callback_recv) = oneshot::channel();
+
+            let event = ServiceRequest::UpdateEnrTcp(socket_addr, callback_send);
+            channel
+                .send(event)
+                .await
+                .map_err(|_| Discv5Error::ServiceChannelClosed)?;
+
+            callback_recv
+                .await
+                .map_err(|_| Discv5Error::ServiceChannelClosed)
+        }
+    }
+
     /// Allows application layer to insert an arbitrary field into the local ENR.
+    ///
+    /// The sequence number of the ENR is incremented and, if the server is running, connected
+    /// peers are pinged such that they request the updated ENR and a `Discv5Event::EnrUpdated`
+    /// is sent.
+    ///
+    /// Returns `EnrError::ExceedsMaxSize` and leaves the local ENR unchanged if the updated ENR
+    /// would exceed the configured maximum ENR size.
     pub fn enr_insert(&mut self, key: &str, value: Vec<u8>) -> Result<Option<Vec<u8>>, EnrError> {
-        self.local_enr
-            .write()
-            .insert(key, value, &self.enr_key.read())
+        self.update_local_enr(|enr, enr_key| enr.insert(key, value, enr_key))
+    }
+
+    /// Applies an update to a copy of the local ENR and replaces the local ENR only if the update
+    /// succeeds and the resulting ENR is within the maximum configured size. Connected peers are
+    /// informed of the update via the service.
+    fn update_local_enr<T>(
+        &mut self,
+        update: impl FnOnce(&mut Enr, &CombinedKey) -> Result<T, EnrError>,
+    ) -> Result<T, EnrError> {
+        let result = {
+            // the lock is held throughout, such that concurrent updates by the service are not lost
+            let mut local_enr = self.local_enr.write();
+            let mut enr = local_enr.clone();
+            let result = update(&mut enr, &self.enr_key.read())?;
+            if enr.size() > self.config.max_enr_size {
+                return Err(EnrError::ExceedsMaxSize);
+            }
+            *local_enr = enr;
+            result
+        };
+        if self.service_channel.is_some() {
+            self.local_enr_updated.notify();
+        }
+        Ok(result)
     }
 
     /// Returns an iterator over all ENR node IDs of nodes currently contained in the routing table.
@@ -365,6 +981,12 @@ impl Discv5 {
 
     /// Returns an iterator over all the ENR's of nodes currently contained in the routing table.
     pub fn table_entries_enr(&mut self) -> Vec<Enr> {
+        self.table_snapshot()
+    }
+
+    /// Returns the ENRs of all nodes in the routing table, such that they can be persisted and
+    /// reloaded via [`Discv5::with_table`] after a restart.
+    pub fn table_snapshot(&self) -> Vec<Enr> {
         self.kbuckets
             .write()
             .iter()
@@ -372,8 +994,107 @@ impl Discv5 {
             .collect()
     }
 
+    /// Returns the external sockets that peers have observed for this node, along with the number
+    /// of peers that currently report each socket, ordered by the number of votes.
+    ///
+    /// The local ENR socket is updated to the majority socket once it has
+    /// `enr_peer_update_min` votes. The tally is empty if `enr_update` is disabled or the service
+    /// is not running.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn ip_vote_tally(&self) -> impl Future<Output = Vec<(SocketAddr, usize)>> + 'static {
+        let channel = self.clone_channel();
+
+        async move {
+            let mut channel = match channel {
+                Ok(channel) => channel,
+                Err(_) => return Vec::new(),
+            };
+            let (callback_send, callback_recv) = oneshot::channel();
+            if channel
+                .send(ServiceRequest::IpVoteTally(callback_send))
+                .await
+                .is_err()
+            {
+                return Vec::new();
+            }
+            callback_recv.await.unwrap_or_default()
+        }
+    }
+
+    /// Returns the ids of the queries that are currently active. Query ids are reported in
+    /// `Discv5Event::QueryProgress` and `Discv5Event::FindNodeResult` events. The list is empty if
+    /// the service is not running.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn active_query_ids(&self) -> impl Future<Output = Vec<QueryId>> + 'static {
+        let channel = self.clone_channel();
+
+        async move {
+            let mut channel = match channel {
+                Ok(channel) => channel,
+                Err(_) => return Vec::new(),
+            };
+            let (callback_send, callback_recv) = oneshot::channel();
+            if channel
+                .send(ServiceRequest::ActiveQueryIds(callback_send))
+                .await
+                .is_err()
+            {
+                return Vec::new();
+            }
+            callback_recv.await.unwrap_or_default()
+        }
+    }
+
+    /// Cancels an active query, freeing its slot of `max_concurrent_queries`. The query's future
+    /// resolves with `QueryError::Cancelled`. Returns whether the query was active.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn cancel_query(&mut self, query_id: QueryId) -> impl Future<Output = bool> + 'static {
+        let channel = self.clone_channel();
+
+        async move {
+            let mut channel = match channel {
+                Ok(channel) => channel,
+                Err(_) => return false,
+            };
+            let (callback_send, callback_recv) = oneshot::channel();
+            if channel
+                .send(ServiceRequest::CancelQuery(query_id, callback_send))
+                .await
+                .is_err()
+            {
+                return false;
+            }
+            callback_recv.await.unwrap_or(false)
+        }
+    }
+
+    /// Returns the score of a peer, accumulated from the round-trip times of its responses and
+    /// penalised for timed out requests and invalid responses. Higher scoring peers are preferred
+    /// over other peers at the same distance when starting a query. Returns `None` for peers
+    /// that have not been contacted or are no longer in the routing table.
+    pub fn peer_score(&self, node_id: &NodeId) -> Option<f64> {
+        self.peer_scores.read().score(node_id)
+    }
+
+    /// Returns the number of connected and disconnected entries in each bucket of the routing
+    /// table, as `(connected, disconnected)`. The bucket at index `i` holds the nodes at log2
+    /// distance `i + 1` from the local node.
+    pub fn table_entries_per_bucket(&self) -> Vec<(usize, usize)> {
+        self.kbuckets.read().entries_per_bucket()
+    }
+
     /// Requests the ENR of a node corresponding to multiaddr or multi-addr string.
     ///
+    /// If `max_age` is given and the ENR of the node in the routing table was confirmed to be
+    /// current within it, by a session established with the node or a sequence number advertised
+    /// in a PING or PONG, the stored ENR is returned without contacting the node.
+    ///
     /// Only `ed25519` and `secp256k1` key types are currently supported.
     ///
     /// Note: The async syntax is forgone here in order to create `'static` futures, where the
@@ -383,6 +1104,7 @@ impl Discv5 {
     pub fn request_enr(
         &mut self,
         multiaddr: impl std::convert::TryInto<Multiaddr> + 'static,
+        max_age: Option<Duration>,
     ) -> impl Future<Output = Result<Option<Enr>, RequestError>> + 'static {
         let channel = self.clone_channel();
 
@@ -401,7 +1123,7 @@ impl Discv5 {
 
             let (callback_send, callback_recv) = oneshot::channel();
 
-            let event = ServiceRequest::FindEnr(node_contact, callback_send);
+            let event = ServiceRequest::FindEnr(node_contact, max_age, callback_send);
             channel
                 .send(event)
                 .await
@@ -412,35 +1134,283 @@ impl Discv5 {
         }
     }
 
+    /// Requests the ENR of a node known only by its socket and public key, such as a node listed
+    /// in a seed file.
+    ///
+    /// The node is contacted directly and its ENR is obtained from the handshake. The returned
+    /// channel is closed without a result if the service has not been started or is currently too
+    /// busy to accept the request.
+    pub fn request_enr_raw(
+        &mut self,
+        socket_addr: SocketAddr,
+        public_key: CombinedPublicKey,
+    ) -> oneshot::Receiver<Option<Enr>> {
+        let (callback_send, callback_recv) = oneshot::channel();
+
+        let node_id: NodeId = public_key.clone().into();
+        if node_id == self.local_enr.read().node_id() {
+            let _ = callback_send.send(Some(self.local_enr()));
+            return callback_recv;
+        }
+
+        let node_contact = NodeContact::Raw {
+            public_key: Box::new(public_key),
+            node_address: Box::new(NodeAddress {
+                socket_addr,
+                node_id,
+            }),
+        };
+        if let Some(channel) = self.service_channel.as_mut() {
+            let event = ServiceRequest::FindEnr(node_contact, None, callback_send);
+            if channel.try_send(event).is_err() {
+                warn!(
+                    "Service channel unavailable. ENR request to {} dropped",
+                    socket_addr
+                );
+            }
+        }
+        callback_recv
+    }
+
+    /// Sends a request to a node and returns the node's response.
+    ///
+    /// This gives direct access to the RPC layer. Sessions are established and requests are timed
+    /// out and retried as for any other request, however the response is not processed by the
+    /// server. In particular, ENRs in a NODES response are neither validated against the requested
+    /// distance nor added to the routing table, and only the first packet of a NODES response
+    /// that spans multiple packets is returned. Sending `RequestBody::GetEnr` requests to nodes
+    /// whose ENR is unknown is the intended way to learn their ENR; prefer the higher level
+    /// methods where possible.
+    ///
+    /// The request fails immediately if the service has not been started, or if the service is
+    /// currently too busy to accept the request.
+    pub fn send_request(
+        &self,
+        contact: NodeContact,
+        body: RequestBody,
+    ) -> oneshot::Receiver<Result<Response, RequestError>> {
+        let (callback_send, callback_recv) = oneshot::channel();
+
+        if contact.node_id() == self.local_enr.read().node_id() {
+            let _ = callback_send.send(Err(RequestError::SelfRequest));
+            return callback_recv;
+        }
+        if let Err(e) = contact.udp_socket() {
+            let _ = callback_send.send(Err(RequestError::InvalidEnr(e.into())));
+            return callback_recv;
+        }
+
+        let mut channel = match self.clone_channel() {
+            Ok(channel) => channel,
+            Err(_) => {
+                let _ = callback_send.send(Err(RequestError::ServiceNotStarted));
+                return callback_recv;
+            }
+        };
+        let event = ServiceRequest::Request(contact, body, callback_send);
+        if let Err(e) = channel.try_send(event) {
+            let (event, reason) = match e {
+                mpsc::error::TrySendError::Full(event) => (event, "Service channel full"),
+                mpsc::error::TrySendError::Closed(event) => (event, "Service channel closed"),
+            };
+            if let ServiceRequest::Request(_, _, callback_send) = event {
+                let _ = callback_send.send(Err(RequestError::ChannelFailed(reason.into())));
+            }
+        }
+        callback_recv
+    }
+
+    /// Registers a handler for TALKREQ requests of the given protocol, replacing any handler
+    /// previously registered for it.
+    ///
+    /// The handler is called with the request payload and the id of the requesting node, and the
+    /// returned payload is sent back as the TALKRESP. Requests for unregistered protocols, and
+    /// requests whose handler panics, are answered with an empty response. Each request is
+    /// handled on its own task, off the service task. TALKREQ requests can be sent to other nodes
+    /// with [`Discv5::send_request`].
+    pub fn register_talk_protocol<F>(&mut self, protocol: Vec<u8>, handler: F)
+    where
+        F: Fn(Vec<u8>, NodeId) -> Vec<u8> + Send + Sync + 'static,
+    {
+        self.talk_protocols
+            .lock()
+            .insert(protocol, Arc::new(handler));
+    }
+
+    /// Pings a node and returns its PONG response, which holds the node's current ENR sequence
+    /// number and our external socket as observed by the node.
+    ///
+    /// The PONG is processed as for any other PING, contributing to the external socket vote and
+    /// updating the node's entry in the routing table. The request fails immediately if the
+    /// service has not been started, or if the service is currently too busy to accept the
+    /// request.
+    pub fn send_ping(&self, enr: Enr) -> oneshot::Receiver<Result<Pong, RequestError>> {
+        let (callback_send, callback_recv) = oneshot::channel();
+
+        if enr.node_id() == self.local_enr.read().node_id() {
+            let _ = callback_send.send(Err(RequestError::SelfRequest));
+            return callback_recv;
+        }
+        if enr.udp_socket().is_none() {
+            let _ = callback_send.send(Err(RequestError::InvalidEnr("No UDP socket".into())));
+            return callback_recv;
+        }
+
+        let mut channel = match self.clone_channel() {
+            Ok(channel) => channel,
+            Err(_) => {
+                let _ = callback_send.send(Err(RequestError::ServiceNotStarted));
+                return callback_recv;
+            }
+        };
+        let event = ServiceRequest::Ping(enr, callback_send);
+        if let Err(e) = channel.try_send(event) {
+            let (event, reason) = match e {
+                mpsc::error::TrySendError::Full(event) => (event, "Service channel full"),
+                mpsc::error::TrySendError::Closed(event) => (event, "Service channel closed"),
+            };
+            if let ServiceRequest::Ping(_, callback_send) = event {
+                let _ = callback_send.send(Err(RequestError::ChannelFailed(reason.into())));
+            }
+        }
+        callback_recv
+    }
+
+    /// Pings a node and returns our external socket as observed by it, regardless of the majority
+    /// of votes from other peers. This is useful to learn our external socket from a single
+    /// trusted peer, such as a bootnode, before enough peers are known for a majority.
+    ///
+    /// The PONG is otherwise processed as with [`Discv5::send_ping`]. Returns `None` if the
+    /// request fails.
+    pub fn observed_address_from(&mut self, enr: Enr) -> oneshot::Receiver<Option<SocketAddr>> {
+        let pong = self.send_ping(enr);
+        let (callback_send, callback_recv) = oneshot::channel();
+        self.config
+            .executor
+            .clone()
+            .expect("Executor must be present")
+            .spawn(Box::pin(async move {
+                let socket = match pong.await {
+                    Ok(Ok(pong)) => Some(SocketAddr::new(pong.ip, pong.port)),
+                    _ => None,
+                };
+                let _ = callback_send.send(socket);
+            }));
+        callback_recv
+    }
+
+    /// Pings a node in the routing table, re-validating it immediately rather than waiting for the
+    /// next ping heartbeat. This is useful when a node's ENR is known to have changed.
+    ///
+    /// The returned future resolves once the PONG is received or the request fails. Fails with
+    /// `RequestError::UnknownNode` if the node is not in the routing table.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn ping_node(
+        &mut self,
+        node_id: &NodeId,
+    ) -> impl Future<Output = Result<(), RequestError>> + 'static {
+        let ping = self.find_enr(node_id).map(|enr| self.send_ping(enr));
+
+        async move {
+            let ping = ping.ok_or(RequestError::UnknownNode)?;
+            ping.await
+                .map_err(|_| RequestError::ChannelFailed("Service channel closed".into()))?
+                .map(|_| ())
+        }
+    }
+
     /// Runs an iterative `FIND_NODE` request.
     ///
     /// This will return peers containing contactable nodes of the DHT closest to the
     /// requested `NodeId`.
     ///
+    /// If `query_min_seed_peers` is configured, the query is only started once the routing table
+    /// contains enough peers, failing with `QueryError::InsufficientSeedPeers` if this does not
+    /// happen within `query_seed_wait_timeout`.
+    ///
+    /// A query that finds no peers fails with `QueryError::Timeout` if it timed out, or
+    /// `QueryError::Unreachable` if none of the contacted peers responded. An empty result means
+    /// the query completed without finding any peers.
+    ///
     /// Note: The async syntax is forgone here in order to create `'static` futures, where the
     /// underlying sending channel is cloned.
     pub fn find_node(
         &mut self,
         target_node: NodeId,
     ) -> impl Future<Output = Result<Vec<Enr>, QueryError>> + 'static {
-        let channel = self.clone_channel();
-
-        async move {
-            let mut channel = channel.map_err(|_| QueryError::ServiceNotStarted)?;
-            let (callback_send, callback_recv) = oneshot::channel();
+        self.start_query(
+            QueryKind::FindNode {
+                target_node,
+                options: QueryOptions::default(),
+            },
+            QueryCallback::Enrs,
+        )
+    }
 
-            let query_kind = QueryKind::FindNode { target_node };
+    /// Runs an iterative `FIND_NODE` request as [`Discv5::find_node`], with the given `options`
+    /// overriding the configured `query_parallelism` and `query_peer_timeout`.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn find_node_with_options(
+        &mut self,
+        target_node: NodeId,
+        options: QueryOptions,
+    ) -> impl Future<Output = Result<Vec<Enr>, QueryError>> + 'static {
+        self.start_query(
+            QueryKind::FindNode {
+                target_node,
+                options,
+            },
+            QueryCallback::Enrs,
+        )
+    }
 
-            let event = ServiceRequest::StartQuery(query_kind, callback_send);
-            channel
-                .send(event)
-                .await
-                .map_err(|_| QueryError::ChannelFailed("Service channel closed".into()))?;
+    /// Runs an iterative `FIND_NODE` request as [`Discv5::find_node`], waiting for at least
+    /// `min_results` peers to be found.
+    ///
+    /// Rather than returning once all reachable peers have been contacted, the query waits for
+    /// unresponsive peers until `query_timeout`. If fewer than `min_results` peers were found by
+    /// then, the peers found, if any, are returned in `QueryError::Partial`. `min_results` is
+    /// capped at the number of peers a query returns.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn find_node_min(
+        &mut self,
+        target_node: NodeId,
+        min_results: usize,
+    ) -> impl Future<Output = Result<Vec<Enr>, QueryError>> + 'static {
+        self.start_query(
+            QueryKind::FindNodeMin {
+                target_node,
+                min_results,
+            },
+            QueryCallback::Enrs,
+        )
+    }
 
-            Ok(callback_recv
-                .await
-                .map_err(|e| QueryError::ChannelFailed(e.to_string()))?)
-        }
+    /// Runs an iterative `FIND_NODE` request as [`Discv5::find_node`], returning only the node ids
+    /// of the closest peers.
+    ///
+    /// The ENRs of the resulting peers are not resolved, so no further requests are made once the
+    /// query has finished. This is useful when only the identities of the closest peers are needed.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn find_node_ids(
+        &mut self,
+        target_node: NodeId,
+    ) -> impl Future<Output = Result<Vec<NodeId>, QueryError>> + 'static {
+        self.start_query(
+            QueryKind::FindNode {
+                target_node,
+                options: QueryOptions::default(),
+            },
+            QueryCallback::NodeIds,
+        )
     }
 
     /// Starts a `FIND_NODE` request.
@@ -451,6 +1421,8 @@ impl Discv5 {
     /// The predicate is a boxed function that takes an ENR reference and returns a boolean
     /// indicating if the record is applicable to the query or not.
     ///
+    /// As with [`Discv5::find_node`], the query waits for `query_min_seed_peers` to be known.
+    ///
     /// Note: The async syntax is forgone here in order to create `'static` futures, where the
     /// underlying sending channel is cloned.
     ///
@@ -465,32 +1437,372 @@ impl Discv5 {
         target_node: NodeId,
         predicate: Box<dyn Fn(&Enr) -> bool + Send>,
         target_peer_no: usize,
+    ) -> impl Future<Output = Result<Vec<Enr>, QueryError>> + 'static {
+        self.start_query(
+            QueryKind::Predicate {
+                target_node,
+                predicate,
+                target_peer_no,
+                options: QueryOptions::default(),
+            },
+            QueryCallback::Enrs,
+        )
+    }
+
+    /// Starts a `FIND_NODE` request as [`Discv5::find_node_predicate`], with the given `options`
+    /// overriding the configured `query_parallelism` and `query_peer_timeout`.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn find_node_predicate_with_options(
+        &mut self,
+        target_node: NodeId,
+        predicate: Box<dyn Fn(&Enr) -> bool + Send>,
+        target_peer_no: usize,
+        options: QueryOptions,
+    ) -> impl Future<Output = Result<Vec<Enr>, QueryError>> + 'static {
+        self.start_query(
+            QueryKind::Predicate {
+                target_node,
+                predicate,
+                target_peer_no,
+                options,
+            },
+            QueryCallback::Enrs,
+        )
+    }
+
+    /// Runs an iterative `FIND_NODE` request towards `target_node`, requesting exactly the given
+    /// log2 `distances` from each contacted peer.
+    ///
+    /// Unlike [`Discv5::find_node`], which requests the distances around the target, this allows
+    /// specific buckets to be refreshed. Each distance is requested once and the resulting peers
+    /// are deduplicated. An empty list of distances returns no peers.
+    ///
+    /// As with [`Discv5::find_node`], the query waits for `query_min_seed_peers` to be known.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn find_node_distances(
+        &mut self,
+        target_node: NodeId,
+        distances: Vec<u64>,
+    ) -> impl Future<Output = Result<Vec<Enr>, QueryError>> + 'static {
+        self.start_query(
+            QueryKind::FindNodeDistances {
+                target_node,
+                distances,
+            },
+            QueryCallback::Enrs,
+        )
+    }
+
+    /// Adds an advertiser of a topic to the local topic table. The advertisers of a topic are
+    /// returned to TOPICQUERY requests for it until their advertisement expires, 15 minutes after
+    /// it was added. Adding an advertiser again renews its advertisement.
+    ///
+    /// Returns false if the topic already has the maximum number of advertisers from the /24
+    /// subnet of the advertiser, in which case it is not added.
+    pub fn add_topic_ad(&mut self, topic: [u8; 32], enr: Enr) -> bool {
+        self.topic_table.write().add_ad(topic, enr)
+    }
+
+    /// Searches the network for the advertisers of a topic.
+    ///
+    /// A `FIND_NODE` query is run towards the topic hash, and each of the resulting peers is sent
+    /// a TOPICQUERY request. The advertisers returned are merged, keeping the latest ENR of each.
+    /// Peers that fail to respond are skipped.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn topic_query(
+        &mut self,
+        topic: [u8; 32],
     ) -> impl Future<Output = Result<Vec<Enr>, QueryError>> + 'static {
         let channel = self.clone_channel();
+        let closest_peers = self.find_node(NodeId::new(&topic));
 
         async move {
             let mut channel = channel.map_err(|_| QueryError::ServiceNotStarted)?;
-            let (callback_send, callback_recv) = oneshot::channel();
+            let mut responses = Vec::new();
+            for enr in closest_peers.await? {
+                let (callback_send, callback_recv) = oneshot::channel();
+                let event = ServiceRequest::TopicQuery(enr.into(), topic, callback_send);
+                channel
+                    .send(event)
+                    .await
+                    .map_err(|_| QueryError::ChannelFailed("Service channel closed".into()))?;
+                responses.push(callback_recv);
+            }
 
-            let query_kind = QueryKind::Predicate {
-                target_node,
-                predicate,
-                target_peer_no,
-            };
+            let mut advertisers: HashMap<NodeId, Enr> = HashMap::new();
+            for response in futures::future::join_all(responses).await {
+                let enrs = match response
+                    .map_err(|_| QueryError::ChannelFailed("Service channel closed".into()))?
+                {
+                    Ok(enrs) => enrs,
+                    Err(e) => {
+                        debug!("TOPICQUERY request failed: {}", e);
+                        continue;
+                    }
+                };
+                for enr in enrs {
+                    match advertisers.get(&enr.node_id()) {
+                        Some(known) if known.seq() >= enr.seq() => {}
+                        _ => {
+                            advertisers.insert(enr.node_id(), enr);
+                        }
+                    }
+                }
+            }
+            Ok(advertisers.into_values().collect())
+        }
+    }
+
+    /// Advertises the local node for a topic on the peers closest to the topic hash.
+    ///
+    /// A `FIND_NODE` query is run towards the topic hash, and each of the resulting peers is sent
+    /// a REGTOPIC request. Peers answer with a ticket and the time to wait before it can be used,
+    /// after which the request is re-sent with the ticket to confirm the registration. Tickets
+    /// with a wait time longer than a minute are not waited for. Returns the peers that confirmed
+    /// the registration. Advertisements expire, so topics should be registered periodically.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn register_topic(
+        &mut self,
+        topic: [u8; 32],
+    ) -> impl Future<Output = Result<Vec<Enr>, QueryError>> + 'static {
+        let channel = self.clone_channel();
+        let closest_peers = self.find_node(NodeId::new(&topic));
+        let local_enr = self.local_enr.clone();
+
+        async move {
+            let channel = channel.map_err(|_| QueryError::ServiceNotStarted)?;
+            let registrations = closest_peers.await?.into_iter().map(|enr| {
+                let registration =
+                    register_topic_with(channel.clone(), enr.clone(), topic, local_enr.clone());
+                async move { (enr, registration.await) }
+            });
+
+            let mut registered = Vec::new();
+            for (enr, result) in futures::future::join_all(registrations).await {
+                match result {
+                    Ok(()) => registered.push(enr),
+                    Err(e) => debug!(
+                        "Failed to register topic {} with: {}: {}",
+                        hex::encode(topic),
+                        enr.node_id(),
+                        e
+                    ),
+                }
+            }
+            Ok(registered)
+        }
+    }
+
+    /// Internal helper to start a query once the required number of seed peers are known. The
+    /// result is returned on the callback built by `callback`.
+    fn start_query<T: Send + 'static>(
+        &mut self,
+        query_kind: QueryKind,
+        callback: fn(oneshot::Sender<Result<T, QueryError>>) -> QueryCallback,
+    ) -> impl Future<Output = Result<T, QueryError>> + 'static {
+        let channel = self.clone_channel();
+        let seed_peers = self.wait_for_seed_peers();
+
+        async move {
+            let mut channel = channel.map_err(|_| QueryError::ServiceNotStarted)?;
+            seed_peers.await?;
+            let (callback_send, callback_recv) = oneshot::channel();
 
-            let event = ServiceRequest::StartQuery(query_kind, callback_send);
+            let event = ServiceRequest::StartQuery(query_kind, callback(callback_send));
             channel
                 .send(event)
                 .await
                 .map_err(|_| QueryError::ChannelFailed("Service channel closed".into()))?;
 
-            Ok(callback_recv
+            callback_recv
                 .await
-                .map_err(|e| QueryError::ChannelFailed(e.to_string()))?)
+                .map_err(|e| QueryError::ChannelFailed(e.to_string()))?
+        }
+    }
+
+    /// Returns information about the sessions currently established with peers.
+    ///
+    /// Unlike the routing table, this reflects the encrypted sessions held by the handler. A peer
+    /// may be in the routing table without an active session and vice versa.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn sessions_info(
+        &self,
+    ) -> impl Future<Output = Result<Vec<SessionInfo>, Discv5Error>> + 'static {
+        let channel = self.clone_channel();
+
+        async move {
+            let mut channel = channel?;
+
+            let (callback_send, callback_recv) = oneshot::channel();
+
+            let event = ServiceRequest::SessionsInfo(callback_send);
+            channel
+                .send(event)
+                .await
+                .map_err(|_| Discv5Error::ServiceChannelClosed)?;
+
+            callback_recv
+                .await
+                .map_err(|_| Discv5Error::ServiceChannelClosed)
+        }
+    }
+
+    /// Returns the number of sessions currently established with peers.
+    ///
+    /// This is counted from the sessions held by the handler, so unlike
+    /// [`Discv5::connected_peers`] it excludes peers that are connected in the routing table
+    /// without a session, and includes peers with a session that are not in the routing table.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn active_sessions(&self) -> impl Future<Output = Result<usize, Discv5Error>> + 'static {
+        let channel = self.clone_channel();
+
+        async move {
+            let mut channel = channel?;
+
+            let (callback_send, callback_recv) = oneshot::channel();
+
+            let event = ServiceRequest::SessionCount(callback_send);
+            channel
+                .send(event)
+                .await
+                .map_err(|_| Discv5Error::ServiceChannelClosed)?;
+
+            callback_recv
+                .await
+                .map_err(|_| Discv5Error::ServiceChannelClosed)
+        }
+    }
+
+    /// Returns the connected peers in the routing table with their status and the time they are
+    /// next pinged. The snapshot is taken by the service, such that the routing table is not
+    /// locked from the calling thread.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn connected_peers_info(
+        &self,
+    ) -> impl Future<Output = Result<Vec<(Enr, NodeStatus, Instant)>, Discv5Error>> + 'static {
+        let channel = self.clone_channel();
+
+        async move {
+            let mut channel = channel?;
+
+            let (callback_send, callback_recv) = oneshot::channel();
+
+            let event = ServiceRequest::ConnectedPeersInfo(callback_send);
+            channel
+                .send(event)
+                .await
+                .map_err(|_| Discv5Error::ServiceChannelClosed)?;
+
+            callback_recv
+                .await
+                .map_err(|_| Discv5Error::ServiceChannelClosed)
+        }
+    }
+
+    /// Crawls the network starting from the `seed` ENRs, returning a stream of the ENRs that are
+    /// discovered.
+    ///
+    /// Unlike [`Discv5::find_node`], a crawl does not converge on a target. Each discovered peer is
+    /// visited once and asked for the peers in its furthest buckets, where most of the network
+    /// lives, maximising the number of unique ENRs discovered per request. Up to
+    /// `query_parallelism` peers are requested at a time. Discovered ENRs are not added to the
+    /// routing table.
+    ///
+    /// The stream ends once every discovered peer has been visited. Dropping the receiver ends the
+    /// crawl. No further peers are requested while the stream is full. If the service has not been
+    /// started, the stream ends immediately.
+    pub fn crawl(&self, seed: Vec<Enr>) -> mpsc::Receiver<Enr> {
+        let (stream, stream_recv) = mpsc::channel(CRAWL_STREAM_CAPACITY);
+        match self.clone_channel() {
+            Ok(mut channel) => {
+                if channel
+                    .try_send(ServiceRequest::Crawl(seed, stream))
+                    .is_err()
+                {
+                    warn!("Could not start the crawl. Service channel full or closed");
+                }
+            }
+            Err(_) => warn!("Could not start the crawl. Service not started"),
+        }
+        stream_recv
+    }
+
+    /// Refreshes the routing table by running a `FINDNODE` query towards a random target at each
+    /// log2 distance from the local node. This fills the buckets quickly after startup, for
+    /// example once the bootnodes have been added.
+    ///
+    /// A few queries are run at a time, within `max_concurrent_queries`, starting with the
+    /// furthest distances. A `Discv5Event::BootstrapProgress` event is sent as each query
+    /// finishes, reporting the size of the routing table. Calling this while a bootstrap is in
+    /// progress has no effect.
+    pub fn bootstrap(&mut self) -> Result<(), Discv5Error> {
+        self.clone_channel()?
+            .try_send(ServiceRequest::Bootstrap)
+            .map_err(|_| Discv5Error::ServiceChannelClosed)
+    }
+
+    /// Adds a list of bootnodes with [`Discv5::add_enr_list`] and verifies that they are
+    /// reachable by sending each a FINDNODE request. Bootnodes that could not be added to the
+    /// routing table are not contacted. Returns the number of bootnodes that responded.
+    ///
+    /// Note: The async syntax is forgone here in order to create `'static` futures, where the
+    /// underlying sending channel is cloned.
+    pub fn bootstrap_from(&mut self, bootnodes: Vec<Enr>) -> impl Future<Output = usize> + 'static {
+        let outcomes = self.add_enr_list(bootnodes.clone());
+        let added: Vec<Enr> = bootnodes
+            .into_iter()
+            .zip(outcomes)
+            .filter_map(|(enr, outcome)| outcome.ok().map(|_| enr))
+            .collect();
+        let channel = self.clone_channel();
+
+        async move {
+            let mut channel = match channel {
+                Ok(channel) => channel,
+                Err(_) => return 0,
+            };
+            let mut responses = Vec::new();
+            for enr in added {
+                let (callback_send, callback_recv) = oneshot::channel();
+                // the furthest bucket is the most likely to be populated
+                let body = RequestBody::FindNode {
+                    distances: vec![256],
+                };
+                let event = ServiceRequest::Request(enr.into(), body, callback_send);
+                if channel.send(event).await.is_err() {
+                    break;
+                }
+                responses.push(callback_recv);
+            }
+            futures::future::join_all(responses)
+                .await
+                .into_iter()
+                .filter(|response| matches!(response, Ok(Ok(_))))
+                .count()
         }
     }
 
     /// Creates an event stream channel which can be polled to receive Discv5 events.
+    ///
+    /// Each call returns a new subscriber, which receives all events alongside any existing
+    /// subscribers. A subscriber is removed once its receiver is dropped.
+    ///
+    /// Returns `Discv5Error::ServiceNotStarted` if the service has not been started or has been
+    /// shut down, and `Discv5Error::ServiceChannelClosed` if the service has otherwise stopped.
     pub fn event_stream(
         &mut self,
     ) -> impl Future<Output = Result<mpsc::Receiver<Discv5Event>, Discv5Error>> + 'static {
@@ -513,6 +1825,29 @@ impl Discv5 {
         }
     }
 
+    /// Waits until the routing table contains at least `query_min_seed_peers` entries. Returns an
+    /// error if this is not the case within `query_seed_wait_timeout`.
+    fn wait_for_seed_peers(&self) -> impl Future<Output = Result<(), QueryError>> + 'static {
+        let kbuckets = self.kbuckets.clone();
+        let min_seed_peers = self.config.query_min_seed_peers;
+        let timeout = self.config.query_seed_wait_timeout;
+
+        async move {
+            let deadline = Instant::now() + timeout;
+            loop {
+                let seed_peers = kbuckets.write().iter().count();
+                if seed_peers >= min_seed_peers {
+                    return Ok(());
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(QueryError::InsufficientSeedPeers(seed_peers));
+                }
+                tokio::time::delay_for(SEED_PEER_POLL_INTERVAL.min(deadline - now)).await;
+            }
+        }
+    }
+
     /// Internal helper function to send events to the Service.
     fn clone_channel(&self) -> Result<mpsc::Sender<ServiceRequest>, Discv5Error> {
         if let Some(channel) = self.service_channel.as_ref() {
@@ -523,8 +1858,56 @@ impl Discv5 {
     }
 }
 
+/// Registers the local node as an advertiser of a topic with a peer, waiting out the tickets it
+/// issues. Up to `MAX_REGTOPIC_ATTEMPTS` REGTOPIC requests are sent.
+async fn register_topic_with(
+    mut channel: mpsc::Sender<ServiceRequest>,
+    peer: Enr,
+    topic: [u8; 32],
+    local_enr: Arc<RwLock<Enr>>,
+) -> Result<(), RequestError> {
+    let mut ticket = Vec::new();
+    for _ in 0..MAX_REGTOPIC_ATTEMPTS {
+        // the latest local ENR is advertised
+        let body = RequestBody::RegTopic {
+            topic,
+            enr: local_enr.read().clone(),
+            ticket,
+        };
+        let (callback_send, callback_recv) = oneshot::channel();
+        let event = ServiceRequest::Request(peer.clone().into(), body, callback_send);
+        channel
+            .send(event)
+            .await
+            .map_err(|_| RequestError::ChannelFailed("Service channel closed".into()))?;
+        let response = callback_recv
+            .await
+            .map_err(|_| RequestError::ChannelFailed("Service channel closed".into()))??;
+
+        match response.body {
+            ResponseBody::RegConfirmation { topic: confirmed } if confirmed == topic => {
+                return Ok(());
+            }
+            ResponseBody::Ticket {
+                ticket: issued,
+                wait_time,
+            } => {
+                let wait_time = Duration::from_secs(wait_time);
+                if wait_time > MAX_TICKET_WAIT_TIME {
+                    return Err(RequestError::TopicNotRegistered);
+                }
+                tokio::time::delay_for(wait_time).await;
+                ticket = issued;
+            }
+            _ => return Err(RequestError::InvalidRemotePacket),
+        }
+    }
+    Err(RequestError::TopicNotRegistered)
+}
+
 impl Drop for Discv5 {
     fn drop(&mut self) {
-        self.shutdown();
+        // the service shuts down in the background
+        drop(self.shutdown());
     }
 }
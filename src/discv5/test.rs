@@ -8,7 +8,11 @@ use enr::{CombinedKey, Enr, EnrBuilder, EnrKey};
 use env_logger;
 use rand_core::{RngCore, SeedableRng};
 use rand_xorshift;
-use std::{collections::HashMap, net::IpAddr};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
 
 fn init() {
     let _ = env_logger::builder().is_test(true).try_init();
@@ -328,3 +332,2579 @@ async fn test_predicate_search() {
     println!("Nodes expected to pass predicate search {}", num_nodes);
     assert!(found_nodes.len() == num_nodes);
 }
+
+#[tokio::test]
+async fn test_self_query_returns_known_peers() {
+    init();
+    let mut nodes = build_nodes(3, 13000);
+    let mut local_node = nodes.remove(0);
+    for node in nodes.iter() {
+        local_node.add_enr(node.local_enr()).unwrap();
+    }
+
+    let local_id = local_node.local_enr().node_id();
+    let found_nodes = local_node.find_node(local_id).await.unwrap();
+
+    // the local node is never contacted and all known peers are returned
+    assert_eq!(found_nodes.len(), nodes.len());
+    assert!(found_nodes.iter().all(|enr| enr.node_id() != local_id));
+    for node in nodes.iter() {
+        assert!(found_nodes.contains(&node.local_enr()));
+    }
+}
+
+#[tokio::test]
+async fn test_find_enr_async() {
+    init();
+    let mut nodes = build_nodes(2, 13100);
+    let remote_enr = nodes[1].local_enr();
+    let local_node = &mut nodes[0];
+    local_node.add_enr(remote_enr.clone()).unwrap();
+
+    // known nodes are returned from the routing table
+    assert_eq!(
+        local_node.find_enr(&remote_enr.node_id()),
+        Some(remote_enr.clone())
+    );
+    let found = local_node.find_enr_async(remote_enr.node_id()).await;
+    assert_eq!(found, Some(remote_enr));
+
+    // unknown nodes are searched for
+    let found = local_node.find_enr_async(NodeId::random()).await;
+    assert_eq!(found, None);
+}
+
+#[tokio::test]
+async fn test_find_enr_async_searches_network() {
+    init();
+    let mut nodes = build_nodes(3, 19700);
+    // the local node only learns of the target from its peer
+    let peer_enr = nodes[1].local_enr();
+    let target_enr = nodes[2].local_enr();
+    nodes[1].add_enr(target_enr.clone()).unwrap();
+    nodes[0].add_enr(peer_enr).unwrap();
+    assert_eq!(nodes[0].find_enr(&target_enr.node_id()), None);
+
+    let found = nodes[0].find_enr_async(target_enr.node_id()).await;
+    assert_eq!(found, Some(target_enr));
+}
+
+#[tokio::test]
+async fn test_local_enr_of() {
+    init();
+    let mut nodes = build_nodes(2, 16000);
+    let remote_enr = nodes[1].local_enr();
+    let local_node = &mut nodes[0];
+
+    // nodes that are not known locally are not requested from the network
+    assert_eq!(local_node.local_enr_of(&remote_enr.node_id()).await, None);
+
+    local_node.add_enr(remote_enr.clone()).unwrap();
+    assert_eq!(
+        local_node.local_enr_of(&remote_enr.node_id()).await,
+        Some(remote_enr)
+    );
+}
+
+#[tokio::test]
+async fn test_local_enr_is_contactable() {
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4").build(&enr_key).unwrap();
+    let config = Discv5ConfigBuilder::new().disable_enr_update().build();
+    let discv5 = Discv5::new(enr, enr_key, config).unwrap();
+    // no socket is advertised
+    assert!(!discv5.local_enr_is_contactable());
+
+    let socket: SocketAddr = "127.0.0.1:16500".parse().unwrap();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip(socket.ip())
+        .udp(socket.port())
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new().disable_enr_update().build();
+    let discv5 = Discv5::new(enr, enr_key, config).unwrap();
+    assert!(discv5.local_enr_is_contactable());
+
+    // with `enr_update` enabled, the majority of votes must agree with the advertised socket
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip(socket.ip())
+        .udp(socket.port())
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new().build();
+    let discv5 = Discv5::new(enr, enr_key, config).unwrap();
+    assert!(!discv5.local_enr_is_contactable());
+
+    let ip_votes = discv5.ip_votes.as_ref().unwrap();
+    let other_socket: SocketAddr = "127.0.0.2:16500".parse().unwrap();
+    for _ in 0..discv5.config.enr_peer_update_min {
+        ip_votes.write().insert(NodeId::random(), other_socket);
+    }
+    assert!(!discv5.local_enr_is_contactable());
+
+    for _ in 0..=discv5.config.enr_peer_update_min {
+        ip_votes.write().insert(NodeId::random(), socket);
+    }
+    assert!(discv5.local_enr_is_contactable());
+}
+
+#[tokio::test]
+async fn test_closest_local_nodes() {
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4").build(&enr_key).unwrap();
+    let mut discv5 = Discv5::new(enr, enr_key, Discv5Config::default()).unwrap();
+    let mut enrs = Vec::new();
+    for port in 16700..16710 {
+        let enr = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(port)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+        discv5.add_enr(enr.clone()).unwrap();
+        enrs.push(enr);
+    }
+
+    let target = NodeId::random();
+    let target_key = kbucket::Key::from(target);
+    enrs.sort_by_key(|enr| target_key.distance(&kbucket::Key::from(enr.node_id())));
+    assert_eq!(discv5.closest_local_nodes(target, 3), enrs[..3].to_vec());
+    assert_eq!(discv5.closest_local_nodes(target, 20), enrs);
+}
+
+#[tokio::test]
+async fn test_estimate_network_size() {
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4").build(&enr_key).unwrap();
+    let mut discv5 = Discv5::new(enr, enr_key, Discv5Config::default()).unwrap();
+    assert_eq!(discv5.estimate_network_size(), None);
+    for port in 17400..17410 {
+        let enr = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(port)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+        discv5.add_enr(enr).unwrap();
+        let expected_some = port - 17400 + 1 >= 8;
+        assert_eq!(discv5.estimate_network_size().is_some(), expected_some);
+    }
+    // even a single entry in the furthest bucket covers half of the key space
+    assert!(discv5.estimate_network_size().unwrap() >= 2.0);
+}
+
+#[tokio::test]
+async fn test_enr_insert_exceeding_max_size() {
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4").build(&enr_key).unwrap();
+    let config = Discv5ConfigBuilder::new().build();
+    let mut discv5 = Discv5::new(enr, enr_key, config).unwrap();
+    let original_enr = discv5.local_enr();
+
+    let result = discv5.enr_insert("large", vec![0; 300]);
+    assert!(matches!(result, Err(enr::EnrError::ExceedsMaxSize)));
+    assert_eq!(discv5.local_enr(), original_enr);
+
+    // small fields are still permitted
+    assert!(discv5.enr_insert("small", vec![1]).is_ok());
+    assert_eq!(discv5.local_enr().get("small"), Some(&vec![1]));
+}
+
+#[tokio::test]
+async fn test_fetch_missing_result_enrs() {
+    init();
+    let ip: IpAddr = "127.0.0.1".parse().unwrap();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip(ip)
+        .udp(13200)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new()
+        .request_timeout(std::time::Duration::from_secs(1))
+        .fetch_missing_result_enrs()
+        .build();
+    let socket_addr = enr.udp_socket().unwrap();
+    let mut local_node = Discv5::new(enr, enr_key, config).unwrap();
+    local_node.start(socket_addr);
+
+    let remote_node = build_nodes(1, 13201).remove(0);
+    let remote_enr = remote_node.local_enr();
+    // a peer that never responds, keeping the query running
+    let unreachable_enr = EnrBuilder::new("v4")
+        .ip(ip)
+        .udp(13202)
+        .build(&CombinedKey::generate_secp256k1())
+        .unwrap();
+    local_node.add_enr(remote_enr.clone()).unwrap();
+    local_node.add_enr(unreachable_enr).unwrap();
+
+    let mut events = local_node.event_stream().await.unwrap();
+    let query = tokio::spawn(local_node.find_node(NodeId::random()));
+    // the remote node responds to the query and is then removed from the routing table, its ENR
+    // must be fetched before the results are returned.
+    loop {
+        if let Some(Discv5Event::QueryProgress { .. }) = events.recv().await {
+            break;
+        }
+    }
+    assert!(local_node.remove_node(&remote_enr.node_id()));
+
+    let found = query.await.unwrap().unwrap();
+    assert_eq!(found, vec![remote_enr]);
+}
+
+#[tokio::test]
+async fn test_send_request() {
+    init();
+    let nodes = build_nodes(2, 13300);
+    let remote_enr = nodes[1].local_enr();
+
+    let response = nodes[0]
+        .send_request(
+            remote_enr.clone().into(),
+            RequestBody::FindNode { distances: vec![0] },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    match response.body {
+        ResponseBody::Nodes { nodes, .. } => assert_eq!(nodes, vec![remote_enr]),
+        body => panic!("Unexpected response: {}", body),
+    }
+
+    // requests to ourselves are rejected
+    let result = nodes[0]
+        .send_request(
+            nodes[0].local_enr().into(),
+            RequestBody::Ping { enr_seq: 1 },
+        )
+        .await
+        .unwrap();
+    assert_eq!(result, Err(RequestError::SelfRequest));
+}
+
+#[tokio::test]
+async fn test_contact_with_socket_override() {
+    init();
+    let mut local_node = build_nodes(1, 16900).remove(0);
+
+    // the remote advertises a socket it does not listen on
+    let enr_key = CombinedKey::generate_secp256k1();
+    let remote_enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(16901)
+        .build(&enr_key)
+        .unwrap();
+    let listen_socket: SocketAddr = "127.0.0.1:16902".parse().unwrap();
+    let mut remote_node =
+        Discv5::new(remote_enr.clone(), enr_key, Discv5Config::default()).unwrap();
+    remote_node.start(listen_socket);
+
+    let contact = NodeContact::EnrWithAddr {
+        enr: Box::new(remote_enr.clone()),
+        socket_addr: listen_socket,
+    };
+    assert_eq!(contact.udp_socket(), Ok(listen_socket));
+    assert_eq!(
+        contact.node_address(),
+        Ok(NodeAddress::new(listen_socket, remote_enr.node_id()))
+    );
+
+    let response = local_node
+        .send_request(contact, RequestBody::Ping { enr_seq: 1 })
+        .await
+        .unwrap()
+        .unwrap();
+    match response.body {
+        ResponseBody::Ping { port, .. } => assert_eq!(port, 16900),
+        body => panic!("Unexpected response: {}", body),
+    }
+    local_node.shutdown().await;
+    remote_node.shutdown().await;
+}
+
+#[tokio::test]
+async fn test_talk_protocol_registry() {
+    init();
+    let mut nodes = build_nodes(2, 16600);
+    let local_id = nodes[0].local_enr().node_id();
+    let remote_enr = nodes[1].local_enr();
+
+    nodes[1].register_talk_protocol(b"echo".to_vec(), move |request: Vec<u8>, node_id| {
+        assert_eq!(node_id, local_id);
+        request.into_iter().rev().collect()
+    });
+    nodes[1].register_talk_protocol(b"panic".to_vec(), |_, _| panic!("handler failure"));
+
+    let talk = |protocol: &[u8]| {
+        nodes[0].send_request(
+            remote_enr.clone().into(),
+            RequestBody::TalkReq {
+                protocol: protocol.to_vec(),
+                request: vec![1, 2, 3],
+            },
+        )
+    };
+
+    let response = talk(b"echo").await.unwrap().unwrap();
+    assert_eq!(
+        response.body,
+        ResponseBody::TalkResp {
+            response: vec![3, 2, 1]
+        }
+    );
+
+    // unregistered protocols are given an empty response
+    let response = talk(b"unknown").await.unwrap().unwrap();
+    assert_eq!(response.body, ResponseBody::TalkResp { response: vec![] });
+
+    // a panicking handler is given an empty response
+    let response = talk(b"panic").await.unwrap().unwrap();
+    assert_eq!(response.body, ResponseBody::TalkResp { response: vec![] });
+}
+
+#[tokio::test]
+async fn test_no_empty_findnode_responses() {
+    init();
+    let build_node = |port, config| {
+        let enr_key = CombinedKey::generate_secp256k1();
+        let enr = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(port)
+            .build(&enr_key)
+            .unwrap();
+        let socket_addr = enr.udp_socket().unwrap();
+        let mut node = Discv5::new(enr, enr_key, config).unwrap();
+        node.start(socket_addr);
+        node
+    };
+    let local_node = build_node(
+        17300,
+        Discv5ConfigBuilder::new()
+            .request_timeout(std::time::Duration::from_millis(300))
+            .request_retries(0)
+            .build(),
+    );
+    let remote_node = build_node(
+        17301,
+        Discv5ConfigBuilder::new()
+            .disable_empty_findnode_responses()
+            .build(),
+    );
+    let remote_enr = remote_node.local_enr();
+
+    // the remote still responds with its own ENR
+    let response = local_node
+        .send_request(remote_enr.clone().into(), RequestBody::GetEnr)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        matches!(response.body, ResponseBody::Nodes { nodes, .. } if nodes == vec![remote_enr.clone()])
+    );
+
+    // the remote knows no nodes, so does not respond
+    let result = local_node
+        .send_request(
+            remote_enr.into(),
+            RequestBody::FindNode {
+                distances: vec![256],
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(result, Err(RequestError::Timeout));
+}
+
+#[tokio::test]
+async fn test_passive_mode() {
+    init();
+    let build_node = |port, config| {
+        let enr_key = CombinedKey::generate_secp256k1();
+        let enr = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(port)
+            .build(&enr_key)
+            .unwrap();
+        let socket_addr = enr.udp_socket().unwrap();
+        let mut node = Discv5::new(enr, enr_key, config).unwrap();
+        node.start(socket_addr);
+        node
+    };
+    let local_node = build_node(17500, Discv5ConfigBuilder::new().build());
+    let mut remote_node = build_node(
+        17501,
+        Discv5ConfigBuilder::new().enable_passive_mode().build(),
+    );
+    let remote_enr = remote_node.local_enr();
+
+    // the remote knows of a peer, but does not share it
+    let peer_enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(17502)
+        .build(&CombinedKey::generate_secp256k1())
+        .unwrap();
+    let distance = kbucket::Key::from(remote_enr.node_id())
+        .log2_distance(&kbucket::Key::from(peer_enr.node_id()))
+        .unwrap();
+    remote_node.add_enr(peer_enr).unwrap();
+
+    let response = local_node
+        .send_request(
+            remote_enr.clone().into(),
+            RequestBody::FindNode {
+                distances: vec![distance],
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(response.body, ResponseBody::Nodes { nodes, .. } if nodes.is_empty()));
+
+    // PINGs and ENR requests are still answered
+    let response = local_node
+        .send_request(remote_enr.clone().into(), RequestBody::GetEnr)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        matches!(response.body, ResponseBody::Nodes { nodes, .. } if nodes == vec![remote_enr.clone()])
+    );
+    let response = local_node
+        .send_request(
+            remote_enr.into(),
+            RequestBody::Ping {
+                enr_seq: local_node.local_enr().seq(),
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(response.body, ResponseBody::Ping { .. }));
+}
+
+#[tokio::test]
+async fn test_query_waits_for_seed_peers() {
+    init();
+    let ip: IpAddr = "127.0.0.1".parse().unwrap();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip(ip)
+        .udp(13400)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new()
+        .query_min_seed_peers(1)
+        .query_seed_wait_timeout(std::time::Duration::from_millis(300))
+        .build();
+    let socket_addr = enr.udp_socket().unwrap();
+    let mut local_node = Discv5::new(enr, enr_key, config).unwrap();
+    local_node.start(socket_addr);
+
+    // no peers become known
+    let result = local_node.find_node(NodeId::random()).await;
+    assert_eq!(result, Err(QueryError::InsufficientSeedPeers(0)));
+
+    // a peer becomes known while the query is waiting
+    let remote_node = build_nodes(1, 13401).remove(0);
+    let remote_enr = remote_node.local_enr();
+    let query = tokio::spawn(local_node.find_node(NodeId::random()));
+    tokio::time::delay_for(std::time::Duration::from_millis(100)).await;
+    local_node.add_enr(remote_enr.clone()).unwrap();
+
+    let found = query.await.unwrap().unwrap();
+    assert_eq!(found, vec![remote_enr]);
+}
+
+#[tokio::test]
+async fn test_sessions_info() {
+    init();
+    let nodes = build_nodes(2, 13500);
+    let remote_enr = nodes[1].local_enr();
+    assert!(nodes[0].sessions_info().await.unwrap().is_empty());
+    assert_eq!(nodes[0].active_sessions().await.unwrap(), 0);
+
+    // establish a session
+    nodes[0]
+        .send_request(
+            remote_enr.clone().into(),
+            RequestBody::FindNode { distances: vec![0] },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+    let sessions = nodes[0].sessions_info().await.unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].node_id, remote_enr.node_id());
+    assert_eq!(Some(sessions[0].remote_addr), remote_enr.udp_socket());
+    assert!(sessions[0].expires > sessions[0].established);
+    assert_eq!(nodes[0].active_sessions().await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_event_stream_errors() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(19200)
+        .build(&enr_key)
+        .unwrap();
+    let socket_addr = enr.udp_socket().unwrap();
+    let mut node = Discv5::new(enr, enr_key, Discv5Config::default()).unwrap();
+    assert!(matches!(
+        node.event_stream().await,
+        Err(Discv5Error::ServiceNotStarted)
+    ));
+
+    node.start(socket_addr);
+    assert!(node.event_stream().await.is_ok());
+
+    node.shutdown().await;
+    assert!(matches!(
+        node.event_stream().await,
+        Err(Discv5Error::ServiceNotStarted)
+    ));
+}
+
+#[tokio::test]
+async fn test_session_established_event() {
+    init();
+    let mut nodes = build_nodes(2, 13800);
+    let remote_enr = nodes[1].local_enr();
+    let mut events = nodes[0].event_stream().await.unwrap();
+
+    nodes[0]
+        .send_request(
+            remote_enr.clone().into(),
+            RequestBody::FindNode { distances: vec![0] },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+    loop {
+        match events.recv().await {
+            Some(Discv5Event::SessionEstablished(enr, socket_addr)) => {
+                assert_eq!(enr, remote_enr);
+                assert_eq!(Some(socket_addr), remote_enr.udp_socket());
+                break;
+            }
+            Some(_) => {}
+            None => panic!("Event stream closed before the session was established"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_node_removed_event() {
+    init();
+    let mut node = build_nodes(1, 13900).remove(0);
+    let mut events = node.event_stream().await.unwrap();
+
+    let build_enr = |ip: &str, port| {
+        EnrBuilder::new("v4")
+            .ip(ip.parse().unwrap())
+            .udp(port)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap()
+    };
+    let removed_enr = build_enr("127.0.0.1", 13901);
+    // the ban list is shared by all tests, so the banned node uses an IP no other test uses
+    let banned_enr = build_enr("192.0.2.1", 13902);
+    node.add_enr(removed_enr.clone()).unwrap();
+    node.add_enr(banned_enr.clone()).unwrap();
+
+    assert!(node.remove_node(&removed_enr.node_id()));
+    node.ban_until(
+        &NodeAddress {
+            node_id: banned_enr.node_id(),
+            socket_addr: banned_enr.udp_socket().unwrap(),
+        },
+        Some(std::time::Instant::now() + std::time::Duration::from_secs(1)),
+    );
+    // removing a node that is not in the table produces no event
+    assert!(!node.remove_node(&removed_enr.node_id()));
+
+    let mut expected = vec![
+        (removed_enr.node_id(), RemovalReason::Removed),
+        (banned_enr.node_id(), RemovalReason::Banned),
+    ]
+    .into_iter();
+    while let Some(event) = events.recv().await {
+        if let Discv5Event::NodeRemoved { node_id, reason } = event {
+            assert_eq!(Some((node_id, reason)), expected.next());
+            if expected.len() == 0 {
+                break;
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_inter_packet_delay() {
+    init();
+    let delay = std::time::Duration::from_millis(500);
+    let ip: IpAddr = "127.0.0.1".parse().unwrap();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip(ip)
+        .udp(13600)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new().inter_packet_delay(delay).build();
+    let socket_addr = enr.udp_socket().unwrap();
+    let mut remote_node = Discv5::new(enr, enr_key, config).unwrap();
+    remote_node.start(socket_addr);
+
+    // fill the furthest bucket of the remote node, such that it responds with multiple packets
+    let remote_key: kbucket::Key<NodeId> = remote_node.local_enr().node_id().into();
+    let mut port = 13610;
+    while remote_node.table_entries_id().len() < 16 {
+        let enr = EnrBuilder::new("v4")
+            .ip(ip)
+            .udp(port)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+        if remote_key.log2_distance(&enr.node_id().into()) == Some(256) {
+            remote_node.add_enr(enr).unwrap();
+            port += 1;
+        }
+    }
+    let target = remote_node.table_entries_id()[0];
+
+    let mut local_node = build_nodes(1, 13601).remove(0);
+    local_node.add_enr(remote_node.local_enr()).unwrap();
+    let mut events = local_node.event_stream().await.unwrap();
+
+    let start = std::time::Instant::now();
+    tokio::spawn(local_node.find_node(target));
+    let discovered = async {
+        loop {
+            match events.recv().await {
+                Some(Discv5Event::Discovered(_)) => break,
+                Some(_) => {}
+                None => panic!("Event stream closed"),
+            }
+        }
+    };
+    tokio::time::timeout(std::time::Duration::from_secs(5), discovered)
+        .await
+        .unwrap();
+    // the nodes are only reported once all packets of the response have been received
+    assert!(start.elapsed() >= delay);
+}
+
+#[tokio::test]
+async fn test_crawl() {
+    init();
+    // generates a key whose node id is within the distances requested by a crawl of `node_id`
+    let crawlable_key = |node_id: NodeId| loop {
+        let key = CombinedKey::generate_secp256k1();
+        let id = EnrBuilder::new("v4").build(&key).unwrap().node_id();
+        if kbucket::Key::from(node_id).log2_distance(&id.into()) >= Some(254) {
+            return key;
+        }
+    };
+
+    let seed_key = CombinedKey::generate_secp256k1();
+    let seed_id = EnrBuilder::new("v4").build(&seed_key).unwrap().node_id();
+    let first_key = crawlable_key(seed_id);
+    let first_id = EnrBuilder::new("v4").build(&first_key).unwrap().node_id();
+    let second_key = crawlable_key(first_id);
+    let keys = vec![
+        CombinedKey::generate_secp256k1(),
+        seed_key,
+        first_key,
+        second_key,
+    ];
+
+    // the seed only knows the first node, which only knows the second node
+    let mut nodes = build_nodes_from_keypairs(keys, 13700);
+    let first_enr = nodes[2].local_enr();
+    let second_enr = nodes[3].local_enr();
+    nodes[1].add_enr(first_enr.clone()).unwrap();
+    nodes[2].add_enr(second_enr.clone()).unwrap();
+
+    let mut stream = nodes[0].crawl(vec![nodes[1].local_enr()]);
+    let mut crawled = Vec::new();
+    while let Some(enr) = tokio::time::timeout(std::time::Duration::from_secs(5), stream.recv())
+        .await
+        .unwrap()
+    {
+        crawled.push(enr);
+    }
+    assert_eq!(crawled, vec![first_enr, second_enr]);
+}
+
+#[tokio::test]
+async fn test_max_concurrent_queries() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(14000)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new().max_concurrent_queries(1).build();
+    let socket_addr = enr.udp_socket().unwrap();
+    let mut node = Discv5::new(enr, enr_key, config).unwrap();
+    node.start(socket_addr);
+
+    // an unresponsive peer keeps the first query active
+    let unresponsive_enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(14001)
+        .build(&CombinedKey::generate_secp256k1())
+        .unwrap();
+    node.add_enr(unresponsive_enr).unwrap();
+
+    tokio::spawn(node.find_node(NodeId::random()));
+    tokio::time::delay_for(std::time::Duration::from_millis(100)).await;
+
+    assert_eq!(
+        node.find_node(NodeId::random()).await,
+        Err(QueryError::TooManyConcurrent)
+    );
+}
+
+#[tokio::test]
+async fn test_query_errors() {
+    init();
+    let build_node = |port, config| {
+        let enr_key = CombinedKey::generate_secp256k1();
+        let enr = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(port)
+            .build(&enr_key)
+            .unwrap();
+        let socket_addr = enr.udp_socket().unwrap();
+        let mut node = Discv5::new(enr, enr_key, config).unwrap();
+        node.start(socket_addr);
+        node
+    };
+    // a peer that never responds
+    let unresponsive_enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(14100)
+        .build(&CombinedKey::generate_secp256k1())
+        .unwrap();
+
+    let config = Discv5ConfigBuilder::new()
+        .request_timeout(std::time::Duration::from_millis(100))
+        .request_retries(0)
+        .build();
+    let mut node = build_node(14101, config);
+    // an empty routing table is not a failure
+    assert_eq!(node.find_node(NodeId::random()).await, Ok(Vec::new()));
+    node.add_enr(unresponsive_enr.clone()).unwrap();
+    assert_eq!(
+        node.find_node(NodeId::random()).await,
+        Err(QueryError::Unreachable)
+    );
+
+    let config = Discv5ConfigBuilder::new()
+        .query_timeout(std::time::Duration::from_millis(100))
+        .build();
+    let mut node = build_node(14102, config);
+    node.add_enr(unresponsive_enr).unwrap();
+    let query = tokio::spawn(node.find_node(NodeId::random()));
+    // query timeouts are checked when the service is next woken
+    tokio::time::delay_for(std::time::Duration::from_millis(200)).await;
+    node.sessions_info().await.unwrap();
+    assert_eq!(query.await.unwrap(), Err(QueryError::Timeout));
+}
+
+#[tokio::test]
+async fn test_find_node_distances() {
+    init();
+    let mut nodes = build_nodes(4, 14200);
+    let bootnode_enr = nodes[1].local_enr();
+    let bootnode_key: kbucket::Key<NodeId> = bootnode_enr.node_id().into();
+    let mut distances = Vec::new();
+    let enrs = nodes[2..]
+        .iter()
+        .map(|node| node.local_enr())
+        .collect::<Vec<_>>();
+    for enr in enrs {
+        distances.push(bootnode_key.log2_distance(&enr.node_id().into()).unwrap());
+        nodes[1].add_enr(enr).unwrap();
+    }
+    // duplicate distances are only requested once
+    distances.push(distances[0]);
+    nodes[0].add_enr(bootnode_enr).unwrap();
+
+    let found = nodes[0]
+        .find_node_distances(NodeId::random(), distances)
+        .await
+        .unwrap();
+    let found_ids = found
+        .iter()
+        .map(|enr| enr.node_id())
+        .collect::<std::collections::HashSet<_>>();
+    let expected_ids = nodes[1..]
+        .iter()
+        .map(|node| node.local_enr().node_id())
+        .collect::<std::collections::HashSet<_>>();
+    assert_eq!(found.len(), expected_ids.len());
+    assert_eq!(found_ids, expected_ids);
+
+    assert_eq!(
+        nodes[0]
+            .find_node_distances(NodeId::random(), Vec::new())
+            .await,
+        Ok(Vec::new())
+    );
+}
+
+#[tokio::test]
+async fn test_table_snapshot() {
+    init();
+    let mut node = build_nodes(1, 14300).remove(0);
+    let mut expected_ids = std::collections::HashSet::new();
+    for port in 14301..14304 {
+        let enr = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(port)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+        expected_ids.insert(enr.node_id());
+        node.add_enr(enr).unwrap();
+    }
+
+    let enrs = node.table_snapshot();
+    assert_eq!(enrs.len(), expected_ids.len());
+    assert!(enrs.iter().all(|enr| expected_ids.contains(&enr.node_id())));
+
+    // the table can still be read once the service has stopped
+    node.shutdown().await;
+    assert_eq!(node.table_snapshot().len(), expected_ids.len());
+}
+
+#[tokio::test]
+async fn test_reload_table() {
+    init();
+    let build_enr = |port, tcp: bool| {
+        let mut builder = EnrBuilder::new("v4");
+        builder.ip("127.0.0.1".parse().unwrap()).udp(port);
+        if tcp {
+            builder.tcp(port);
+        }
+        builder.build(&CombinedKey::generate_secp256k1()).unwrap()
+    };
+    let table = vec![
+        build_enr(14401, false),
+        build_enr(14402, false),
+        // rejected by the table filter
+        build_enr(14403, true),
+    ];
+
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(14404)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new()
+        .table_filter(|enr| enr.tcp().is_none())
+        .build();
+    let reloaded = Discv5::with_table(enr, enr_key, config, table.clone()).unwrap();
+
+    let snapshot = reloaded.table_snapshot();
+    assert_eq!(snapshot.len(), 2);
+    assert!(snapshot.iter().all(|enr| table[..2].contains(enr)));
+    // reloaded nodes are not connected
+    assert_eq!(reloaded.connected_peers(), 0);
+}
+
+#[tokio::test]
+async fn test_query_parallelism_override() {
+    init();
+    let mut node = build_nodes(1, 14500).remove(0);
+    // unresponsive peers that record whether they were contacted
+    let mut sockets = Vec::new();
+    for port in 14501..14505 {
+        let enr = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(port)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+        node.add_enr(enr.clone()).unwrap();
+        sockets.push(
+            tokio::net::UdpSocket::bind(enr.udp_socket().unwrap())
+                .await
+                .unwrap(),
+        );
+    }
+
+    // the default parallelism of 3 would leave one peer uncontacted
+    tokio::spawn(node.find_node_with_options(
+        NodeId::random(),
+        QueryOptions {
+            parallelism: Some(4),
+            ..Default::default()
+        },
+    ));
+    let mut buffer = [0; 1280];
+    for socket in sockets.iter_mut() {
+        tokio::time::timeout(
+            std::time::Duration::from_millis(500),
+            socket.recv_from(&mut buffer),
+        )
+        .await
+        .expect("All peers should be contacted")
+        .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_query_peer_timeout_override() {
+    init();
+    let mut node = build_nodes(1, 19300).remove(0);
+    // unresponsive peers that record whether they were contacted
+    let mut sockets = Vec::new();
+    for port in 19301..19305 {
+        let enr = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(port)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+        node.add_enr(enr.clone()).unwrap();
+        sockets.push(
+            tokio::net::UdpSocket::bind(enr.udp_socket().unwrap())
+                .await
+                .unwrap(),
+        );
+    }
+
+    // the last peer is only contacted once another is marked unresponsive, which the default
+    // peer timeout of 2 seconds would delay past the deadline. Peer timeouts are checked each
+    // second.
+    let deadline = tokio::time::Instant::now() + std::time::Duration::from_millis(1900);
+    tokio::spawn(node.find_node_with_options(
+        NodeId::random(),
+        QueryOptions {
+            peer_timeout: Some(std::time::Duration::from_millis(100)),
+            ..Default::default()
+        },
+    ));
+    let mut buffer = [0; 1280];
+    for socket in sockets.iter_mut() {
+        tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer))
+            .await
+            .expect("All peers should be contacted")
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_find_node_result_event() {
+    init();
+    let mut nodes = build_nodes(2, 14600);
+    let remote_enr = nodes[1].local_enr();
+    nodes[0].add_enr(remote_enr.clone()).unwrap();
+    let mut events = nodes[0].event_stream().await.unwrap();
+
+    let mut query_ids = Vec::new();
+    for _ in 0..2 {
+        let target = NodeId::random();
+        let found = nodes[0].find_node(target).await.unwrap();
+        loop {
+            match events.recv().await {
+                Some(Discv5Event::FindNodeResult {
+                    key,
+                    closer_peers,
+                    query_id,
+                }) => {
+                    assert_eq!(key, target);
+                    assert_eq!(
+                        closer_peers,
+                        found.iter().map(|enr| enr.node_id()).collect::<Vec<_>>()
+                    );
+                    assert!(closer_peers.contains(&remote_enr.node_id()));
+                    query_ids.push(query_id);
+                    break;
+                }
+                Some(_) => {}
+                None => panic!("Event stream closed"),
+            }
+        }
+    }
+    assert_ne!(query_ids[0], query_ids[1]);
+}
+
+#[tokio::test]
+async fn test_enr_insert_updates_peers() {
+    init();
+    let mut nodes = build_nodes(2, 14700);
+    let local_id = nodes[0].local_enr().node_id();
+    let remote_enr = nodes[1].local_enr();
+    nodes[0].add_enr(remote_enr).unwrap();
+    // establish a session, such that the nodes are connected
+    nodes[0].find_node(NodeId::random()).await.unwrap();
+
+    let seq = nodes[0].local_enr().seq();
+    nodes[0].enr_insert("test", vec![1]).unwrap();
+    assert_eq!(nodes[0].local_enr().seq(), seq + 1);
+
+    // the remote node requests the updated ENR once it is pinged
+    let updated = async {
+        loop {
+            if let Some(enr) = nodes[1].find_enr(&local_id) {
+                if enr.get("test").is_some() {
+                    return;
+                }
+            }
+            tokio::time::delay_for(std::time::Duration::from_millis(50)).await;
+        }
+    };
+    tokio::time::timeout(std::time::Duration::from_secs(2), updated)
+        .await
+        .expect("Peer should learn of the updated ENR");
+}
+
+#[tokio::test]
+async fn test_set_tcp_socket() {
+    init();
+    let mut nodes = build_nodes(2, 19600);
+    let local_id = nodes[0].local_enr().node_id();
+    let remote_enr = nodes[1].local_enr();
+    nodes[0].add_enr(remote_enr).unwrap();
+    // establish a session, such that the nodes are connected
+    nodes[0].find_node(NodeId::random()).await.unwrap();
+
+    let tcp_socket: SocketAddr = "127.0.0.1:19610".parse().unwrap();
+    assert!(nodes[0].set_tcp_socket(tcp_socket).await.unwrap());
+    assert_eq!(nodes[0].local_enr().tcp_socket(), Some(tcp_socket));
+    // setting the same socket leaves the ENR unchanged
+    let seq = nodes[0].local_enr().seq();
+    assert!(!nodes[0].set_tcp_socket(tcp_socket).await.unwrap());
+    assert_eq!(nodes[0].local_enr().seq(), seq);
+
+    // the remote node requests the updated ENR once it is pinged
+    let updated = async {
+        loop {
+            if let Some(enr) = nodes[1].find_enr(&local_id) {
+                if enr.tcp_socket() == Some(tcp_socket) {
+                    return;
+                }
+            }
+            tokio::time::delay_for(std::time::Duration::from_millis(50)).await;
+        }
+    };
+    tokio::time::timeout(std::time::Duration::from_secs(2), updated)
+        .await
+        .expect("Peer should learn of the updated ENR");
+
+    // a mismatched IP is rejected, while an unspecified IP only sets the port
+    let other_ip: SocketAddr = "10.0.0.1:19611".parse().unwrap();
+    assert!(!nodes[0].set_tcp_socket(other_ip).await.unwrap());
+    assert_eq!(nodes[0].local_enr().seq(), seq);
+    let unspecified: SocketAddr = "0.0.0.0:19610".parse().unwrap();
+    assert!(!nodes[0].set_tcp_socket(unspecified).await.unwrap());
+    let unspecified: SocketAddr = "0.0.0.0:19611".parse().unwrap();
+    assert!(nodes[0].set_tcp_socket(unspecified).await.unwrap());
+    let tcp_socket: SocketAddr = "127.0.0.1:19611".parse().unwrap();
+    assert_eq!(nodes[0].local_enr().tcp_socket(), Some(tcp_socket));
+    assert_eq!(
+        nodes[0].local_enr().udp_socket(),
+        Some("127.0.0.1:19600".parse().unwrap())
+    );
+}
+
+#[tokio::test]
+async fn test_enr_updated_event() {
+    init();
+    let mut node = build_nodes(1, 17200).remove(0);
+    let mut events = node.event_stream().await.unwrap();
+
+    node.enr_insert("test", vec![1]).unwrap();
+    let enr = tokio::time::timeout(std::time::Duration::from_secs(1), async {
+        loop {
+            if let Some(Discv5Event::EnrUpdated { enr }) = events.recv().await {
+                break enr;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(enr, node.local_enr());
+    assert_eq!(enr.get("test"), Some(&vec![1]));
+}
+
+#[tokio::test]
+async fn test_enr_updated_event_coalesced() {
+    init();
+    let mut node = build_nodes(1, 17210).remove(0);
+    let mut events = node.event_stream().await.unwrap();
+
+    // fill the event stream beyond its capacity without reading from it
+    for i in 0..40u8 {
+        node.enr_insert("test", vec![i]).unwrap();
+        tokio::time::delay_for(std::time::Duration::from_millis(5)).await;
+    }
+
+    // the latest update is delivered once the stream has capacity
+    let enr = tokio::time::timeout(std::time::Duration::from_secs(1), async {
+        loop {
+            if let Some(Discv5Event::EnrUpdated { enr }) = events.recv().await {
+                if enr.get("test") == Some(&vec![39]) {
+                    break enr;
+                }
+            }
+        }
+    })
+    .await
+    .expect("The latest ENR update should be delivered");
+    assert_eq!(enr, node.local_enr());
+}
+
+#[tokio::test]
+async fn test_response_latency_event() {
+    init();
+    let mut nodes = build_nodes(2, 17600);
+    let remote_enr = nodes[1].local_enr();
+    let mut events = nodes[0].event_stream().await.unwrap();
+
+    let start = std::time::Instant::now();
+    nodes[0]
+        .send_ping(remote_enr.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    let elapsed = start.elapsed();
+    let (node_id, rtt) = tokio::time::timeout(std::time::Duration::from_secs(1), async {
+        loop {
+            if let Some(Discv5Event::ResponseLatency { node_id, rtt }) = events.recv().await {
+                break (node_id, rtt);
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(node_id, remote_enr.node_id());
+    assert!(rtt <= elapsed);
+}
+
+#[tokio::test]
+async fn test_ping_node() {
+    init();
+    let mut nodes = build_nodes(2, 17800);
+    let remote_enr = nodes[1].local_enr();
+
+    // the node is not in the routing table
+    assert_eq!(
+        nodes[0].ping_node(&remote_enr.node_id()).await,
+        Err(RequestError::UnknownNode)
+    );
+
+    nodes[0].add_enr(remote_enr.clone()).unwrap();
+    assert_eq!(nodes[0].ping_node(&remote_enr.node_id()).await, Ok(()));
+
+    // the node stops responding
+    drop(nodes.remove(1));
+    assert_eq!(
+        nodes[0].ping_node(&remote_enr.node_id()).await,
+        Err(RequestError::Timeout)
+    );
+}
+
+#[tokio::test]
+async fn test_session_expired_reestablished() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(17900)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new()
+        .session_timeout(std::time::Duration::from_secs(1))
+        .reestablish_expired_sessions()
+        .build();
+    let socket_addr = enr.udp_socket().unwrap();
+    let mut local_node = Discv5::new(enr, enr_key, config).unwrap();
+    local_node.start(socket_addr);
+    let remote_node = build_nodes(1, 17901).remove(0);
+    let remote_enr = remote_node.local_enr();
+
+    local_node.add_enr(remote_enr.clone()).unwrap();
+    local_node
+        .send_ping(remote_enr.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    let mut events = local_node.event_stream().await.unwrap();
+
+    // the session expires and is established again
+    let mut expired = false;
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            match events.recv().await {
+                Some(Discv5Event::SessionExpired(node_id)) => {
+                    assert_eq!(node_id, remote_enr.node_id());
+                    expired = true;
+                }
+                Some(Discv5Event::SessionEstablished(enr, _)) if expired => {
+                    assert_eq!(enr.node_id(), remote_enr.node_id());
+                    break;
+                }
+                _ => {}
+            }
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_topic_query() {
+    init();
+    let mut nodes = build_nodes(2, 18000);
+    let remote_enr = nodes[1].local_enr();
+    let topic = [7; 32];
+    let advertiser = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(18002)
+        .build(&CombinedKey::generate_secp256k1())
+        .unwrap();
+    assert!(nodes[1].add_topic_ad(topic, advertiser.clone()));
+
+    nodes[0].add_enr(remote_enr).unwrap();
+    assert_eq!(nodes[0].topic_query(topic).await.unwrap(), vec![advertiser]);
+    assert!(nodes[0].topic_query([8; 32]).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_register_topic() {
+    init();
+    let build_node = |port, config| {
+        let enr_key = CombinedKey::generate_secp256k1();
+        let enr = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(port)
+            .build(&enr_key)
+            .unwrap();
+        let socket_addr = enr.udp_socket().unwrap();
+        let mut node = Discv5::new(enr, enr_key, config).unwrap();
+        node.start(socket_addr);
+        node
+    };
+    let mut local_node = build_node(18100, Discv5ConfigBuilder::new().build());
+    let remote_node = build_node(
+        18101,
+        Discv5ConfigBuilder::new()
+            .ticket_wait_time(std::time::Duration::from_secs(1))
+            .build(),
+    );
+    let remote_enr = remote_node.local_enr();
+    let topic = [7; 32];
+
+    // the remote issues a ticket, and confirms the registration once it is re-submitted
+    local_node.add_enr(remote_enr.clone()).unwrap();
+    assert!(local_node.topic_query(topic).await.unwrap().is_empty());
+    assert_eq!(
+        local_node.register_topic(topic).await.unwrap(),
+        vec![remote_enr]
+    );
+    assert_eq!(
+        local_node.topic_query(topic).await.unwrap(),
+        vec![local_node.local_enr()]
+    );
+}
+
+#[tokio::test]
+async fn test_observed_address_from() {
+    init();
+    let mut nodes = build_nodes(2, 18200);
+    let local_enr = nodes[0].local_enr();
+    let remote_enr = nodes[1].local_enr();
+
+    assert_eq!(
+        nodes[0].observed_address_from(remote_enr).await.unwrap(),
+        local_enr.udp_socket()
+    );
+    assert_eq!(
+        nodes[0].observed_address_from(local_enr).await.unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_enr_refresh_min_gap() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(18300)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new().enr_refresh_min_gap(2).build();
+    let socket_addr = enr.udp_socket().unwrap();
+    let mut local_node = Discv5::new(enr, enr_key, config).unwrap();
+    local_node.start(socket_addr);
+    let mut remote_node = build_nodes(1, 18301).remove(0);
+    let remote_id = remote_node.local_enr().node_id();
+    let seq = remote_node.local_enr().seq();
+    local_node.add_enr(remote_node.local_enr()).unwrap();
+
+    let ping_and_get_seq = |local_node: &mut Discv5, remote_enr| {
+        let pong = local_node.send_ping(remote_enr);
+        let known_enr = local_node.local_enr_of(&remote_id);
+        async move {
+            pong.await.unwrap().unwrap();
+            tokio::time::delay_for(std::time::Duration::from_millis(200)).await;
+            known_enr.await.unwrap().seq()
+        }
+    };
+
+    // establish a session, such that updated ENRs are not exchanged in a handshake
+    let seq_known = ping_and_get_seq(&mut local_node, remote_node.local_enr()).await;
+    assert_eq!(seq_known, seq);
+
+    // the sequence number must increase by at least the gap
+    assert!(update_enr(&mut remote_node, "a", &[1]));
+    let seq_known = ping_and_get_seq(&mut local_node, remote_node.local_enr()).await;
+    assert_eq!(seq_known, seq);
+    assert!(update_enr(&mut remote_node, "a", &[2]));
+    let seq_known = ping_and_get_seq(&mut local_node, remote_node.local_enr()).await;
+    assert_eq!(seq_known, seq + 2);
+
+    // the ENR is not requested again shortly after
+    assert!(update_enr(&mut remote_node, "a", &[3]));
+    assert!(update_enr(&mut remote_node, "a", &[4]));
+    let seq_known = ping_and_get_seq(&mut local_node, remote_node.local_enr()).await;
+    assert_eq!(seq_known, seq + 2);
+}
+
+#[tokio::test]
+async fn test_cancel_query() {
+    init();
+    let mut node = build_nodes(1, 18400).remove(0);
+    // the peer never responds, keeping the query active
+    let peer_enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(18401)
+        .build(&CombinedKey::generate_secp256k1())
+        .unwrap();
+    node.add_enr(peer_enr).unwrap();
+    assert!(node.active_query_ids().await.is_empty());
+
+    let query = tokio::spawn(node.find_node(NodeId::random()));
+    tokio::time::delay_for(std::time::Duration::from_millis(100)).await;
+    let query_ids = node.active_query_ids().await;
+    assert_eq!(query_ids.len(), 1);
+
+    assert!(node.cancel_query(query_ids[0]).await);
+    assert_eq!(query.await.unwrap(), Err(QueryError::Cancelled));
+    assert!(node.active_query_ids().await.is_empty());
+    assert!(!node.cancel_query(query_ids[0]).await);
+}
+
+#[tokio::test]
+async fn test_bootstrap_from() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(18500)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new()
+        .request_timeout(std::time::Duration::from_millis(300))
+        .request_retries(0)
+        .build();
+    let socket_addr = enr.udp_socket().unwrap();
+    let mut local_node = Discv5::new(enr, enr_key, config).unwrap();
+    local_node.start(socket_addr);
+    let remote_nodes = build_nodes(2, 18501);
+
+    let mut bootnodes: Vec<Enr<CombinedKey>> =
+        remote_nodes.iter().map(|node| node.local_enr()).collect();
+    // an unreachable bootnode, and one that cannot be added
+    bootnodes.push(
+        EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(18503)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap(),
+    );
+    bootnodes.push(
+        EnrBuilder::new("v4")
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap(),
+    );
+
+    assert_eq!(local_node.bootstrap_from(bootnodes).await, 2);
+    assert_eq!(local_node.table_entries_id().len(), 3);
+}
+
+#[tokio::test]
+async fn test_send_ping() {
+    init();
+    let nodes = build_nodes(2, 14800);
+    let local_enr = nodes[0].local_enr();
+    let remote_enr = nodes[1].local_enr();
+
+    let pong = nodes[0]
+        .send_ping(remote_enr.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(pong.enr_seq, remote_enr.seq());
+    assert_eq!(Some(pong.ip), local_enr.ip().map(IpAddr::from));
+    assert_eq!(Some(pong.port), local_enr.udp());
+
+    assert_eq!(
+        nodes[0].send_ping(local_enr).await.unwrap(),
+        Err(RequestError::SelfRequest)
+    );
+}
+
+#[tokio::test]
+async fn test_ip_vote_tally() {
+    init();
+    let nodes = build_nodes(2, 14900);
+    let local_enr = nodes[0].local_enr();
+    assert!(nodes[0].ip_vote_tally().await.is_empty());
+
+    nodes[0]
+        .send_ping(nodes[1].local_enr())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        nodes[0].ip_vote_tally().await,
+        vec![(local_enr.udp_socket().unwrap(), 1)]
+    );
+}
+
+#[tokio::test]
+async fn test_find_node_ids() {
+    init();
+    let mut nodes = build_nodes(3, 15000);
+    let bootnode_enr = nodes[1].local_enr();
+    let peer_enr = nodes[2].local_enr();
+    nodes[1].add_enr(peer_enr).unwrap();
+    nodes[0].add_enr(bootnode_enr).unwrap();
+
+    let target = NodeId::random();
+    let found_ids = nodes[0].find_node_ids(target).await.unwrap();
+    assert!(!found_ids.is_empty());
+    // the node ids match the ENRs returned by the same query
+    let found_enrs = nodes[0].find_node(target).await.unwrap();
+    assert_eq!(
+        found_ids
+            .into_iter()
+            .collect::<std::collections::HashSet<_>>(),
+        found_enrs
+            .iter()
+            .map(|enr| enr.node_id())
+            .collect::<std::collections::HashSet<_>>()
+    );
+}
+
+#[tokio::test]
+async fn test_shutdown_completes() {
+    init();
+    let mut nodes = build_nodes(2, 15100);
+    let enr = nodes[1].local_enr();
+    nodes[0].add_enr(enr.clone()).unwrap();
+    nodes[0].send_ping(enr).await.unwrap().unwrap();
+
+    // the shutdown waits for the service and handler to terminate
+    tokio::time::timeout(std::time::Duration::from_secs(1), nodes[0].shutdown())
+        .await
+        .expect("Shutdown should complete");
+    assert!(nodes[0].sessions_info().await.is_err());
+    // shutting down again completes immediately
+    tokio::time::timeout(std::time::Duration::from_secs(1), nodes[0].shutdown())
+        .await
+        .expect("Shutdown should complete");
+}
+
+#[tokio::test]
+async fn test_connected_peers_info() {
+    init();
+    let mut nodes = build_nodes(2, 15200);
+    let enr = nodes[1].local_enr();
+    nodes[0].add_enr(enr.clone()).unwrap();
+    assert!(nodes[0].connected_peers_info().await.unwrap().is_empty());
+
+    nodes[0].send_ping(enr.clone()).await.unwrap().unwrap();
+    let peers = nodes[0].connected_peers_info().await.unwrap();
+    assert_eq!(peers.len(), 1);
+    let (peer, status, next_ping) = &peers[0];
+    assert_eq!(peer.node_id(), enr.node_id());
+    assert_eq!(*status, NodeStatus::Connected);
+    assert!(*next_ping <= std::time::Instant::now() + Discv5Config::default().ping_interval);
+}
+
+#[tokio::test]
+async fn test_ping_backoff() {
+    init();
+    let ping_interval = std::time::Duration::from_millis(300);
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(16800)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new()
+        .ping_interval(ping_interval)
+        .ping_failure_limit(3)
+        .request_timeout(std::time::Duration::from_millis(100))
+        .request_retries(0)
+        .build();
+    let socket_addr = enr.udp_socket().unwrap();
+    let mut local_node = Discv5::new(enr, enr_key, config).unwrap();
+    local_node.start(socket_addr);
+    let mut events = local_node.event_stream().await.unwrap();
+
+    let mut remote_node = build_nodes(1, 16801).remove(0);
+    let remote_enr = remote_node.local_enr();
+    local_node.add_enr(remote_enr.clone()).unwrap();
+    local_node
+        .send_ping(remote_enr.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(local_node.connected_peers(), 1);
+
+    // the peer is backed off for 1 and then 3 heartbeats before its third failure disconnects it
+    remote_node.shutdown().await;
+    let stopped = std::time::Instant::now();
+    while let Some(event) = events.recv().await {
+        if let Discv5Event::NodeRemoved { node_id, reason } = event {
+            assert_eq!(node_id, remote_enr.node_id());
+            assert_eq!(reason, RemovalReason::RequestFailed);
+            break;
+        }
+    }
+    assert!(stopped.elapsed() >= ping_interval * 5);
+    assert_eq!(local_node.connected_peers(), 0);
+}
+
+#[tokio::test]
+async fn test_oversized_enrs_rejected() {
+    init();
+    // the remote is banned along with its IP, which is not used by any other test
+    let ip: IpAddr = "127.0.0.26".parse().unwrap();
+    let build_node = |port, config| {
+        let enr_key = CombinedKey::generate_secp256k1();
+        let enr = EnrBuilder::new("v4")
+            .ip(ip)
+            .udp(port)
+            .build(&enr_key)
+            .unwrap();
+        let socket_addr = enr.udp_socket().unwrap();
+        let mut node = Discv5::new(enr, enr_key, config).unwrap();
+        node.start(socket_addr);
+        node
+    };
+    let config = Discv5ConfigBuilder::new()
+        .max_enr_size(200)
+        .ban_oversized_enrs()
+        .build();
+    let mut node = build_node(15300, config);
+    let mut remote = build_node(15301, Discv5Config::default());
+
+    // the remote knows of an ENR padded beyond our limit, and one within it, in the same bucket
+    let remote_key: kbucket::Key<NodeId> = remote.local_enr().node_id().into();
+    let build_enr = |port, pad: usize| loop {
+        let enr = EnrBuilder::new("v4")
+            .ip(ip)
+            .udp(port)
+            .add_value("pad".into(), vec![0; pad])
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+        if remote_key.log2_distance(&enr.node_id().into()) == Some(256) {
+            return enr;
+        }
+    };
+    let oversized_enr = build_enr(15302, 100);
+    let enr = build_enr(15303, 1);
+    assert!(oversized_enr.encode().len() > 200 && enr.encode().len() <= 200);
+    remote.add_enr(oversized_enr.clone()).unwrap();
+    remote.add_enr(enr.clone()).unwrap();
+    node.add_enr(remote.local_enr()).unwrap();
+
+    // the remote is banned while its response is processed, before the other ENR is discovered
+    let mut events = node.event_stream().await.unwrap();
+    let query = node.find_node_distances(NodeId::random(), vec![256]);
+    tokio::spawn(query);
+    let mut banned = false;
+    loop {
+        match events.recv().await {
+            Some(Discv5Event::Discovered(discovered)) => {
+                assert_ne!(discovered.node_id(), oversized_enr.node_id());
+                if discovered.node_id() == enr.node_id() {
+                    break;
+                }
+            }
+            Some(Discv5Event::NodeRemoved { node_id, reason }) => {
+                assert_eq!(node_id, remote.local_enr().node_id());
+                assert_eq!(reason, RemovalReason::Banned);
+                banned = true;
+            }
+            Some(_) => {}
+            None => panic!("Event stream closed before the ENR was discovered"),
+        }
+    }
+    assert!(banned);
+}
+
+#[tokio::test]
+async fn test_session_filter() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(15400)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new()
+        .session_filter(|enr, _| enr.tcp().is_some())
+        .build();
+    let mut node = Discv5::new(enr, enr_key, config).unwrap();
+    node.start("127.0.0.1:15400".parse().unwrap());
+    let remote = build_nodes(1, 15401).remove(0);
+    let remote_enr = remote.local_enr();
+    node.add_enr(remote_enr.clone()).unwrap();
+
+    // the session is established, but dropped as the remote has no tcp port
+    assert_eq!(
+        node.send_ping(remote_enr).await.unwrap(),
+        Err(RequestError::SessionRejected)
+    );
+    assert!(node.sessions_info().await.unwrap().is_empty());
+    assert!(node.connected_peers_info().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_request_metrics() {
+    init();
+    let nodes = build_nodes(2, 15500);
+    let before = nodes[0].metrics();
+    nodes[0]
+        .send_ping(nodes[1].local_enr())
+        .await
+        .unwrap()
+        .unwrap();
+    let after = nodes[0].metrics();
+
+    // the metrics are shared by all nodes, so only increases can be checked
+    let count = |counts: &HashMap<&'static str, usize>, name| *counts.get(name).unwrap_or(&0);
+    assert!(after.requests_sent > before.requests_sent);
+    assert!(after.responses_received > before.responses_received);
+    assert!(
+        count(&after.requests_sent_per_type, "PING")
+            > count(&before.requests_sent_per_type, "PING")
+    );
+    assert!(
+        count(&after.responses_received_per_type, "PONG")
+            > count(&before.responses_received_per_type, "PONG")
+    );
+    assert!(after.bytes_sent > before.bytes_sent);
+    assert!(after.bytes_received > before.bytes_received);
+}
+
+#[tokio::test]
+async fn test_packet_decode_failed() {
+    init();
+    let mut node = build_nodes(1, 17000).remove(0);
+    let mut events = node.event_stream().await.unwrap();
+    let before = node.metrics();
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:17001").unwrap();
+    socket
+        .send_to(&[1u8; 40], node.local_enr().udp_socket().unwrap())
+        .unwrap();
+
+    let source = tokio::time::timeout(std::time::Duration::from_secs(1), async {
+        loop {
+            if let Some(Discv5Event::PacketDecodeFailed { source }) = events.recv().await {
+                break source;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(source, socket.local_addr().unwrap());
+    assert!(node.metrics().dropped_packets_decode_failed > before.dropped_packets_decode_failed);
+}
+
+#[tokio::test]
+async fn test_handshake_failed() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(18600)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new()
+        .request_timeout(std::time::Duration::from_millis(300))
+        .request_retries(0)
+        .build();
+    let socket_addr = enr.udp_socket().unwrap();
+    let mut node = Discv5::new(enr, enr_key, config).unwrap();
+    node.start(socket_addr);
+    let mut events = node.event_stream().await.unwrap();
+
+    // the peer never answers the handshake
+    let peer_enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(18601)
+        .build(&CombinedKey::generate_secp256k1())
+        .unwrap();
+    assert_eq!(
+        node.send_ping(peer_enr.clone()).await.unwrap(),
+        Err(RequestError::Timeout)
+    );
+
+    let (node_address, error) = tokio::time::timeout(std::time::Duration::from_secs(1), async {
+        loop {
+            if let Some(Discv5Event::HandshakeFailed {
+                node_address,
+                error,
+            }) = events.recv().await
+            {
+                break (node_address, error);
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(node_address.node_id, peer_enr.node_id());
+    assert_eq!(error, HandshakeError::Timeout);
+}
+
+#[tokio::test]
+async fn test_connection_state_changed_event() {
+    init();
+    let mut nodes = build_nodes(2, 18700);
+    let remote_enr = nodes[1].local_enr();
+    let mut events = nodes[0].event_stream().await.unwrap();
+
+    // the node is added as disconnected, and connected once a session is established
+    nodes[0].add_enr(remote_enr.clone()).unwrap();
+    nodes[0]
+        .send_ping(remote_enr.clone())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let (node_id, old, new) = tokio::time::timeout(std::time::Duration::from_secs(1), async {
+        loop {
+            if let Some(Discv5Event::ConnectionStateChanged { node_id, old, new }) =
+                events.recv().await
+            {
+                break (node_id, old, new);
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(node_id, remote_enr.node_id());
+    assert_eq!(old, NodeStatus::Disconnected);
+    assert_eq!(new, NodeStatus::Connected);
+}
+
+#[tokio::test]
+async fn test_stale_node_removed() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(18900)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new()
+        .ping_interval(std::time::Duration::from_millis(200))
+        .disconnected_node_timeout(std::time::Duration::from_millis(300))
+        .build();
+    let socket_addr = enr.udp_socket().unwrap();
+    let mut node = Discv5::new(enr, enr_key, config).unwrap();
+    node.start(socket_addr);
+    let mut events = node.event_stream().await.unwrap();
+
+    // the node is added as disconnected and never contacted
+    let peer_enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(18901)
+        .build(&CombinedKey::generate_secp256k1())
+        .unwrap();
+    node.add_enr(peer_enr.clone()).unwrap();
+
+    let node_id = tokio::time::timeout(std::time::Duration::from_secs(2), async {
+        loop {
+            if let Some(Discv5Event::NodeRemoved {
+                node_id,
+                reason: RemovalReason::Stale,
+            }) = events.recv().await
+            {
+                break node_id;
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(node_id, peer_enr.node_id());
+    assert!(node.table_entries_id().is_empty());
+}
+
+#[tokio::test]
+async fn test_find_enr_max_age() {
+    use crate::service::ServiceRequest;
+    use tokio::sync::oneshot;
+
+    init();
+    let mut nodes = build_nodes(2, 19000);
+    let remote_enr = nodes[1].local_enr();
+    // establishing a session confirms the remote's ENR
+    nodes[0]
+        .send_ping(remote_enr.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    nodes[1].shutdown().await;
+
+    let mut channel = nodes[0].clone_channel().unwrap();
+    let mut find_enr = |max_age| {
+        let (callback_send, callback_recv) = oneshot::channel();
+        let request =
+            ServiceRequest::FindEnr(remote_enr.clone().into(), Some(max_age), callback_send);
+        assert!(channel.try_send(request).is_ok());
+        callback_recv
+    };
+
+    // the stored ENR is returned without contacting the remote
+    let enr = find_enr(std::time::Duration::from_secs(60)).await.unwrap();
+    assert_eq!(enr, Some(remote_enr.clone()));
+
+    // an older confirmation requires a request, which the remote no longer answers
+    tokio::time::delay_for(std::time::Duration::from_millis(100)).await;
+    let enr = find_enr(std::time::Duration::from_millis(50))
+        .await
+        .unwrap();
+    assert_eq!(enr, None);
+}
+
+#[tokio::test]
+async fn test_request_enr_raw() {
+    init();
+    let mut nodes = build_nodes(2, 19400);
+    let remote_enr = nodes[1].local_enr();
+
+    let enr = nodes[0]
+        .request_enr_raw(remote_enr.udp_socket().unwrap(), remote_enr.public_key())
+        .await
+        .unwrap();
+    assert_eq!(enr, Some(remote_enr));
+
+    // a node that isn't listening doesn't return an ENR
+    let unknown_key = CombinedKey::generate_secp256k1();
+    let enr = nodes[0]
+        .request_enr_raw("127.0.0.1:19402".parse().unwrap(), unknown_key.public())
+        .await
+        .unwrap();
+    assert_eq!(enr, None);
+}
+
+#[tokio::test]
+async fn test_pending_eviction() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(19500)
+        .build(&enr_key)
+        .unwrap();
+    let local_key = kbucket::Key::from(enr.node_id());
+    let config = Discv5ConfigBuilder::new()
+        .pending_eviction_timeout(std::time::Duration::from_millis(500))
+        .build();
+    let mut node = Discv5::new(enr.clone(), enr_key, config).unwrap();
+    node.start("127.0.0.1:19500".parse().unwrap());
+    let mut events = node.event_stream().await.unwrap();
+
+    // fill the furthest bucket with disconnected nodes
+    let mut port = 19510;
+    while node.table_entries_id().len() < kbucket::MAX_NODES_PER_BUCKET {
+        let enr = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(port)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+        if local_key.log2_distance(&kbucket::Key::from(enr.node_id())) == Some(256) {
+            node.add_enr(enr).unwrap();
+            port += 1;
+        }
+    }
+
+    // a remote in the same bucket connects to the node
+    let remote_key = loop {
+        let key = CombinedKey::generate_secp256k1();
+        let node_id = EnrBuilder::new("v4").build(&key).unwrap().node_id();
+        if local_key.log2_distance(&kbucket::Key::from(node_id)) == Some(256) {
+            break key;
+        }
+    };
+    let remote_enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(19501)
+        .build(&remote_key)
+        .unwrap();
+    let mut remote = Discv5::new(remote_enr.clone(), remote_key, Discv5Config::default()).unwrap();
+    remote.start("127.0.0.1:19501".parse().unwrap());
+    remote.send_ping(enr).await.unwrap().unwrap();
+
+    // the remote is inserted once the disconnected node it is pending on fails to respond
+    let mut pending_on = None;
+    let replaced = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while let Some(event) = events.recv().await {
+            match event {
+                Discv5Event::NodePending {
+                    inserting,
+                    pending_on: node_id,
+                } if inserting == remote_enr.node_id() => pending_on = Some(node_id),
+                Discv5Event::NodeInserted { node_id, replaced }
+                    if node_id == remote_enr.node_id() =>
+                {
+                    return replaced;
+                }
+                _ => {}
+            }
+        }
+        None
+    })
+    .await
+    .expect("The pending node should be inserted");
+    assert!(pending_on.is_some());
+    assert_eq!(replaced, pending_on);
+    assert!(node.table_entries_id().contains(&remote_enr.node_id()));
+}
+
+#[tokio::test]
+async fn test_peer_score() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(19100)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new()
+        .request_timeout(std::time::Duration::from_millis(300))
+        .request_retries(0)
+        .build();
+    let socket_addr = enr.udp_socket().unwrap();
+    let mut node = Discv5::new(enr, enr_key, config).unwrap();
+    node.start(socket_addr);
+
+    let remote_node = build_nodes(1, 19101).remove(0);
+    let remote_enr = remote_node.local_enr();
+    assert_eq!(node.peer_score(&remote_enr.node_id()), None);
+    node.send_ping(remote_enr.clone()).await.unwrap().unwrap();
+    assert!(node.peer_score(&remote_enr.node_id()).unwrap() > 0.0);
+
+    // the peer never responds
+    let silent_enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(19102)
+        .build(&CombinedKey::generate_secp256k1())
+        .unwrap();
+    assert_eq!(
+        node.send_ping(silent_enr.clone()).await.unwrap(),
+        Err(RequestError::Timeout)
+    );
+    assert!(node.peer_score(&silent_enr.node_id()).unwrap() < 0.0);
+}
+
+#[tokio::test]
+async fn test_find_node_multiple_distances() {
+    init();
+    let mut nodes = build_nodes(2, 18800);
+    let remote_enr = nodes[1].local_enr();
+    let remote_key = kbucket::Key::from(remote_enr.node_id());
+
+    let mut peer_enrs = Vec::new();
+    for port in 18802..18806 {
+        let enr = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(port)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+        nodes[1].add_enr(enr.clone()).unwrap();
+        peer_enrs.push(enr);
+    }
+    // the distances of the peers, requested along with the remote's own ENR and duplicates
+    let mut distances: Vec<u64> = peer_enrs
+        .iter()
+        .map(|enr| {
+            remote_key
+                .log2_distance(&kbucket::Key::from(enr.node_id()))
+                .unwrap()
+        })
+        .collect();
+    distances.extend_from_slice(&[0, 0]);
+
+    let response = nodes[0]
+        .send_request(
+            remote_enr.clone().into(),
+            RequestBody::FindNode { distances },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    let mut found = match response.body {
+        ResponseBody::Nodes { nodes, .. } => nodes,
+        body => panic!("Unexpected response: {}", body),
+    };
+    peer_enrs.push(remote_enr);
+    let sort_key = |enr: &Enr<CombinedKey>| enr.node_id().raw();
+    found.sort_by_key(sort_key);
+    peer_enrs.sort_by_key(sort_key);
+    assert_eq!(found, peer_enrs);
+}
+
+#[tokio::test]
+async fn test_find_node_response_capped() {
+    init();
+    let mut nodes = build_nodes(2, 18850);
+    let remote_enr = nodes[1].local_enr();
+    for _ in 0..40 {
+        let enr = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(18852)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+        let _ = nodes[1].add_enr(enr);
+    }
+    assert!(nodes[1].table_entries_id().len() > kbucket::MAX_NODES_PER_BUCKET);
+
+    // a request for many distances is answered with at most a bucket's worth of nodes. The raw
+    // response is the first packet, which is filled with as many of the equally sized ENRs as fit.
+    let response = nodes[0]
+        .send_request(
+            remote_enr.clone().into(),
+            RequestBody::FindNode {
+                distances: (240..=256).rev().collect(),
+            },
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    match response.body {
+        ResponseBody::Nodes { total, nodes } => {
+            let per_packet = nodes.len();
+            let packets = kbucket::MAX_NODES_PER_BUCKET.div_ceil(per_packet);
+            assert_eq!(total, packets as u64);
+        }
+        body => panic!("Unexpected response: {}", body),
+    }
+}
+
+#[tokio::test]
+async fn test_socket_buffer_sizes() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(17100)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new()
+        .socket_recv_buffer_size(1 << 20)
+        .socket_send_buffer_size(1 << 20)
+        .build();
+    let socket_addr = enr.udp_socket().unwrap();
+    let mut node = Discv5::new(enr, enr_key, config).unwrap();
+    node.start(socket_addr);
+
+    let remote = build_nodes(1, 17101).remove(0);
+    node.send_ping(remote.local_enr()).await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_start_with_socket() {
+    init();
+    // bind to an OS-assigned port and advertise it in the ENR
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let socket_addr = socket.local_addr().unwrap();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip(socket_addr.ip())
+        .udp(socket_addr.port())
+        .build(&enr_key)
+        .unwrap();
+    let mut node = Discv5::new(enr, enr_key, Discv5Config::default()).unwrap();
+    node.start_with_socket(socket).unwrap();
+
+    let remote = build_nodes(1, 15600).remove(0);
+    let pong = remote.send_ping(node.local_enr()).await.unwrap().unwrap();
+    assert_eq!(pong.port, 15600);
+}
+
+#[tokio::test]
+async fn test_get_enr_not_filtered_by_distance() {
+    init();
+    let mut nodes = build_nodes(2, 15700);
+    let remote_enr = nodes[1].local_enr();
+    nodes[0].add_enr(remote_enr.clone()).unwrap();
+
+    // a distance of 0 requests the peer's own ENR, which is returned as the result
+    let found = nodes[0]
+        .find_node_distances(NodeId::random(), vec![0])
+        .await
+        .unwrap();
+    assert_eq!(found, vec![remote_enr]);
+}
+
+#[tokio::test]
+async fn test_table_filter_with_state() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    init();
+    let accept = Arc::new(AtomicBool::new(false));
+    let filter_accept = accept.clone();
+    let config = Discv5ConfigBuilder::new()
+        .table_filter(move |_| filter_accept.load(Ordering::Relaxed))
+        .build();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4").build(&enr_key).unwrap();
+    let mut node = Discv5::new(enr, enr_key, config).unwrap();
+
+    let remote_enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(9000)
+        .build(&CombinedKey::generate_secp256k1())
+        .unwrap();
+    assert!(node.add_enr(remote_enr.clone()).is_err());
+
+    // the filter's state can be changed without rebuilding the config
+    accept.store(true, Ordering::Relaxed);
+    assert!(node.add_enr(remote_enr).is_ok());
+}
+
+#[tokio::test]
+async fn test_query_progress_events() {
+    init();
+    let mut nodes = build_nodes(3, 15800);
+    let bootnode_enr = nodes[1].local_enr();
+    let peer_enr = nodes[2].local_enr();
+    nodes[1].add_enr(peer_enr).unwrap();
+    nodes[0].add_enr(bootnode_enr).unwrap();
+    let mut events = nodes[0].event_stream().await.unwrap();
+
+    nodes[0].find_node(NodeId::random()).await.unwrap();
+
+    // the peers respond to each of the query's requests, but progress is only reported once
+    let mut progress = Vec::new();
+    loop {
+        match events.recv().await {
+            Some(Discv5Event::QueryProgress {
+                query_id,
+                peers_contacted,
+                ..
+            }) => progress.push((query_id, peers_contacted)),
+            Some(Discv5Event::FindNodeResult { query_id, .. }) => {
+                assert_eq!(progress.len(), 1);
+                assert_eq!(progress[0].0, query_id);
+                assert!(progress[0].1 >= 1);
+                break;
+            }
+            Some(_) => {}
+            None => panic!("Event stream closed before the query finished"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_multiple_event_streams() {
+    init();
+    let mut nodes = build_nodes(2, 15900);
+    let remote_enr = nodes[1].local_enr();
+    let mut first = nodes[0].event_stream().await.unwrap();
+    let mut second = nodes[0].event_stream().await.unwrap();
+    // a dropped subscriber does not affect the others
+    drop(nodes[0].event_stream().await.unwrap());
+
+    nodes[0]
+        .send_ping(remote_enr.clone())
+        .await
+        .unwrap()
+        .unwrap();
+
+    for events in [&mut first, &mut second].iter_mut() {
+        loop {
+            match events.recv().await {
+                Some(Discv5Event::SessionEstablished(enr, _)) => {
+                    assert_eq!(enr, remote_enr);
+                    break;
+                }
+                Some(_) => {}
+                None => panic!("Event stream closed before the session was established"),
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_bootstrap_fills_table() {
+    init();
+    // the local node and the peers of the bootnode are all at the furthest distance from the
+    // bootnode, such that every query towards a target near the local node learns of the peers
+    let bootnode_key = CombinedKey::generate_secp256k1();
+    let bootnode_id = kbucket::Key::from(NodeId::from(bootnode_key.public()));
+    let mut keys = vec![bootnode_key];
+    while keys.len() < 5 {
+        let key = CombinedKey::generate_secp256k1();
+        let node_id = NodeId::from(key.public());
+        if bootnode_id.log2_distance(&node_id.into()) == Some(256) {
+            keys.push(key);
+        }
+    }
+    keys.swap(0, 1);
+    let mut nodes = build_nodes_from_keypairs(keys, 16100);
+    for i in 2..5 {
+        let enr = nodes[i].local_enr();
+        nodes[1].add_enr(enr).unwrap();
+    }
+    let bootnode = nodes[1].local_enr();
+    nodes[0].add_enr(bootnode).unwrap();
+
+    let mut events = nodes[0].event_stream().await.unwrap();
+    nodes[0].bootstrap().unwrap();
+    // a bootstrap in progress is not restarted
+    nodes[0].bootstrap().unwrap();
+
+    let mut last_completed = 0;
+    loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(30), events.recv()).await {
+            Ok(Some(Discv5Event::BootstrapProgress {
+                completed,
+                total,
+                table_size,
+            })) => {
+                assert_eq!(total, 256);
+                assert_eq!(completed, last_completed + 1);
+                last_completed = completed;
+                if completed == total {
+                    assert_eq!(table_size, 4);
+                    break;
+                }
+            }
+            Ok(Some(_)) => {}
+            _ => panic!("Bootstrap did not complete"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_session_evicted() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(16200)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new().session_cache_capacity(1).build();
+    let mut node = Discv5::new(enr, enr_key, config).unwrap();
+    node.start("127.0.0.1:16200".parse().unwrap());
+    let remotes = build_nodes(2, 16201);
+    assert_eq!(node.session_cache_stats().await.unwrap(), (0, 1));
+
+    let mut events = node.event_stream().await.unwrap();
+    for remote in remotes.iter() {
+        node.send_ping(remote.local_enr()).await.unwrap().unwrap();
+    }
+
+    // the first session is evicted to make room for the second
+    loop {
+        match tokio::time::timeout(std::time::Duration::from_secs(5), events.recv()).await {
+            Ok(Some(Discv5Event::SessionEvicted(node_id))) => {
+                assert_eq!(node_id, remotes[0].local_enr().node_id());
+                break;
+            }
+            Ok(Some(_)) => {}
+            _ => panic!("No session was evicted"),
+        }
+    }
+    assert_eq!(node.session_cache_stats().await.unwrap(), (1, 1));
+}
+
+#[tokio::test]
+async fn test_add_enr_outcome() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4").build(&enr_key).unwrap();
+    let mut node = Discv5::new(enr, enr_key, Discv5Config::default()).unwrap();
+    let build_enr = || {
+        EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(9000)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap()
+    };
+
+    let first = build_enr();
+    assert_eq!(
+        node.add_enr(first.clone()).unwrap(),
+        AddEnrOutcome::Inserted
+    );
+    assert_eq!(node.add_enr(first).unwrap(), AddEnrOutcome::Updated);
+
+    // fill the furthest bucket, which holds half of all node ids
+    let mut outcomes = Vec::new();
+    while !outcomes.contains(&AddEnrOutcome::Full) {
+        outcomes.push(node.add_enr(build_enr()).unwrap());
+    }
+    // disconnected nodes are not made pending
+    let (full, inserted) = outcomes.split_last().unwrap();
+    assert_eq!(*full, AddEnrOutcome::Full);
+    assert!(inserted
+        .iter()
+        .all(|outcome| *outcome == AddEnrOutcome::Inserted));
+}
+
+#[tokio::test]
+async fn test_require_contactable() {
+    init();
+    let build_node = |port, config| {
+        let enr_key = CombinedKey::generate_secp256k1();
+        let enr = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(port)
+            .build(&enr_key)
+            .unwrap();
+        let socket_addr = enr.udp_socket().unwrap();
+        let mut node = Discv5::new(enr, enr_key, config).unwrap();
+        node.start(socket_addr);
+        node
+    };
+    let mut default_node = build_node(17700, Discv5Config::default());
+    let mut strict_node = build_node(
+        17701,
+        Discv5ConfigBuilder::new()
+            .require_contactable()
+            .request_timeout(std::time::Duration::from_millis(300))
+            .build(),
+    );
+
+    // the remote node's ENR has no IP address
+    let remote_key = CombinedKey::generate_secp256k1();
+    let remote_enr = EnrBuilder::new("v4").build(&remote_key).unwrap();
+    assert!(remote_enr.udp_socket().is_none());
+    let config = Discv5ConfigBuilder::new()
+        .request_timeout(std::time::Duration::from_millis(300))
+        .request_retries(0)
+        .build();
+    let mut remote_node = Discv5::new(remote_enr.clone(), remote_key, config).unwrap();
+    remote_node.start("127.0.0.1:17702".parse().unwrap());
+
+    // the default node inserts the remote node and responds
+    remote_node
+        .send_ping(default_node.local_enr())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(default_node.table_entries_id(), vec![remote_enr.node_id()]);
+
+    // the strict node does not insert it
+    let _ = remote_node
+        .send_ping(strict_node.local_enr())
+        .await
+        .unwrap();
+    assert!(strict_node.table_entries_id().is_empty());
+}
+
+#[tokio::test]
+async fn test_add_enrs() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4").build(&enr_key).unwrap();
+    let local_key = kbucket::Key::from(enr.node_id());
+    let config = Discv5ConfigBuilder::new().ip_limit().build();
+    let mut node = Discv5::new(enr, enr_key, config).unwrap();
+
+    // three nodes of the same subnet in the furthest bucket, which only admits two of them
+    let mut enrs = Vec::new();
+    while enrs.len() < 3 {
+        let enr = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(9000)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap();
+        if local_key.log2_distance(&kbucket::Key::from(enr.node_id())) == Some(256) {
+            enrs.push(enr);
+        }
+    }
+    // an ENR without a UDP socket
+    enrs.insert(
+        1,
+        EnrBuilder::new("v4")
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap(),
+    );
+    enrs.push(enrs[0].clone());
+
+    let outcomes = node.add_enrs(enrs);
+    assert_eq!(outcomes.len(), 5);
+    assert_eq!(outcomes[0].as_ref().unwrap(), &AddEnrOutcome::Inserted);
+    assert!(outcomes[1].is_err());
+    assert_eq!(outcomes[2].as_ref().unwrap(), &AddEnrOutcome::Inserted);
+    assert!(outcomes[3].is_err());
+    assert_eq!(outcomes[4].as_ref().unwrap(), &AddEnrOutcome::Updated);
+    assert_eq!(node.table_entries_id().len(), 2);
+}
+
+#[tokio::test]
+async fn test_invalid_enr_signature_rejected() {
+    init();
+    let mut node = build_nodes(1, 16300).remove(0);
+
+    // the remote node is given its own IP, as its IP is banned
+    let remote_key = CombinedKey::generate_secp256k1();
+    let remote_enr = EnrBuilder::new("v4")
+        .ip("127.0.0.2".parse().unwrap())
+        .udp(16301)
+        .build(&remote_key)
+        .unwrap();
+    let mut remote = Discv5::new(remote_enr.clone(), remote_key, Discv5Config::default()).unwrap();
+    remote.start("127.0.0.2:16301".parse().unwrap());
+
+    // an ENR whose signature has been corrupted
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(16302)
+        .build(&CombinedKey::generate_secp256k1())
+        .unwrap();
+    let mut encoded = rlp::encode(&enr);
+    encoded[10] ^= 0xff;
+    let tampered = rlp::decode::<Enr<CombinedKey>>(&encoded).unwrap();
+    remote.add_enr(tampered.clone()).unwrap();
+
+    node.add_enr(remote_enr.clone()).unwrap();
+    let distance = kbucket::Key::from(remote_enr.node_id())
+        .log2_distance(&kbucket::Key::from(tampered.node_id()))
+        .unwrap();
+    let mut events = node.event_stream().await.unwrap();
+    let found = node
+        .find_node_distances(NodeId::random(), vec![distance])
+        .await
+        .unwrap();
+    assert!(!found.contains(&tampered));
+
+    // the remote is banned, which removes it from the routing table
+    loop {
+        match events.recv().await {
+            Some(Discv5Event::NodeRemoved { node_id, reason }) => {
+                assert_eq!(node_id, remote_enr.node_id());
+                assert_eq!(reason, RemovalReason::Banned);
+                break;
+            }
+            Some(Discv5Event::Discovered(enr)) => assert_ne!(enr, tampered),
+            Some(_) => {}
+            None => panic!("Event stream closed before the remote was banned"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_find_node_min_partial() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(16400)
+        .build(&enr_key)
+        .unwrap();
+    let config = Discv5ConfigBuilder::new()
+        .query_timeout(std::time::Duration::from_secs(2))
+        .build();
+    let mut node = Discv5::new(enr, enr_key, config).unwrap();
+    node.start("127.0.0.1:16400".parse().unwrap());
+    let remote = build_nodes(1, 16401).remove(0);
+    let remote_enr = remote.local_enr();
+    node.add_enr(remote_enr.clone()).unwrap();
+
+    // a query for the local node is answered from the routing table, which holds too few peers
+    let local_id = node.local_enr().node_id();
+    let result = node.find_node_min(local_id, 3).await;
+    assert_eq!(result, Err(QueryError::Partial(vec![remote_enr.clone()])));
+
+    // the only peer knows of no other peers, so the minimum cannot be met
+    let result = node.find_node_min(NodeId::random(), 3).await;
+    assert_eq!(result, Err(QueryError::Partial(vec![remote_enr.clone()])));
+
+    // without a responsive peer, no results are returned as partial results
+    assert!(node.remove_node(&remote_enr.node_id()));
+    let unreachable_enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(16402)
+        .build(&CombinedKey::generate_secp256k1())
+        .unwrap();
+    node.add_enr(unreachable_enr).unwrap();
+    let result = node.find_node_min(NodeId::random(), 3).await;
+    assert_eq!(result, Err(QueryError::Partial(Vec::new())));
+}
+
+#[tokio::test]
+async fn test_bootstrap_sources_resolved_in_background() {
+    init();
+    let enr_key = CombinedKey::generate_secp256k1();
+    let enr = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(20100)
+        .build(&enr_key)
+        .unwrap();
+    let bootnode = EnrBuilder::new("v4")
+        .ip("127.0.0.1".parse().unwrap())
+        .udp(20101)
+        .build(&CombinedKey::generate_secp256k1())
+        .unwrap();
+    // a DNS source whose resolver blocks before failing
+    let tree_key = secp256k1::SecretKey::random(&mut rand::thread_rng());
+    let url = format!(
+        "enrtree://{}@nodes.example.org",
+        data_encoding::BASE32_NOPAD
+            .encode(&secp256k1::PublicKey::from_secret_key(&tree_key).serialize_compressed())
+    );
+    let resolver: TxtResolver = Arc::new(|_: &str| {
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        Err("No such record".to_string())
+    });
+    let config = Discv5ConfigBuilder::new()
+        .bootstrap_sources(vec![
+            BootstrapSource::Dns { url, resolver },
+            BootstrapSource::Enrs(vec![bootnode.clone()]),
+        ])
+        .build();
+    let mut node = Discv5::new(enr, enr_key, config).unwrap();
+
+    // starting the server does not wait for the sources
+    let started = std::time::Instant::now();
+    node.start("127.0.0.1:20100".parse().unwrap());
+    assert!(started.elapsed() < std::time::Duration::from_millis(250));
+    assert_eq!(node.bootstrap_source_results(), None);
+
+    let results = tokio::time::timeout(std::time::Duration::from_secs(2), async {
+        loop {
+            if let Some(results) = node.bootstrap_source_results() {
+                break results;
+            }
+            tokio::time::delay_for(std::time::Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("The sources should be resolved");
+    assert!(results[0].is_err());
+    assert_eq!(results[1], Ok(1));
+    assert_eq!(node.table_entries_id(), vec![bootnode.node_id()]);
+}
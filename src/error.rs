@@ -1,3 +1,4 @@
+use crate::Enr;
 use rlp::DecoderError;
 
 #[derive(Debug)]
@@ -30,12 +31,48 @@ pub enum RequestError {
     InvalidRemotePacket,
     EncryptionFailed(String),
     InvalidMultiaddr(String),
+    /// The node is a bootnode that is currently not contacted as it has been unreachable.
+    CircuitOpen,
+    /// The session with the node was rejected by the `session_filter`.
+    SessionRejected,
+    /// The node is not in the routing table.
+    UnknownNode,
+    /// The node did not confirm the registration of a topic.
+    TopicNotRegistered,
+}
+
+/// The reason a session handshake with a node failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandshakeError {
+    /// The node's ENR is not known, and the node did not provide it in the handshake.
+    NoEnr,
+    /// The handshake could not be authenticated: its keys could not be derived, or it could not
+    /// be decrypted or verified.
+    DecryptionFailed,
+    /// The node's ENR does not match its node id or the socket it communicates from.
+    InvalidEnr,
+    /// The node did not complete the handshake before the request timed out.
+    Timeout,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum QueryError {
     ServiceNotStarted,
     ChannelFailed(String),
+    /// The routing table did not contain the configured minimum number of seed peers in time.
+    /// Holds the number of peers that were known.
+    InsufficientSeedPeers(usize),
+    /// The configured maximum number of concurrent queries are already active.
+    TooManyConcurrent,
+    /// The query timed out before any peers were found.
+    Timeout,
+    /// None of the peers contacted during the query responded.
+    Unreachable,
+    /// The query timed out before the requested minimum number of peers were found. Holds the
+    /// peers that were found.
+    Partial(Vec<Enr>),
+    /// The query was cancelled with `Discv5::cancel_query`.
+    Cancelled,
     InvalidEnr(String),
     EncryptionFailed(String),
     InvalidMultiaddr(String),
@@ -53,6 +90,12 @@ impl std::fmt::Display for RequestError {
     }
 }
 
+impl std::fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
 impl std::fmt::Display for QueryError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self)
@@ -23,7 +23,7 @@
 //! Requests to the handler can be made via the sending channel using a [`HandlerRequest`].
 //! Responses come by the receiving channel in the form of a [`HandlerResponse`].
 use crate::config::Discv5Config;
-use crate::error::{Discv5Error, RequestError};
+use crate::error::{Discv5Error, HandshakeError, RequestError};
 use crate::packet::{AuthHeader, AuthTag, Magic, Nonce, Packet, Tag, TAG_LENGTH};
 use crate::rpc::{Message, Request, RequestBody, RequestId, Response, ResponseBody};
 use crate::socket::Socket;
@@ -35,7 +35,13 @@ use lru_time_cache::LruCache;
 use parking_lot::RwLock;
 use sha2::{Digest, Sha256};
 use std::sync::Arc;
-use std::{collections::HashMap, default::Default, net::SocketAddr, sync::atomic::Ordering};
+use std::{
+    collections::HashMap,
+    default::Default,
+    net::SocketAddr,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
 use tokio::sync::{mpsc, oneshot};
 
 mod crypto;
@@ -50,8 +56,11 @@ use crate::metrics::METRICS;
 use hashmap_delay::HashMapDelay;
 use session::Session;
 
+/// The interval at which established sessions are checked for expiry.
+const SESSION_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Events sent to the handler to be executed.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug)]
 #[allow(clippy::large_enum_variant)]
 pub enum HandlerRequest {
     /// Sends a `Request` to a `NodeContact`. A `NodeContact` is an abstract type
@@ -64,7 +73,7 @@ pub enum HandlerRequest {
     ///
     /// A Request is flagged and permits responses through the packet filter.
     ///
-    /// Note: To update an ENR for an unknown node, we request a FINDNODE with distance 0 to the
+    /// Note: To update an ENR for an unknown node, we send a `RequestBody::GetEnr` request to the
     /// `NodeContact` we know of.
     Request(NodeContact, Box<Request>),
 
@@ -79,6 +88,28 @@ pub enum HandlerRequest {
     /// The `WhoAreYouRef` is sent out in the `HandlerResponse::WhoAreYou` event and should
     /// be returned here to submit the application's response.
     WhoAreYou(WhoAreYouRef, Option<Enr>),
+
+    /// Requests information about the currently established sessions.
+    SessionsInfo(oneshot::Sender<Vec<SessionInfo>>),
+
+    /// Requests the number of currently established sessions.
+    SessionCount(oneshot::Sender<usize>),
+
+    /// Drops the session with a node, failing any requests awaiting the session.
+    DropSession(NodeAddress),
+}
+
+/// Information about an established session with a peer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionInfo {
+    /// The `NodeId` of the peer.
+    pub node_id: NodeId,
+    /// The socket address of the peer the session is bound to.
+    pub remote_addr: SocketAddr,
+    /// When the current session keys were established.
+    pub established: Instant,
+    /// When the session expires if no further messages are exchanged with the peer.
+    pub expires: Instant,
 }
 
 /// The outputs provided by the `Handler`.
@@ -88,7 +119,9 @@ pub enum HandlerResponse {
     ///
     /// A session is only considered established once we have received a signed ENR from the
     /// node and received messages from it's `SocketAddr` matching it's ENR fields.
-    Established(Enr),
+    ///
+    /// The `SocketAddr` is the address the session is established with.
+    Established(Enr, SocketAddr),
 
     /// A Request has been received.
     Request(NodeAddress, Box<Request>),
@@ -104,6 +137,20 @@ pub enum HandlerResponse {
     ///
     /// This returns the request ID and an error indicating why the request failed.
     RequestFailed(RequestId, RequestError),
+
+    /// The least recently used session was evicted to make room for a new session, as the
+    /// session cache is full.
+    SessionEvicted(NodeId),
+
+    /// A session was removed as it has been inactive for longer than the session timeout.
+    SessionExpired(NodeAddress),
+
+    /// An inbound packet could not be decoded. These are rate-limited, so not every malformed
+    /// packet is reported.
+    PacketDecodeFailed(SocketAddr),
+
+    /// A session handshake with a node failed.
+    HandshakeFailed(NodeAddress, HandshakeError),
 }
 
 /// A reference for the application layer to send back when the handler requests any known
@@ -140,7 +187,7 @@ impl RequestCall {
             packet,
             request,
             handshake_sent: false,
-            retries: 1,
+            retries: 0,
             remaining_responses: None,
         }
     }
@@ -175,6 +222,13 @@ pub struct Handler {
     active_challenges: LruCache<NodeAddress, Challenge>,
     /// Established sessions with peers.
     sessions: LruCache<NodeAddress, Session>,
+    /// The duration an inactive session is kept for. Sessions are checked for expiry every
+    /// `SESSION_EXPIRY_CHECK_INTERVAL`.
+    session_timeout: Duration,
+    /// The maximum number of established sessions.
+    session_cache_capacity: usize,
+    /// The interval at which sessions are checked for expiry.
+    session_expiry_check: tokio::time::Interval,
     /// The channel that receives requests from the application layer.
     inbound_channel: mpsc::Receiver<HandlerRequest>,
     /// The channel to send responses to the application layer.
@@ -184,18 +238,22 @@ pub struct Handler {
     /// The discovery v5 UDP socket tasks.
     socket: Socket,
     /// Exit channel to shutdown the handler.
-    exit: oneshot::Receiver<()>,
+    exit: oneshot::Receiver<oneshot::Sender<()>>,
 }
 
 impl Handler {
     /// A new Session service which instantiates the UDP socket send/recv tasks.
+    ///
+    /// If `socket` is provided, this already bound socket is used rather than binding to
+    /// `listen_socket`.
     pub(crate) fn spawn(
         enr: Arc<RwLock<Enr>>,
         key: Arc<RwLock<CombinedKey>>,
         listen_socket: SocketAddr,
+        socket: Option<std::net::UdpSocket>,
         config: Discv5Config,
     ) -> (
-        oneshot::Sender<()>,
+        oneshot::Sender<oneshot::Sender<()>>,
         mpsc::Sender<HandlerRequest>,
         mpsc::Receiver<HandlerResponse>,
     ) {
@@ -227,9 +285,12 @@ impl Handler {
         let socket_config = socket::SocketConfig {
             executor: config.executor.clone().expect("Executor must exist"),
             socket_addr: listen_socket,
+            socket,
             filter_config,
             whoareyou_magic: magic,
             expected_responses: filter_expected_responses.clone(),
+            recv_buffer_size: config.socket_recv_buffer_size,
+            send_buffer_size: config.socket_send_buffer_size,
         };
 
         let node_id = enr.read().node_id();
@@ -250,10 +311,11 @@ impl Handler {
                     active_requests_auth: HashMap::new(),
                     pending_requests: HashMap::new(),
                     filter_expected_responses,
-                    sessions: LruCache::with_expiry_duration_and_capacity(
-                        config.session_timeout,
-                        config.session_cache_capacity,
-                    ),
+                    // sessions are expired by `expire_sessions`, such that expiries are reported
+                    sessions: LruCache::with_capacity(config.session_cache_capacity),
+                    session_timeout: config.session_timeout,
+                    session_cache_capacity: config.session_cache_capacity,
+                    session_expiry_check: tokio::time::interval(SESSION_EXPIRY_CHECK_INTERVAL),
                     active_challenges: LruCache::with_expiry_duration(config.request_timeout * 2),
                     inbound_channel,
                     outbound_channel,
@@ -283,15 +345,44 @@ impl Handler {
                         }
                         HandlerRequest::Response(dst, response) => self.send_response(dst, *response).await,
                         HandlerRequest::WhoAreYou(wru_ref, enr) => self.send_challenge(wru_ref, enr).await,
+                        HandlerRequest::SessionsInfo(callback) => {
+                            let _ = callback.send(self.sessions_info());
+                        }
+                        HandlerRequest::SessionCount(callback) => {
+                            let _ = callback.send(self.session_count());
+                        }
+                        HandlerRequest::DropSession(node_address) => {
+                            self.fail_session(&node_address, RequestError::SessionRejected).await;
+                        }
                     }
                 }
                 Some(inbound_packet) = self.socket.recv.next() => {
                     self.process_inbound_packet(inbound_packet).await;
                 }
+                Some(src) = self.socket.decode_failures.next() => {
+                    let _ = self
+                        .outbound_channel
+                        .send(HandlerResponse::PacketDecodeFailed(src))
+                        .await;
+                }
                 Some(Ok((node_address, pending_request))) = self.active_requests.next() => {
                     self.handle_request_timeout(node_address, pending_request).await;
                 }
-                _ = &mut self.exit => {
+                _ = self.session_expiry_check.next() => {
+                    self.expire_sessions().await;
+                }
+                exit = &mut self.exit => {
+                    // send any responses still queued by the application before shutting down
+                    while let Ok(request) = self.inbound_channel.try_recv() {
+                        if let HandlerRequest::Response(dst, response) = request {
+                            self.send_response(dst, *response).await;
+                        }
+                    }
+                    self.socket.shutdown().await;
+                    debug!("Handler shutdown");
+                    if let Ok(done) = exit {
+                        let _ = done.send(());
+                    }
                     return;
                 }
             }
@@ -366,7 +457,12 @@ impl Handler {
             trace!("Request timed out with {}", node_address);
             // Remove the request from the awaiting packet_filter
             self.remove_expected_response(node_address.socket_addr.clone());
+            let handshake_pending = self.sessions.peek(&node_address).is_none();
             self.fail_request(request_call, RequestError::Timeout).await;
+            if handshake_pending {
+                self.handshake_failed(node_address, HandshakeError::Timeout)
+                    .await;
+            }
         } else {
             // increment the request retry count and restart the timeout
             trace!(
@@ -411,7 +507,7 @@ impl Handler {
         let tag = self.tag(&node_address.node_id);
 
         let packet = {
-            if let Some(session) = self.sessions.get(&node_address) {
+            if let Some(session) = self.sessions.get_mut(&node_address) {
                 // Encrypt the message and send
                 session
                     .encrypt_message(tag, &request.clone().encode())
@@ -441,7 +537,7 @@ impl Handler {
     async fn send_response(&mut self, node_address: NodeAddress, response: Response) {
         let tag = self.tag(&node_address.node_id);
         // Check for an established session
-        if let Some(session) = self.sessions.get(&node_address) {
+        if let Some(session) = self.sessions.get_mut(&node_address) {
             // Encrypt the message and send
             let packet = match session.encrypt_message(tag, &response.encode()) {
                 Ok(packet) => packet,
@@ -571,6 +667,8 @@ impl Handler {
                 error!("Could not generate a session. Error: {:?}", e);
                 self.fail_request(request_call, RequestError::InvalidRemotePacket)
                     .await;
+                self.handshake_failed(node_address, HandshakeError::DecryptionFailed)
+                    .await;
                 return;
             }
         };
@@ -587,9 +685,14 @@ impl Handler {
 
         // Check if we know the ENR, if not request it and flag the session as awaiting an ENR.
         match request_call.contact.clone() {
-            NodeContact::Enr(enr) => {
-                // Verify the ENR and establish or fail a session.
-                if self.verify_enr(&enr, &node_address) {
+            NodeContact::Enr(enr) | NodeContact::EnrWithAddr { enr, .. } => {
+                // Verify the ENR and establish or fail a session. A node contacted at an overridden
+                // socket only needs to match the node id of its ENR.
+                let verified = match request_call.contact {
+                    NodeContact::EnrWithAddr { .. } => enr.node_id() == node_address.node_id,
+                    _ => self.verify_enr(&enr, &node_address),
+                };
+                if verified {
                     // Send the Auth response
                     trace!(
                         "Sending Authentication response to node: {}",
@@ -606,7 +709,7 @@ impl Handler {
 
                     // Notify the application the session has been established
                     self.outbound_channel
-                        .send(HandlerResponse::Established(*enr))
+                        .send(HandlerResponse::Established(*enr, node_address.socket_addr))
                         .await
                         .unwrap_or_else(|_| ());
                 } else {
@@ -619,6 +722,8 @@ impl Handler {
                     );
                     self.fail_request(request_call, RequestError::InvalidRemoteEnr)
                         .await;
+                    self.handshake_failed(node_address, HandshakeError::InvalidEnr)
+                        .await;
                     return;
                 }
             }
@@ -643,7 +748,7 @@ impl Handler {
                 let id = rand::random();
                 let request = Request {
                     id,
-                    body: RequestBody::FindNode { distance: 0 },
+                    body: RequestBody::GetEnr,
                 };
 
                 session.awaiting_enr = Some(id);
@@ -652,7 +757,7 @@ impl Handler {
                     .unwrap_or_else(|_| ());
             }
         }
-        self.new_session(node_address, session);
+        self.new_session(node_address, session).await;
     }
 
     /// Verifies a Node ENR to it's observed address. If it fails, any associated session is also
@@ -698,10 +803,10 @@ impl Handler {
                         // Session is valid
                         // Notify the application
                         self.outbound_channel
-                            .send(HandlerResponse::Established(enr))
+                            .send(HandlerResponse::Established(enr, node_address.socket_addr))
                             .await
                             .unwrap_or_else(|_| ());
-                        self.new_session(node_address.clone(), session);
+                        self.new_session(node_address.clone(), session).await;
                         self.handle_message(node_address, auth_header.auth_tag, message, tag)
                             .await;
                     } else {
@@ -714,6 +819,8 @@ impl Handler {
                         );
                         self.fail_session(&node_address, RequestError::InvalidRemoteEnr)
                             .await;
+                        self.handshake_failed(node_address, HandshakeError::InvalidEnr)
+                            .await;
                     }
                 }
                 Err(e) => {
@@ -721,8 +828,13 @@ impl Handler {
                         "Invalid Authentication header. Dropping session. Error: {:?}",
                         e
                     );
+                    let error = match e {
+                        Discv5Error::SessionNotEstablished => HandshakeError::NoEnr,
+                        _ => HandshakeError::DecryptionFailed,
+                    };
                     self.fail_session(&node_address, RequestError::InvalidRemotePacket)
                         .await;
+                    self.handshake_failed(node_address, error).await;
                 }
             }
         } else {
@@ -815,7 +927,10 @@ impl Handler {
                                         if self.verify_enr(&enr, &node_address) {
                                             // Notify the application
                                             self.outbound_channel
-                                                .send(HandlerResponse::Established(enr))
+                                                .send(HandlerResponse::Established(
+                                                    enr,
+                                                    node_address.socket_addr,
+                                                ))
                                                 .await
                                                 .unwrap_or_else(|_| ());
                                             return;
@@ -827,6 +942,8 @@ impl Handler {
                             debug!("Session failed invalid ENR response");
                             self.fail_session(&node_address, RequestError::InvalidRemoteEnr)
                                 .await;
+                            self.handshake_failed(node_address, HandshakeError::InvalidEnr)
+                                .await;
                             return;
                         }
                     }
@@ -950,17 +1067,89 @@ impl Handler {
         self.active_requests_auth.insert(auth_tag, node_address);
     }
 
-    fn new_session(&mut self, node_address: NodeAddress, session: Session) {
+    /// Returns information about the established sessions, without refreshing them.
+    fn sessions_info(&self) -> Vec<SessionInfo> {
+        self.sessions
+            .peek_iter()
+            .map(|(node_address, session)| SessionInfo {
+                node_id: node_address.node_id,
+                remote_addr: node_address.socket_addr,
+                established: session.established,
+                expires: session.last_active + self.session_timeout,
+            })
+            .collect()
+    }
+
+    /// Returns the number of established sessions, excluding sessions that have expired but are
+    /// yet to be removed.
+    fn session_count(&self) -> usize {
+        let now = Instant::now();
+        self.sessions
+            .peek_iter()
+            .filter(|(_, session)| session.last_active + self.session_timeout > now)
+            .count()
+    }
+
+    /// Removes and reports the sessions that have been inactive for longer than the session
+    /// timeout.
+    async fn expire_sessions(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<NodeAddress> = self
+            .sessions
+            .peek_iter()
+            .filter(|(_, session)| session.last_active + self.session_timeout <= now)
+            .map(|(node_address, _)| node_address.clone())
+            .collect();
+        for node_address in expired {
+            debug!("Session expired with: {}", node_address);
+            self.sessions.remove(&node_address);
+            let _ = self
+                .outbound_channel
+                .send(HandlerResponse::SessionExpired(node_address))
+                .await;
+        }
+        METRICS
+            .active_sessions
+            .store(self.sessions.len(), Ordering::Relaxed);
+    }
+
+    async fn new_session(&mut self, node_address: NodeAddress, session: Session) {
         if let Some(current_session) = self.sessions.get_mut(&node_address) {
             current_session.update(session);
         } else {
+            // The cache evicts the least recently used session if it is full. Sessions are
+            // iterated in the most recently used order.
+            let evicted = if self.sessions.len() >= self.session_cache_capacity {
+                self.sessions
+                    .peek_iter()
+                    .last()
+                    .map(|(node_address, _)| node_address.node_id)
+            } else {
+                None
+            };
             self.sessions.insert(node_address, session);
             METRICS
                 .active_sessions
                 .store(self.sessions.len(), Ordering::Relaxed);
+            if let Some(node_id) = evicted {
+                debug!("Session cache full. Evicted session with: {}", node_id);
+                let _ = self
+                    .outbound_channel
+                    .send(HandlerResponse::SessionEvicted(node_id))
+                    .await;
+            }
         }
     }
 
+    /// Reports a failed session handshake to the application.
+    async fn handshake_failed(&mut self, node_address: NodeAddress, error: HandshakeError) {
+        debug!("Handshake with {} failed: {}", node_address, error);
+        let _ = self
+            .outbound_channel
+            .send(HandlerResponse::HandshakeFailed(node_address, error))
+            .await;
+    }
+
     async fn fail_request(&mut self, request_call: RequestCall, error: RequestError) {
         // The Request has expired, remove the session.
         let auth_tag = request_call
@@ -2,6 +2,7 @@ use super::*;
 use crate::node_info::NodeContact;
 use crate::packet::AuthResponse;
 use enr::{CombinedKey, NodeId};
+use std::time::Instant;
 use zeroize::Zeroize;
 
 #[derive(Zeroize, PartialEq)]
@@ -30,14 +31,21 @@ pub(crate) struct Session {
     ///
     /// This field holds the request_id associated with the ENR request.
     pub awaiting_enr: Option<RequestId>,
+    /// When the current session was established.
+    pub established: Instant,
+    /// When a message was last encrypted or decrypted with this session.
+    pub last_active: Instant,
 }
 
 impl Session {
     pub fn new(keys: Keys) -> Self {
+        let now = Instant::now();
         Session {
             keys,
             awaiting_keys: None,
             awaiting_enr: None,
+            established: now,
+            last_active: now,
         }
     }
 
@@ -46,11 +54,17 @@ impl Session {
         // Await the new sessions keys
         self.awaiting_keys = Some(new_session.keys);
         self.awaiting_enr = new_session.awaiting_enr;
+        self.established = new_session.established;
     }
 
     /// Uses the current `Session` to encrypt a message. Encrypt packets with the current session
     /// key if we are awaiting a response from AuthMessage.
-    pub(crate) fn encrypt_message(&self, tag: Tag, message: &[u8]) -> Result<Packet, Discv5Error> {
+    pub(crate) fn encrypt_message(
+        &mut self,
+        tag: Tag,
+        message: &[u8],
+    ) -> Result<Packet, Discv5Error> {
+        self.last_active = Instant::now();
         //TODO: Establish a counter to prevent repeats of nonce
         let auth_tag: AuthTag = rand::random();
 
@@ -71,6 +85,7 @@ impl Session {
         message: &[u8],
         aad: &[u8],
     ) -> Result<Vec<u8>, Discv5Error> {
+        self.last_active = Instant::now();
         // try with the new keys
         if let Some(new_keys) = self.awaiting_keys.take() {
             let result = crypto::decrypt_message(&new_keys.decryption_key, nonce, message, aad);
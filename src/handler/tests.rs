@@ -48,6 +48,7 @@ async fn simple_session_message() {
         arc_rw!(sender_enr.clone()),
         arc_rw!(key1),
         sender_enr.udp_socket().unwrap(),
+        None,
         config.clone(),
     );
 
@@ -55,6 +56,7 @@ async fn simple_session_message() {
         arc_rw!(receiver_enr.clone()),
         arc_rw!(key2),
         receiver_enr.udp_socket().unwrap(),
+        None,
         config,
     );
 
@@ -126,6 +128,7 @@ async fn multiple_messages() {
         arc_rw!(sender_enr.clone()),
         arc_rw!(key1),
         sender_enr.udp_socket().unwrap(),
+        None,
         config.clone(),
     );
 
@@ -133,6 +136,7 @@ async fn multiple_messages() {
         arc_rw!(receiver_enr.clone()),
         arc_rw!(key2),
         receiver_enr.udp_socket().unwrap(),
+        None,
         config,
     );
 
@@ -166,7 +170,7 @@ async fn multiple_messages() {
     let sender = async move {
         loop {
             match sender_handler_recv.next().await {
-                Some(HandlerResponse::Established(_)) => {
+                Some(HandlerResponse::Established(_, _)) => {
                     // now the session is established, send the rest of the messages
                     for _ in 0..messages_to_send - 1 {
                         let _ = sender_handler
@@ -217,3 +221,70 @@ async fn multiple_messages() {
         }
     }
 }
+
+#[tokio::test]
+// Tests that a request is re-sent `request_retries` times before it fails
+async fn request_retries() {
+    init();
+
+    let sender_port = 5004;
+    let receiver_port = 5005;
+    let ip: IpAddr = "127.0.0.1".parse().unwrap();
+    let key1 = CombinedKey::generate_secp256k1();
+    let key2 = CombinedKey::generate_secp256k1();
+
+    let config = Discv5ConfigBuilder::new()
+        .executor(Box::new(TokioExecutor(tokio::runtime::Handle::current())))
+        .request_timeout(Duration::from_millis(100))
+        .request_retries(2)
+        .build();
+
+    let sender_enr = EnrBuilder::new("v4")
+        .ip(ip)
+        .udp(sender_port)
+        .build(&key1)
+        .unwrap();
+    let receiver_enr = EnrBuilder::new("v4")
+        .ip(ip)
+        .udp(receiver_port)
+        .build(&key2)
+        .unwrap();
+
+    // the receiver never responds
+    let mut receiver_socket = tokio::net::UdpSocket::bind(receiver_enr.udp_socket().unwrap())
+        .await
+        .unwrap();
+
+    let (_exit_send, mut sender_handler, mut sender_events) = Handler::spawn(
+        arc_rw!(sender_enr.clone()),
+        arc_rw!(key1),
+        sender_enr.udp_socket().unwrap(),
+        None,
+        config,
+    );
+
+    let _ = sender_handler
+        .send(HandlerRequest::Request(
+            receiver_enr.into(),
+            Box::new(Request {
+                id: 1,
+                body: RequestBody::Ping { enr_seq: 1 },
+            }),
+        ))
+        .await;
+
+    let mut buffer = [0; crate::socket::MAX_PACKET_SIZE];
+    let mut packets = 0;
+    loop {
+        tokio::select! {
+            Ok(_) = receiver_socket.recv_from(&mut buffer) => packets += 1,
+            Some(event) = sender_events.recv() => {
+                assert!(matches!(event, HandlerResponse::RequestFailed(1, RequestError::Timeout)));
+                break;
+            }
+            _ = delay_for(Duration::from_secs(1)) => panic!("Test timed out"),
+        }
+    }
+    // the initial request and two retries
+    assert_eq!(packets, 3);
+}
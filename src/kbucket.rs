@@ -224,6 +224,41 @@ where
         })
     }
 
+    /// Returns the number of connected and disconnected entries in each bucket, as
+    /// `(connected, disconnected)`. The bucket at index `i` holds the entries at log2 distance
+    /// `i + 1` from the local key. Pending entries are not counted.
+    pub fn entries_per_bucket(&self) -> Vec<(usize, usize)> {
+        self.buckets
+            .iter()
+            .map(|bucket| {
+                bucket
+                    .iter()
+                    .fold((0, 0), |(connected, disconnected), (_, status)| {
+                        if status == NodeStatus::Connected {
+                            (connected + 1, disconnected)
+                        } else {
+                            (connected, disconnected + 1)
+                        }
+                    })
+            })
+            .collect()
+    }
+
+    /// Estimates the number of nodes in the network from the density of nodes in the closest
+    /// occupied bucket. The bucket at log2 distance `d` covers `2^(d - 1)` of the `2^256` possible
+    /// keys, so `n` entries in it give an estimate of `n * 2^(257 - d)` nodes. Returns `None` if
+    /// the table is empty.
+    pub fn estimate_network_size(&self) -> Option<f64> {
+        let (index, entries) = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.num_entries())
+            .enumerate()
+            .find(|(_, entries)| *entries > 0)?;
+        // the bucket at index `i` holds the entries at log2 distance `i + 1`
+        Some(entries as f64 * 2f64.powi(NUM_BUCKETS as i32 - index as i32))
+    }
+
     /// Consumes the next applied pending entry, if any.
     ///
     /// When an entry is attempted to be inserted and the respective bucket is full,
@@ -401,7 +436,8 @@ impl ClosestBucketsIter {
     fn new(distance: Distance) -> Self {
         let state = match BucketIndex::new(&distance) {
             Some(i) => ClosestBucketsIterState::Start(i),
-            None => ClosestBucketsIterState::Done,
+            // the target is the local key, so every bucket is visited in order
+            None => ClosestBucketsIterState::ZoomIn(BucketIndex(0)),
         };
         Self { distance, state }
     }
@@ -523,6 +559,50 @@ mod tests {
         assert_eq!(res[0], other_id);
     }
 
+    #[test]
+    fn entries_per_bucket() {
+        let local_key = Key::from(NodeId::random());
+        let mut table = KBucketsTable::<_, ()>::new(local_key.clone(), Duration::from_secs(5));
+        let mut expected = vec![(0, 0); NUM_BUCKETS];
+        for i in 0..10 {
+            let key = Key::from(NodeId::random());
+            let status = if i % 2 == 0 {
+                NodeStatus::Connected
+            } else {
+                NodeStatus::Disconnected
+            };
+            if let Entry::Absent(entry) = table.entry(&key) {
+                if let InsertResult::Inserted = entry.insert((), status) {
+                    let index = local_key.log2_distance(&key).unwrap() as usize - 1;
+                    if status == NodeStatus::Connected {
+                        expected[index].0 += 1;
+                    } else {
+                        expected[index].1 += 1;
+                    }
+                }
+            }
+        }
+        assert_eq!(table.entries_per_bucket(), expected);
+    }
+
+    #[test]
+    fn estimate_network_size() {
+        let local_key = Key::from(NodeId::random());
+        let mut table = KBucketsTable::<_, ()>::new(local_key.clone(), Duration::from_secs(5));
+        assert_eq!(table.estimate_network_size(), None);
+        for distance in [250, 250, 250, 256, 256].iter() {
+            let key = Key::from(local_key.random_at_distance(*distance).unwrap());
+            if let Entry::Absent(entry) = table.entry(&key) {
+                match entry.insert((), NodeStatus::Connected) {
+                    InsertResult::Inserted => {}
+                    _ => panic!("Entry not inserted"),
+                }
+            }
+        }
+        // 3 entries covering 2^249 keys
+        assert_eq!(table.estimate_network_size(), Some(384.0));
+    }
+
     #[test]
     fn update_local_id_fails() {
         let local_key = Key::from(NodeId::random());
@@ -536,7 +616,7 @@ mod tests {
     #[test]
     fn closest() {
         let local_key = Key::from(NodeId::random());
-        let mut table = KBucketsTable::<_, ()>::new(local_key, Duration::from_secs(5));
+        let mut table = KBucketsTable::<_, ()>::new(local_key.clone(), Duration::from_secs(5));
         let mut count = 0;
         loop {
             if count == 100 {
@@ -566,6 +646,11 @@ mod tests {
             expected_keys.sort_by_key(|k| k.distance(&target_key));
             assert_eq!(keys, expected_keys);
         }
+
+        // targeting the local key visits every bucket
+        let keys = table.closest_keys(&local_key).collect::<Vec<_>>();
+        expected_keys.sort_by_key(|k| k.distance(&local_key));
+        assert_eq!(keys, expected_keys);
     }
 
     #[test]
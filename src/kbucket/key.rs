@@ -124,6 +124,31 @@ impl From<NodeId> for Key<NodeId> {
     }
 }
 
+impl Key<NodeId> {
+    /// Returns a random node id at the given log2 distance from this key, or `None` if the
+    /// distance is not in the range 1-256.
+    pub fn random_at_distance(&self, log2_distance: u64) -> Option<NodeId> {
+        if log2_distance == 0 || log2_distance > 256 {
+            return None;
+        }
+        // the highest set bit of the XOR distance determines the log2 distance
+        let bit = (log2_distance - 1) as usize;
+        let byte = 31 - bit / 8;
+        let mask = 1u8 << (bit % 8);
+        let mut xor: [u8; 32] = rand::random();
+        for b in xor.iter_mut().take(byte) {
+            *b = 0;
+        }
+        xor[byte] = (xor[byte] & (mask - 1)) | mask;
+
+        let mut raw = self.preimage.raw();
+        for (b, x) in raw.iter_mut().zip(xor.iter()) {
+            *b ^= x;
+        }
+        NodeId::parse(&raw).ok()
+    }
+}
+
 /// A distance between two `Key`s.
 #[derive(Copy, Clone, PartialEq, Eq, Default, PartialOrd, Ord, Debug)]
 pub struct Distance(pub(super) U256);
@@ -170,6 +195,16 @@ mod tests {
         quickcheck(prop as fn(_, _, _) -> _)
     }
 
+    #[test]
+    fn random_at_distance() {
+        fn prop(a: Key<NodeId>, distance: u8) -> bool {
+            let distance = u64::from(distance) + 1;
+            let b = Key::from(a.random_at_distance(distance).unwrap());
+            a.log2_distance(&b) == Some(distance)
+        }
+        quickcheck(prop as fn(_, _) -> _)
+    }
+
     #[test]
     fn unidirectionality() {
         fn prop(a: Key<NodeId>, b: Key<NodeId>) -> bool {
@@ -109,6 +109,7 @@
 //! [`Service`]: service/struct.Service.html
 //! [`Session`]: session/struct.Session.html
 
+mod bootstrap_source;
 mod config;
 mod discv5;
 mod error;
@@ -129,11 +130,18 @@ extern crate lazy_static;
 
 pub type Enr = enr::Enr<enr::CombinedKey>;
 
-pub use crate::discv5::{Discv5, Discv5Event};
+pub use crate::discv5::{AddEnrOutcome, Discv5, Discv5Event, RemovalReason};
+pub use bootstrap_source::{BootstrapSource, TxtResolver};
 pub use config::{Discv5Config, Discv5ConfigBuilder};
-pub use error::{Discv5Error, QueryError, RequestError};
+pub use error::{Discv5Error, HandshakeError, QueryError, RequestError};
 pub use executor::{Executor, TokioExecutor};
-pub use permit_ban::PermitBanList;
+pub use handler::SessionInfo;
+pub use kbucket::NodeStatus;
+pub use node_info::{NodeAddress, NodeContact};
+pub use permit_ban::{BanListExport, PermitBanList};
+pub use query_pool::QueryId;
+pub use rpc::{Pong, RequestBody, Response, ResponseBody};
+pub use service::QueryOptions;
 pub use socket::{FilterConfig, FilterConfigBuilder};
 // re-export the ENR crate
 pub use enr;
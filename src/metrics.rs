@@ -12,6 +12,30 @@ lazy_static! {
 pub struct InternalMetrics {
     /// The number of active UDP sessions that are currently established.
     pub active_sessions: AtomicUsize,
+    /// The number of bootnodes that are currently not contacted due to repeated failures.
+    pub open_bootnode_circuits: AtomicUsize,
+    /// The number of FINDNODE responses that were dampened as the request was repeated.
+    pub dampened_findnode_responses: AtomicUsize,
+    /// The number of inbound packets dropped as their source IP exceeded its rate limit.
+    pub dropped_packets_ip_rate_limit: AtomicUsize,
+    /// The number of inbound packets dropped as the global rate limit was exceeded.
+    pub dropped_packets_global_rate_limit: AtomicUsize,
+    /// The number of inbound packets dropped as they could not be decoded.
+    pub dropped_packets_decode_failed: AtomicUsize,
+    /// The number of RPC requests sent.
+    pub requests_sent: AtomicUsize,
+    /// The number of RPC responses received. Each packet of a NODES response is counted.
+    pub responses_received: AtomicUsize,
+    /// The number of RPC requests that timed out.
+    pub requests_timed_out: AtomicUsize,
+    /// The number of RPC requests sent per request type.
+    pub requests_sent_per_type: RwLock<HashMap<&'static str, usize>>,
+    /// The number of RPC responses received per response type.
+    pub responses_received_per_type: RwLock<HashMap<&'static str, usize>>,
+    /// The number of bytes sent on the UDP socket.
+    pub bytes_sent: AtomicUsize,
+    /// The number of bytes received on the UDP socket.
+    pub bytes_received: AtomicUsize,
     /// The number of seconds to store received packets to taking a moving average over.
     pub moving_window: u64,
     /// The number of unsolicited requests received per moving window.
@@ -29,6 +53,18 @@ impl Default for InternalMetrics {
         InternalMetrics {
             moving_window: 5,
             active_sessions: AtomicUsize::new(0),
+            open_bootnode_circuits: AtomicUsize::new(0),
+            dampened_findnode_responses: AtomicUsize::new(0),
+            dropped_packets_ip_rate_limit: AtomicUsize::new(0),
+            dropped_packets_global_rate_limit: AtomicUsize::new(0),
+            dropped_packets_decode_failed: AtomicUsize::new(0),
+            requests_sent: AtomicUsize::new(0),
+            responses_received: AtomicUsize::new(0),
+            requests_timed_out: AtomicUsize::new(0),
+            requests_sent_per_type: RwLock::new(HashMap::new()),
+            responses_received_per_type: RwLock::new(HashMap::new()),
+            bytes_sent: AtomicUsize::new(0),
+            bytes_received: AtomicUsize::new(0),
             unsolicited_requests_per_window: AtomicUsize::new(0),
             requests_per_node_per_second: RwLock::new(HashMap::new()),
             requests_per_ip_per_second: RwLock::new(HashMap::new()),
@@ -41,6 +77,30 @@ impl Default for InternalMetrics {
 pub struct Metrics {
     /// The number of active UDP sessions that are currently established.
     pub active_sessions: usize,
+    /// The number of bootnodes that are currently not contacted due to repeated failures.
+    pub open_bootnode_circuits: usize,
+    /// The number of FINDNODE responses that were dampened as the request was repeated.
+    pub dampened_findnode_responses: usize,
+    /// The number of inbound packets dropped as their source IP exceeded its rate limit.
+    pub dropped_packets_ip_rate_limit: usize,
+    /// The number of inbound packets dropped as the global rate limit was exceeded.
+    pub dropped_packets_global_rate_limit: usize,
+    /// The number of inbound packets dropped as they could not be decoded.
+    pub dropped_packets_decode_failed: usize,
+    /// The number of RPC requests sent.
+    pub requests_sent: usize,
+    /// The number of RPC responses received. Each packet of a NODES response is counted.
+    pub responses_received: usize,
+    /// The number of RPC requests that timed out.
+    pub requests_timed_out: usize,
+    /// The number of RPC requests sent per request type.
+    pub requests_sent_per_type: HashMap<&'static str, usize>,
+    /// The number of RPC responses received per response type.
+    pub responses_received_per_type: HashMap<&'static str, usize>,
+    /// The number of bytes sent on the UDP socket.
+    pub bytes_sent: usize,
+    /// The number of bytes received on the UDP socket.
+    pub bytes_received: usize,
     /// The number of unsolicited requests received per second (averaged over a moving window).
     pub unsolicited_requests_per_second: f64,
     /// The number of unsolicited requests per node per second (averaged over a moving window).
@@ -53,6 +113,31 @@ impl From<&METRICS> for Metrics {
     fn from(internal_metrics: &METRICS) -> Self {
         Metrics {
             active_sessions: internal_metrics.active_sessions.load(Ordering::Relaxed),
+            open_bootnode_circuits: internal_metrics
+                .open_bootnode_circuits
+                .load(Ordering::Relaxed),
+            dampened_findnode_responses: internal_metrics
+                .dampened_findnode_responses
+                .load(Ordering::Relaxed),
+            dropped_packets_ip_rate_limit: internal_metrics
+                .dropped_packets_ip_rate_limit
+                .load(Ordering::Relaxed),
+            dropped_packets_global_rate_limit: internal_metrics
+                .dropped_packets_global_rate_limit
+                .load(Ordering::Relaxed),
+            dropped_packets_decode_failed: internal_metrics
+                .dropped_packets_decode_failed
+                .load(Ordering::Relaxed),
+            requests_sent: internal_metrics.requests_sent.load(Ordering::Relaxed),
+            responses_received: internal_metrics.responses_received.load(Ordering::Relaxed),
+            requests_timed_out: internal_metrics.requests_timed_out.load(Ordering::Relaxed),
+            requests_sent_per_type: internal_metrics.requests_sent_per_type.read().clone(),
+            responses_received_per_type: internal_metrics
+                .responses_received_per_type
+                .read()
+                .clone(),
+            bytes_sent: internal_metrics.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: internal_metrics.bytes_received.load(Ordering::Relaxed),
             unsolicited_requests_per_second: internal_metrics
                 .unsolicited_requests_per_window
                 .load(Ordering::Relaxed) as f64
@@ -12,11 +12,21 @@ use libp2p_core::{identity::PublicKey, multiaddr::Protocol, Multiaddr};
 pub enum NodeContact {
     /// We know the ENR of the node we are contacting.
     Enr(Box<Enr>),
+    /// We know the ENR of the node, but contact it at the given socket rather than the socket in
+    /// its ENR, for example if the node is behind a NAT and its ENR is out of date. The ENR must
+    /// still match the node id of the node that responds.
+    EnrWithAddr {
+        /// The ENR of the node.
+        enr: Box<Enr>,
+        /// The socket to contact the node at.
+        socket_addr: SocketAddr,
+    },
     /// We don't have an ENR, but have enough information to start a handshake.
     ///
     /// The handshake will request the ENR at the first opportunity.
-    /// The public key can be derived from multiaddr's whose keys can be inlined. The `TryFrom`
-    /// implementation for `String` and `MultiAddr`. This is gated behind the `libp2p` feature.
+    /// The public key can be derived from multiaddr's whose keys can be inlined, via
+    /// [`NodeContact::from_multiaddr`] or the `TryFrom<Multiaddr>` implementation. These are gated
+    /// behind the `libp2p` feature.
     Raw {
         /// An ENR compatible public key, required for handshaking with peers.
         public_key: Box<CombinedPublicKey>,
@@ -28,28 +38,30 @@ pub enum NodeContact {
 impl NodeContact {
     pub fn node_id(&self) -> NodeId {
         match self {
-            NodeContact::Enr(enr) => enr.node_id(),
+            NodeContact::Enr(enr) | NodeContact::EnrWithAddr { enr, .. } => enr.node_id(),
             NodeContact::Raw { node_address, .. } => node_address.node_id,
         }
     }
 
     pub fn seq_no(&self) -> Option<u64> {
         match self {
-            NodeContact::Enr(enr) => Some(enr.seq()),
+            NodeContact::Enr(enr) | NodeContact::EnrWithAddr { enr, .. } => Some(enr.seq()),
             _ => None,
         }
     }
 
     pub fn public_key(&self) -> CombinedPublicKey {
         match self {
-            NodeContact::Enr(ref enr) => enr.public_key(),
+            NodeContact::Enr(ref enr) | NodeContact::EnrWithAddr { ref enr, .. } => {
+                enr.public_key()
+            }
             NodeContact::Raw { public_key, .. } => *public_key.clone(),
         }
     }
 
     pub fn is_enr(&self) -> bool {
         match self {
-            NodeContact::Enr(_) => true,
+            NodeContact::Enr(_) | NodeContact::EnrWithAddr { .. } => true,
             _ => false,
         }
     }
@@ -59,6 +71,7 @@ impl NodeContact {
             NodeContact::Enr(ref enr) => enr
                 .udp_socket()
                 .ok_or_else(|| "ENR does not contain an IP and UDP port"),
+            NodeContact::EnrWithAddr { socket_addr, .. } => Ok(*socket_addr),
             NodeContact::Raw { node_address, .. } => Ok(node_address.socket_addr),
         }
     }
@@ -71,6 +84,16 @@ impl NodeContact {
             socket_addr,
         })
     }
+
+    /// Builds a contact from a multiaddr with an `/ip4` or `/ip6` address, a `/udp` port and a
+    /// `/p2p` component with an inlined secp256k1 or ed25519 public key, such as
+    /// `/ip4/127.0.0.1/udp/9000/p2p/16Uiu2...`. An error describing the missing or invalid
+    /// component is returned otherwise.
+    #[cfg(feature = "libp2p")]
+    pub fn from_multiaddr(multiaddr: Multiaddr) -> Result<Self, &'static str> {
+        use std::convert::TryFrom;
+        NodeContact::try_from(multiaddr)
+    }
 }
 
 impl From<Enr> for NodeContact {
@@ -143,6 +166,9 @@ impl std::fmt::Display for NodeContact {
             NodeContact::Enr(enr) => {
                 write!(f, "Node: {}, addr: {:?}", enr.node_id(), enr.udp_socket())
             }
+            NodeContact::EnrWithAddr { enr, socket_addr } => {
+                write!(f, "Node: {}, addr: {:?}", enr.node_id(), socket_addr)
+            }
             NodeContact::Raw { node_address, .. } => write!(f, "{}", node_address),
         }
     }
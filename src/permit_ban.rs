@@ -1,34 +1,219 @@
 use crate::node_info::NodeAddress;
 use enr::NodeId;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
 use std::net::IpAddr;
+use std::time::Instant;
+
+/// The bans of a [`PermitBanList`], as exported by [`PermitBanList::export_bans`]. Each entry
+/// holds the expiry of the ban, or `None` for a permanent ban.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BanListExport {
+    /// The banned nodes.
+    pub nodes: Vec<(NodeId, Option<Instant>)>,
+    /// The banned IPs.
+    pub ips: Vec<(IpAddr, Option<Instant>)>,
+}
 
 #[derive(Debug, Clone)]
 pub struct PermitBanList {
     /// A set of IPs which pass all filters.
     pub permit_ips: HashSet<IpAddr>,
-    /// A set of IPs whose packets get dropped instantly.
-    pub ban_ips: HashSet<IpAddr>,
+    /// A map of IPs whose packets get dropped instantly, with an optional expiry of the ban. A ban
+    /// without an expiry is permanent.
+    pub ban_ips: HashMap<IpAddr, Option<Instant>>,
     /// A set of NodeIds which pass all filters.
     pub permit_nodes: HashSet<NodeId>,
-    /// A set of NodeIds whose packets get dropped instantly.
-    pub ban_nodes: HashSet<NodeId>,
+    /// A map of NodeIds whose packets get dropped instantly, with an optional expiry of the ban.
+    /// A ban without an expiry is permanent.
+    pub ban_nodes: HashMap<NodeId, Option<Instant>>,
 }
 
 impl Default for PermitBanList {
     fn default() -> Self {
         PermitBanList {
             permit_ips: HashSet::new(),
-            ban_ips: HashSet::new(),
+            ban_ips: HashMap::new(),
             permit_nodes: HashSet::new(),
-            ban_nodes: HashSet::new(),
+            ban_nodes: HashMap::new(),
         }
     }
 }
 
 impl PermitBanList {
+    /// Permanently bans the node and its IP.
     pub fn ban(&mut self, node_address: NodeAddress) {
-        self.ban_ips.insert(node_address.socket_addr.ip());
-        self.ban_nodes.insert(node_address.node_id);
+        self.ban_until(node_address, None);
+    }
+
+    /// Bans the node and its IP until `expiry`, or permanently if `expiry` is `None`.
+    pub fn ban_until(&mut self, node_address: NodeAddress, expiry: Option<Instant>) {
+        self.ban_ips.insert(node_address.socket_addr.ip(), expiry);
+        self.ban_nodes.insert(node_address.node_id, expiry);
+    }
+
+    /// Removes any ban of the node and its IP.
+    pub fn unban(&mut self, node_address: &NodeAddress) {
+        self.ban_ips.remove(&node_address.socket_addr.ip());
+        self.ban_nodes.remove(&node_address.node_id);
+    }
+
+    /// Returns whether the node or its IP is banned. Bans that have expired are ignored.
+    pub fn is_banned(&self, node_address: &NodeAddress) -> bool {
+        self.is_ip_banned(&node_address.socket_addr.ip())
+            || is_active(self.ban_nodes.get(&node_address.node_id))
+    }
+
+    /// Returns whether the IP is banned. Bans that have expired are ignored.
+    pub fn is_ip_banned(&self, ip: &IpAddr) -> bool {
+        is_active(self.ban_ips.get(ip))
+    }
+
+    /// Removes all bans that have expired.
+    pub fn purge_expired(&mut self) {
+        let now = Instant::now();
+        let unexpired =
+            |expiry: &Option<Instant>| !matches!(expiry, Some(expiry) if *expiry <= now);
+        self.ban_ips.retain(|_, expiry| unexpired(expiry));
+        self.ban_nodes.retain(|_, expiry| unexpired(expiry));
+    }
+
+    /// Returns the banned nodes and IPs along with the expiry of their ban. Bans that have already
+    /// expired are not included.
+    pub fn export_bans(&self) -> BanListExport {
+        BanListExport {
+            nodes: export(&self.ban_nodes),
+            ips: export(&self.ban_ips),
+        }
+    }
+
+    /// Adds previously exported node and IP bans to the list. Entries that have already expired
+    /// are dropped. If a node or IP is already banned, the ban that lasts the longest is kept.
+    /// Returns the number of entries imported.
+    pub fn import_bans(&mut self, bans: BanListExport) -> usize {
+        import(&mut self.ban_nodes, bans.nodes) + import(&mut self.ban_ips, bans.ips)
+    }
+}
+
+/// Returns the unexpired bans of the map.
+fn export<K: Copy>(bans: &HashMap<K, Option<Instant>>) -> Vec<(K, Option<Instant>)> {
+    let now = Instant::now();
+    bans.iter()
+        .filter(|(_, expiry)| !matches!(expiry, Some(expiry) if *expiry <= now))
+        .map(|(key, expiry)| (*key, *expiry))
+        .collect()
+}
+
+/// Merges the unexpired bans into the map, returning the number of bans imported.
+fn import<K: Eq + Hash>(
+    bans: &mut HashMap<K, Option<Instant>>,
+    imported_bans: Vec<(K, Option<Instant>)>,
+) -> usize {
+    let now = Instant::now();
+    let mut imported = 0;
+    for (key, expiry) in imported_bans {
+        if matches!(expiry, Some(expiry) if expiry <= now) {
+            continue;
+        }
+        let entry = bans.entry(key).or_insert(expiry);
+        *entry = match (*entry, expiry) {
+            (Some(current), Some(expiry)) => Some(current.max(expiry)),
+            _ => None,
+        };
+        imported += 1;
+    }
+    imported
+}
+
+/// Returns whether a ban with the given expiry is in effect.
+fn is_active(ban: Option<&Option<Instant>>) -> bool {
+    match ban {
+        Some(Some(expiry)) => *expiry > Instant::now(),
+        Some(None) => true,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_ban_unban() {
+        let node_address = NodeAddress {
+            node_id: NodeId::random(),
+            socket_addr: "127.0.0.1:9000".parse().unwrap(),
+        };
+        let mut list = PermitBanList::default();
+        assert!(!list.is_banned(&node_address));
+
+        list.ban(node_address.clone());
+        assert!(list.is_banned(&node_address));
+
+        // the ban also applies to other nodes on the same IP
+        let other_address = NodeAddress {
+            node_id: NodeId::random(),
+            socket_addr: "127.0.0.1:9001".parse().unwrap(),
+        };
+        assert!(list.is_banned(&other_address));
+
+        list.unban(&node_address);
+        assert!(!list.is_banned(&node_address));
+        assert!(!list.is_banned(&other_address));
+    }
+
+    #[test]
+    fn test_temporary_bans_expire() {
+        let node_address = NodeAddress {
+            node_id: NodeId::random(),
+            socket_addr: "127.0.0.1:9000".parse().unwrap(),
+        };
+        let mut list = PermitBanList::default();
+
+        list.ban_until(
+            node_address.clone(),
+            Some(Instant::now() + Duration::from_secs(60)),
+        );
+        assert!(list.is_banned(&node_address));
+        list.purge_expired();
+        assert_eq!(list.ban_nodes.len(), 1);
+
+        // expired bans are ignored and purged
+        list.ban_until(node_address.clone(), Some(Instant::now()));
+        assert!(!list.is_banned(&node_address));
+        list.purge_expired();
+        assert!(list.ban_nodes.is_empty());
+        assert!(list.ban_ips.is_empty());
+    }
+
+    #[test]
+    fn test_ban_list_export_import_round_trip() {
+        let permanent = NodeId::random();
+        let temporary = NodeId::random();
+        let expired = NodeId::random();
+        let banned_ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+        let expiry = now + Duration::from_secs(60);
+
+        let mut list = PermitBanList::default();
+        list.ban_nodes.insert(permanent, None);
+        list.ban_nodes.insert(temporary, Some(expiry));
+        list.ban_nodes.insert(expired, Some(now));
+        list.ban_ips.insert(banned_ip, Some(expiry));
+
+        let mut exported = list.export_bans();
+        assert_eq!(exported.nodes.len(), 2);
+        assert_eq!(exported.ips, vec![(banned_ip, Some(expiry))]);
+
+        let mut restored = PermitBanList::default();
+        exported.nodes.push((expired, Some(now)));
+        let imported = restored.import_bans(exported);
+        assert_eq!(imported, 3);
+        assert_eq!(restored.ban_nodes.len(), 2);
+        assert_eq!(restored.ban_nodes.get(&permanent), Some(&None));
+        assert_eq!(restored.ban_nodes.get(&temporary), Some(&Some(expiry)));
+        assert!(!restored.ban_nodes.contains_key(&expired));
+        assert!(restored.is_ip_banned(&banned_ip));
     }
 }
@@ -31,7 +31,13 @@ use crate::kbucket::{Key, PredicateKey};
 use fnv::FnvHashMap;
 use std::time::{Duration, Instant};
 
+/// The target of a query, which derives the key the query iterates towards.
+///
+/// Peers are ordered by the XOR distance of their keys to the target's key, so a target is free
+/// to derive its key from something other than a node id, such as the hash of a topic, and
+/// still be driven by the `QueryPool`.
 pub trait TargetKey<TNodeId> {
+    /// The key of the target in the key space of the peers.
     fn key(&self) -> Key<TNodeId>;
 }
 
@@ -134,6 +140,11 @@ where
         self.queries.get_mut(&id)
     }
 
+    /// Removes a query from the pool, returning it if it was present.
+    pub fn remove(&mut self, id: QueryId) -> Option<Query<TTarget, TNodeId, TResult>> {
+        self.queries.remove(&id)
+    }
+
     /// Polls the pool to advance the queries.
     pub fn poll(&mut self) -> QueryPoolState<'_, TTarget, TNodeId, TResult> {
         let now = Instant::now();
@@ -44,6 +44,8 @@
 pub mod closest;
 pub mod predicate;
 
+use std::time::Instant;
+
 /// The state of the query reported by [`Query::next`].
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum QueryState<TNodeId> {
@@ -75,3 +77,52 @@ pub struct ReturnPeer<TNodeId> {
     /// The current request iteration of this peer.
     pub iteration: usize,
 }
+
+/// The state of `QueryPeer` in the context of a query.
+#[derive(Debug, Copy, Clone)]
+enum QueryPeerState {
+    /// The peer has not yet been contacted.
+    ///
+    /// This is the starting state for every peer known to, or discovered by, a query.
+    NotContacted,
+
+    /// The query is waiting for a result from the peer.
+    Waiting(Instant),
+
+    /// A result was not delivered for the peer within the configured timeout.
+    ///
+    /// The peer is not taken into account for the termination conditions
+    /// of the iterator until and unless it responds.
+    Unresponsive,
+
+    /// The peer is waiting to to begin another iteration.
+    PendingIteration,
+
+    /// Obtaining a result from the peer has failed.
+    ///
+    /// This is a final state, reached as a result of a call to `on_failure`.
+    Failed,
+
+    /// A successful result from the peer has been delivered.
+    ///
+    /// This is a final state, reached as a result of a call to `on_success`.
+    Succeeded,
+}
+
+/// Sets the peers whose request has timed out by `now` to `QueryPeerState::Unresponsive`.
+/// Returns the number of peers that timed out, which are no longer waited on.
+fn mark_unresponsive<'a>(
+    states: impl Iterator<Item = &'a mut QueryPeerState>,
+    now: Instant,
+) -> usize {
+    let mut timed_out = 0;
+    for state in states {
+        if let QueryPeerState::Waiting(timeout) = *state {
+            if now >= timeout {
+                *state = QueryPeerState::Unresponsive;
+                timed_out += 1;
+            }
+        }
+    }
+    timed_out
+}
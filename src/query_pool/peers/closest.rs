@@ -66,6 +66,14 @@ pub struct FindNodeQueryConfig {
     /// single k-bucket, i.e. the `k` parameter in the Kademlia paper.
     pub num_results: usize,
 
+    /// Minimum number of results to produce.
+    ///
+    /// A query that has contacted all available peers does not terminate until
+    /// it has obtained successful results for this many of the closest peers,
+    /// waiting on unresponsive peers until the query times out instead.
+    /// Defaults to `0`.
+    pub min_results: usize,
+
     /// The timeout for a single peer.
     ///
     /// If a successful result is not reported for a peer within this timeout
@@ -80,6 +88,7 @@ impl FindNodeQueryConfig {
         Self {
             parallelism: config.query_parallelism,
             num_results: MAX_NODES_PER_BUCKET,
+            min_results: 0,
             peer_timeout: config.query_peer_timeout,
         }
     }
@@ -273,6 +282,15 @@ where
         // peers to contact, see `active_counter`).
         let mut result_counter = Some(0);
 
+        // Peers that don't respond within timeout are set to `Unresponsive`. This is done before
+        // checking the capacity, such that they make room for further peers to be contacted.
+        let timed_out = mark_unresponsive(
+            self.closest_peers.values_mut().map(|peer| &mut peer.state),
+            now,
+        );
+        debug_assert!(self.num_waiting >= timed_out);
+        self.num_waiting -= timed_out;
+
         // Check if the query is at capacity w.r.t. the allowed parallelism.
         let at_capacity = self.at_capacity();
 
@@ -294,13 +312,8 @@ where
                     }
                 }
 
-                QueryPeerState::Waiting(timeout) => {
-                    if now >= timeout {
-                        // Peers that don't respond within timeout are set to `Failed`.
-                        debug_assert!(self.num_waiting > 0);
-                        self.num_waiting -= 1;
-                        peer.state = QueryPeerState::Unresponsive;
-                    } else if at_capacity {
+                QueryPeerState::Waiting(_) => {
+                    if at_capacity {
                         // The query is still waiting for a result from a peer and is
                         // at capacity w.r.t. the maximum number of peers being waited on.
                         return QueryState::WaitingAtCapacity;
@@ -336,6 +349,10 @@ where
             // the allowed parallelism, but there are no new peers to contact
             // at the moment.
             QueryState::Waiting(None)
+        } else if result_counter.unwrap_or(0) < self.config.min_results {
+            // All available peers have been contacted, but too few have responded.
+            // Unresponsive peers may still deliver results until the query times out.
+            QueryState::Waiting(None)
         } else {
             // The query is finished because all available peers have been contacted
             // and the query is not waiting for any more results.
@@ -438,37 +455,6 @@ impl<TNodeId> QueryPeer<TNodeId> {
     }
 }
 
-/// The state of `QueryPeer` in the context of a query.
-#[derive(Debug, Copy, Clone)]
-enum QueryPeerState {
-    /// The peer has not yet been contacted.
-    ///
-    /// This is the starting state for every peer known to, or discovered by, a query.
-    NotContacted,
-
-    /// The query is waiting for a result from the peer.
-    Waiting(Instant),
-
-    /// A result was not delivered for the peer within the configured timeout.
-    ///
-    /// The peer is not taken into account for the termination conditions
-    /// of the iterator until and unless it responds.
-    Unresponsive,
-
-    /// The peer is waiting to to begin another iteration.
-    PendingIteration,
-
-    /// Obtaining a result from the peer has failed.
-    ///
-    /// This is a final state, reached as a result of a call to `on_failure`.
-    Failed,
-
-    /// A successful result from the peer has been delivered.
-    ///
-    /// This is a final state, reached as a result of a call to `on_success`.
-    Succeeded,
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -489,6 +475,7 @@ mod tests {
         let config = FindNodeQueryConfig {
             parallelism: g.gen_range(1, 10),
             num_results: g.gen_range(1, 25),
+            min_results: 0,
             peer_timeout: Duration::from_secs(g.gen_range(10, 30)),
         };
         let iterations = 1;
@@ -682,6 +669,62 @@ mod tests {
         QuickCheck::new().tests(10).quickcheck(prop as fn(_) -> _)
     }
 
+    #[test]
+    fn min_results() {
+        let peers: Vec<NodeId> = random_nodes(3).collect();
+        for min_results in 0..2 {
+            let config = FindNodeQueryConfig {
+                parallelism: 3,
+                num_results: 3,
+                min_results,
+                peer_timeout: Duration::from_secs(10),
+            };
+            let mut query = FindNodeQuery::with_config(
+                config,
+                NodeId::random().into(),
+                peers.iter().cloned().map(Key::from),
+                1,
+            );
+            let now = Instant::now();
+            for _ in 0..peers.len() {
+                match query.next(now) {
+                    QueryState::Waiting(Some(peer)) => query.on_failure(&peer.key),
+                    state => panic!("Unexpected query state: {:?}", state),
+                }
+            }
+
+            // with no peers left to contact, the query only finishes once the minimum is met
+            let finished = matches!(query.next(now), QueryState::Finished);
+            assert_eq!(finished, min_results == 0);
+        }
+    }
+
+    #[test]
+    fn timed_out_peers_free_capacity() {
+        let peers: Vec<NodeId> = random_nodes(4).collect();
+        let config = FindNodeQueryConfig {
+            parallelism: 3,
+            num_results: 4,
+            min_results: 0,
+            peer_timeout: Duration::from_secs(10),
+        };
+        let mut query = FindNodeQuery::with_config(
+            config,
+            NodeId::random().into(),
+            peers.iter().cloned().map(Key::from),
+            1,
+        );
+        let mut now = Instant::now();
+        for _ in 0..3 {
+            assert!(matches!(query.next(now), QueryState::Waiting(Some(_))));
+        }
+        assert!(matches!(query.next(now), QueryState::WaitingAtCapacity));
+
+        // the remaining peer is contacted by the same poll that times out the others
+        now += query.config.peer_timeout;
+        assert!(matches!(query.next(now), QueryState::Waiting(Some(_))));
+    }
+
     #[test]
     fn timeout() {
         fn prop(mut query: TestQuery) -> bool {
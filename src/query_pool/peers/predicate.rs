@@ -258,6 +258,15 @@ where
         // peers to contact, see `active_counter`).
         let mut result_counter = Some(0);
 
+        // Peers that don't respond within timeout are set to `Unresponsive`. This is done before
+        // checking the capacity, such that they make room for further peers to be contacted.
+        let timed_out = mark_unresponsive(
+            self.closest_peers.values_mut().map(|peer| &mut peer.state),
+            now,
+        );
+        debug_assert!(self.num_waiting >= timed_out);
+        self.num_waiting -= timed_out;
+
         // Check if the query is at capacity w.r.t. the allowed parallelism.
         let at_capacity = self.at_capacity();
 
@@ -279,13 +288,8 @@ where
                     }
                 }
 
-                QueryPeerState::Waiting(timeout) => {
-                    if now >= timeout {
-                        // Peers that don't respond within timeout are set to `Failed`.
-                        debug_assert!(self.num_waiting > 0);
-                        self.num_waiting -= 1;
-                        peer.state = QueryPeerState::Unresponsive;
-                    } else if at_capacity {
+                QueryPeerState::Waiting(_) => {
+                    if at_capacity {
                         // The query is still waiting for a result from a peer and is
                         // at capacity w.r.t. the maximum number of peers being waited on.
                         return QueryState::WaitingAtCapacity;
@@ -435,34 +439,3 @@ impl<TNodeId> QueryPeer<TNodeId> {
         }
     }
 }
-
-/// The state of `QueryPeer` in the context of a query.
-#[derive(Debug, Copy, Clone)]
-enum QueryPeerState {
-    /// The peer has not yet been contacted.
-    ///
-    /// This is the starting state for every peer known to, or discovered by, a query.
-    NotContacted,
-
-    /// The query is waiting for a result from the peer.
-    Waiting(Instant),
-
-    /// A result was not delivered for the peer within the configured timeout.
-    ///
-    /// The peer is not taken into account for the termination conditions
-    /// of the iterator until and unless it responds.
-    Unresponsive,
-
-    /// The peer is waiting to to begin another iteration.
-    PendingIteration,
-
-    /// Obtaining a result from the peer has failed.
-    ///
-    /// This is a final state, reached as a result of a call to `on_failure`.
-    Failed,
-
-    /// A successful result from the peer has been delivered.
-    ///
-    /// This is a final state, reached as a result of a call to `on_success`.
-    Succeeded,
-}
@@ -1,13 +1,42 @@
-use enr::{CombinedKey, Enr};
+use enr::{CombinedKey, CombinedPublicKey, Enr};
 use log::debug;
 use rlp::{DecoderError, RlpStream};
 use std::net::IpAddr;
+use tiny_keccak::{Hasher, Keccak};
 
-type TopicHash = [u8; 32];
+pub(crate) type TopicHash = [u8; 32];
 
 /// Wrapping type for requests.
 pub type RequestId = u64;
 
+/// Verifies the signature of an ENR.
+///
+/// `Enr::verify` accepts any well-formed signature of a secp256k1 key without checking it against
+/// the record, so the signatures of these keys are verified here.
+pub(crate) fn verify_enr(enr: &Enr<CombinedKey>) -> bool {
+    match enr.public_key() {
+        CombinedPublicKey::Secp256k1(public_key) => {
+            // the signed content is the RLP list of the sequence number and key/value pairs
+            let mut stream = RlpStream::new_list(enr.iter().count() * 2 + 1);
+            stream.append(&enr.seq());
+            for (key, value) in enr.iter() {
+                stream.append(key);
+                stream.append(value);
+            }
+            let mut digest = [0u8; 32];
+            let mut hasher = Keccak::v256();
+            hasher.update(&stream.drain());
+            hasher.finalize(&mut digest);
+
+            let message = enr::secp256k1::Message::parse(&digest);
+            enr::secp256k1::Signature::parse_slice(enr.signature())
+                .map(|signature| enr::secp256k1::verify(&message, &signature, &public_key))
+                .unwrap_or(false)
+        }
+        CombinedPublicKey::Ed25519(_) => enr.verify(),
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 /// A combined type representing requests and responses.
 pub enum Message {
@@ -44,15 +73,32 @@ pub enum RequestBody {
     },
     /// A FINDNODE request.
     FindNode {
-        /// The distance of peers we expect to be returned in the response.
-        distance: u64,
+        /// The distances of peers we expect to be returned in the response. A distance of 0
+        /// requests the ENR of the recipient.
+        distances: Vec<u64>,
+    },
+    /// A request for the ENR of the recipient. This is sent as a FINDNODE request at distance 0,
+    /// and a received FINDNODE request at distance 0 is decoded as this request.
+    GetEnr,
+    /// A REGTOPIC request, advertising the sender for a topic. It is answered with a TICKET
+    /// unless it carries a ticket that has become valid, in which case it is confirmed.
+    RegTopic {
+        /// The topic to be advertised for.
+        topic: TopicHash,
+        /// The ENR of the advertiser, which must be the sender.
+        enr: Enr<CombinedKey>,
+        /// A ticket previously issued by the recipient, or empty if none is held.
+        ticket: Vec<u8>,
     },
-    /// A TICKET request.
-    Ticket { topic: TopicHash },
-    /// A REGISTERTOPIC request.
-    RegisterTopic { ticket: Vec<u8> },
     /// A TOPICQUERY request.
     TopicQuery { topic: TopicHash },
+    /// A TALKREQ request, carrying an application-level request for the given protocol.
+    TalkReq {
+        /// The protocol the request is intended for.
+        protocol: Vec<u8>,
+        /// The opaque request payload.
+        request: Vec<u8>,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -73,23 +119,55 @@ pub enum ResponseBody {
         /// A list of ENR's returned by the responder.
         nodes: Vec<Enr<CombinedKey>>,
     },
+    /// A TICKET response to a REGTOPIC request.
     Ticket {
+        /// The opaque ticket, to be sent in the next REGTOPIC request.
         ticket: Vec<u8>,
+        /// The number of seconds to wait before the ticket can be used.
         wait_time: u64,
     },
-    RegisterTopic {
-        registered: bool,
+    /// A REGCONFIRMATION response, confirming that a REGTOPIC request was accepted.
+    RegConfirmation {
+        /// The topic the sender is now advertised for.
+        topic: TopicHash,
+    },
+    /// A TALKRESP response. The response is empty if the protocol is unknown to the responder.
+    TalkResp {
+        /// The opaque response payload.
+        response: Vec<u8>,
     },
 }
 
+/// The PONG response to a PING request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pong {
+    /// The current ENR sequence number of the responder.
+    pub enr_seq: u64,
+    /// Our external IP address as observed by the responder.
+    pub ip: IpAddr,
+    /// Our external UDP port as observed by the responder.
+    pub port: u16,
+}
+
 impl Request {
     pub fn msg_type(&self) -> u8 {
         match self.body {
             RequestBody::Ping { .. } => 1,
-            RequestBody::FindNode { .. } => 3,
-            RequestBody::Ticket { .. } => 5,
-            RequestBody::RegisterTopic { .. } => 7,
+            RequestBody::FindNode { .. } | RequestBody::GetEnr => 3,
+            RequestBody::RegTopic { .. } => 7,
             RequestBody::TopicQuery { .. } => 9,
+            RequestBody::TalkReq { .. } => 10,
+        }
+    }
+
+    /// The name of the request type, as used in the metrics.
+    pub fn msg_name(&self) -> &'static str {
+        match self.body {
+            RequestBody::Ping { .. } => "PING",
+            RequestBody::FindNode { .. } | RequestBody::GetEnr => "FINDNODE",
+            RequestBody::RegTopic { .. } => "REGTOPIC",
+            RequestBody::TopicQuery { .. } => "TOPICQUERY",
+            RequestBody::TalkReq { .. } => "TALKREQ",
         }
     }
 
@@ -108,27 +186,35 @@ impl Request {
                 buf.extend_from_slice(&s.drain());
                 buf
             }
-            RequestBody::FindNode { distance } => {
+            RequestBody::FindNode { distances } => {
                 let mut s = RlpStream::new();
                 s.begin_list(2);
                 s.append(id);
-                s.append(&distance);
+                // a single distance is encoded as a value, such that it is understood by peers
+                // that only support a single distance
+                if let [distance] = distances.as_slice() {
+                    s.append(distance);
+                } else {
+                    s.append_list(&distances);
+                }
                 buf.extend_from_slice(&s.drain());
                 buf
             }
-            RequestBody::Ticket { topic } => {
+            RequestBody::GetEnr => {
                 let mut s = RlpStream::new();
                 s.begin_list(2);
                 s.append(id);
-                s.append(&topic.to_vec());
+                s.append(&0u64);
                 buf.extend_from_slice(&s.drain());
                 buf
             }
-            RequestBody::RegisterTopic { ticket } => {
+            RequestBody::RegTopic { topic, enr, ticket } => {
                 let mut s = RlpStream::new();
-                s.begin_list(2);
+                s.begin_list(4);
                 s.append(id);
-                s.append(&ticket.to_vec());
+                s.append(&topic.to_vec());
+                s.append(&enr);
+                s.append(&ticket);
                 buf.extend_from_slice(&s.drain());
                 buf
             }
@@ -140,6 +226,15 @@ impl Request {
                 buf.extend_from_slice(&s.drain());
                 buf
             }
+            RequestBody::TalkReq { protocol, request } => {
+                let mut s = RlpStream::new();
+                s.begin_list(3);
+                s.append(id);
+                s.append(&protocol);
+                s.append(&request);
+                buf.extend_from_slice(&s.drain());
+                buf
+            }
         }
     }
 }
@@ -150,7 +245,19 @@ impl Response {
             ResponseBody::Ping { .. } => 2,
             ResponseBody::Nodes { .. } => 4,
             ResponseBody::Ticket { .. } => 6,
-            ResponseBody::RegisterTopic { .. } => 8,
+            ResponseBody::RegConfirmation { .. } => 8,
+            ResponseBody::TalkResp { .. } => 11,
+        }
+    }
+
+    /// The name of the response type, as used in the metrics.
+    pub fn msg_name(&self) -> &'static str {
+        match self.body {
+            ResponseBody::Ping { .. } => "PONG",
+            ResponseBody::Nodes { .. } => "NODES",
+            ResponseBody::Ticket { .. } => "TICKET",
+            ResponseBody::RegConfirmation { .. } => "REGCONFIRMATION",
+            ResponseBody::TalkResp { .. } => "TALKRESP",
         }
     }
 
@@ -166,23 +273,14 @@ impl Response {
             }
             ResponseBody::Nodes { .. } => match req {
                 RequestBody::FindNode { .. } => true,
+                RequestBody::GetEnr => true,
                 RequestBody::TopicQuery { .. } => true,
                 _ => false,
             },
-            ResponseBody::Ticket { .. } => {
-                if let RequestBody::Ticket { .. } = req {
-                    true
-                } else {
-                    false
-                }
-            }
-            ResponseBody::RegisterTopic { .. } => {
-                if let RequestBody::TopicQuery { .. } = req {
-                    true
-                } else {
-                    false
-                }
+            ResponseBody::Ticket { .. } | ResponseBody::RegConfirmation { .. } => {
+                matches!(req, RequestBody::RegTopic { .. })
             }
+            ResponseBody::TalkResp { .. } => matches!(req, RequestBody::TalkReq { .. }),
         }
     }
 
@@ -233,11 +331,19 @@ impl Response {
                 buf.extend_from_slice(&s.drain());
                 buf
             }
-            ResponseBody::RegisterTopic { registered } => {
+            ResponseBody::RegConfirmation { topic } => {
                 let mut s = RlpStream::new();
                 s.begin_list(2);
                 s.append(id);
-                s.append(&registered);
+                s.append(&topic.to_vec());
+                buf.extend_from_slice(&s.drain());
+                buf
+            }
+            ResponseBody::TalkResp { response } => {
+                let mut s = RlpStream::new();
+                s.begin_list(2);
+                s.append(id);
+                s.append(&response);
                 buf.extend_from_slice(&s.drain());
                 buf
             }
@@ -285,8 +391,11 @@ impl std::fmt::Display for ResponseBody {
             ResponseBody::Ticket { ticket, wait_time } => {
                 write!(f, "TICKET: Ticket: {:?}, Wait time: {}", ticket, wait_time)
             }
-            ResponseBody::RegisterTopic { registered } => {
-                write!(f, "REGTOPIC: Registered: {}", registered)
+            ResponseBody::RegConfirmation { topic } => {
+                write!(f, "REGCONFIRMATION: topic: {}", hex::encode(topic))
+            }
+            ResponseBody::TalkResp { response } => {
+                write!(f, "TALKRESP: response: {}", hex::encode(response))
             }
         }
     }
@@ -302,14 +411,24 @@ impl std::fmt::Display for RequestBody {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             RequestBody::Ping { enr_seq } => write!(f, "PING: enr_seq: {}", enr_seq),
-            RequestBody::FindNode { distance } => {
-                write!(f, "FINDNODE Request: distance: {}", distance)
+            RequestBody::FindNode { distances } => {
+                write!(f, "FINDNODE Request: distances: {:?}", distances)
             }
-            RequestBody::Ticket { topic } => write!(f, "TICKET: topic: {:?}", topic),
+            RequestBody::GetEnr => write!(f, "FINDNODE Request: distance: 0"),
             RequestBody::TopicQuery { topic } => write!(f, "TOPICQUERY: topic: {:?}", topic),
-            RequestBody::RegisterTopic { ticket } => {
-                write!(f, "TOPICQUERY: ticket: {}", hex::encode(ticket))
-            }
+            RequestBody::RegTopic { topic, enr, ticket } => write!(
+                f,
+                "REGTOPIC: topic: {}, enr: {}, ticket: {}",
+                hex::encode(topic),
+                enr,
+                hex::encode(ticket)
+            ),
+            RequestBody::TalkReq { protocol, request } => write!(
+                f,
+                "TALKREQ: protocol: {}, request: {}",
+                hex::encode(protocol),
+                hex::encode(request)
+            ),
         }
     }
 }
@@ -403,12 +522,23 @@ impl Message {
                     );
                     return Err(DecoderError::RlpIncorrectListLen);
                 }
-                Message::Request(Request {
-                    id,
-                    body: RequestBody::FindNode {
-                        distance: rlp.val_at::<u64>(1)?,
-                    },
-                })
+                // peers may request a single distance or a list of distances
+                let distances = if rlp.at(1)?.is_list() {
+                    rlp.list_at::<u64>(1)?
+                } else {
+                    vec![rlp.val_at::<u64>(1)?]
+                };
+                if distances.is_empty() {
+                    debug!("FindNode Request has no distances");
+                    return Err(DecoderError::Custom("No distances"));
+                }
+                // a single distance of 0 requests the ENR of the recipient
+                let body = if distances == [0] {
+                    RequestBody::GetEnr
+                } else {
+                    RequestBody::FindNode { distances }
+                };
+                Message::Request(Request { id, body })
             }
             4 => {
                 // NodesResponse
@@ -437,30 +567,6 @@ impl Message {
                     },
                 })
             }
-            5 => {
-                // TicketRequest
-                if list_len != 2 {
-                    debug!(
-                        "Ticket Request has an invalid RLP list length. Expected 2, found {}",
-                        list_len
-                    );
-                    return Err(DecoderError::RlpIncorrectListLen);
-                }
-                let topic = {
-                    let topic_bytes = rlp.val_at::<Vec<u8>>(1)?;
-                    if topic_bytes.len() > 32 {
-                        debug!("Ticket Request has a topic greater than 32 bytes");
-                        return Err(DecoderError::RlpIsTooBig);
-                    }
-                    let mut topic = [0u8; 32];
-                    topic[32 - topic_bytes.len()..].copy_from_slice(&topic_bytes);
-                    topic
-                };
-                Message::Request(Request {
-                    id,
-                    body: RequestBody::Ticket { topic },
-                })
-            }
             6 => {
                 // TicketResponse
                 if list_len != 3 {
@@ -478,27 +584,36 @@ impl Message {
                 })
             }
             7 => {
-                // RegisterTopicRequest
-                if list_len != 2 {
-                    debug!("RegisterTopic Request has an invalid RLP list length. Expected 2, found {}", list_len);
+                // RegTopicRequest
+                if list_len != 4 {
+                    debug!(
+                        "RegTopic Request has an invalid RLP list length. Expected 4, found {}",
+                        list_len
+                    );
                     return Err(DecoderError::RlpIncorrectListLen);
                 }
-                let ticket = rlp.val_at::<Vec<u8>>(1)?;
                 Message::Request(Request {
                     id,
-                    body: RequestBody::RegisterTopic { ticket },
+                    body: RequestBody::RegTopic {
+                        topic: decode_topic(&rlp, 1)?,
+                        enr: rlp.val_at::<Enr<CombinedKey>>(2)?,
+                        ticket: rlp.val_at::<Vec<u8>>(3)?,
+                    },
                 })
             }
             8 => {
-                // RegisterTopicResponse
+                // RegConfirmationResponse
                 if list_len != 2 {
-                    debug!("RegisterTopic Response has an invalid RLP list length. Expected 2, found {}", list_len);
+                    debug!(
+                        "RegConfirmation Response has an invalid RLP list length. Expected 2, found {}",
+                        list_len
+                    );
                     return Err(DecoderError::RlpIncorrectListLen);
                 }
                 Message::Response(Response {
                     id,
-                    body: ResponseBody::RegisterTopic {
-                        registered: rlp.val_at::<bool>(1)?,
+                    body: ResponseBody::RegConfirmation {
+                        topic: decode_topic(&rlp, 1)?,
                     },
                 })
             }
@@ -511,19 +626,44 @@ impl Message {
                     );
                     return Err(DecoderError::RlpIncorrectListLen);
                 }
-                let topic = {
-                    let topic_bytes = rlp.val_at::<Vec<u8>>(1)?;
-                    if topic_bytes.len() > 32 {
-                        debug!("Ticket Request has a topic greater than 32 bytes");
-                        return Err(DecoderError::RlpIsTooBig);
-                    }
-                    let mut topic = [0u8; 32];
-                    topic[32 - topic_bytes.len()..].copy_from_slice(&topic_bytes);
-                    topic
-                };
                 Message::Request(Request {
                     id,
-                    body: RequestBody::TopicQuery { topic },
+                    body: RequestBody::TopicQuery {
+                        topic: decode_topic(&rlp, 1)?,
+                    },
+                })
+            }
+            10 => {
+                // TalkRequest
+                if list_len != 3 {
+                    debug!(
+                        "Talk Request has an invalid RLP list length. Expected 3, found {}",
+                        list_len
+                    );
+                    return Err(DecoderError::RlpIncorrectListLen);
+                }
+                Message::Request(Request {
+                    id,
+                    body: RequestBody::TalkReq {
+                        protocol: rlp.val_at::<Vec<u8>>(1)?,
+                        request: rlp.val_at::<Vec<u8>>(2)?,
+                    },
+                })
+            }
+            11 => {
+                // TalkResponse
+                if list_len != 2 {
+                    debug!(
+                        "Talk Response has an invalid RLP list length. Expected 2, found {}",
+                        list_len
+                    );
+                    return Err(DecoderError::RlpIncorrectListLen);
+                }
+                Message::Response(Response {
+                    id,
+                    body: ResponseBody::TalkResp {
+                        response: rlp.val_at::<Vec<u8>>(1)?,
+                    },
                 })
             }
             _ => {
@@ -535,6 +675,19 @@ impl Message {
     }
 }
 
+/// Decodes the topic hash at the given index of an RLP list. Shorter topics are left-padded with
+/// zeros.
+fn decode_topic(rlp: &rlp::Rlp<'_>, index: usize) -> Result<TopicHash, DecoderError> {
+    let topic_bytes = rlp.val_at::<Vec<u8>>(index)?;
+    if topic_bytes.len() > 32 {
+        debug!("RPC message has a topic greater than 32 bytes");
+        return Err(DecoderError::RlpIsTooBig);
+    }
+    let mut topic = [0u8; 32];
+    topic[32 - topic_bytes.len()..].copy_from_slice(&topic_bytes);
+    Ok(topic)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -563,7 +716,9 @@ mod tests {
         let distance = 256;
         let message = Message::Request(Request {
             id,
-            body: RequestBody::FindNode { distance },
+            body: RequestBody::FindNode {
+                distances: vec![distance],
+            },
         });
 
         // expected hex output
@@ -572,49 +727,6 @@ mod tests {
         assert_eq!(message.encode(), expected_output);
     }
 
-    #[test]
-    fn ref_test_encode_request_ticket() {
-        // reference input
-        let id = 1;
-        let hash_bytes =
-            hex::decode("fb757dc581730490a1d7a00deea65e9b1936924caaea8f44d476014856b68736")
-                .unwrap();
-
-        // expected hex output
-        let expected_output =
-            hex::decode("05e201a0fb757dc581730490a1d7a00deea65e9b1936924caaea8f44d476014856b68736")
-                .unwrap();
-
-        let mut topic_hash = [0; 32];
-        topic_hash.copy_from_slice(&hash_bytes);
-
-        let message = Message::Request(Request {
-            id,
-            body: RequestBody::Ticket { topic: topic_hash },
-        });
-        assert_eq!(message.encode(), expected_output);
-    }
-
-    #[test]
-    fn ref_test_encode_request_register_topic() {
-        // reference input
-        let id = 1;
-        let ticket =
-            hex::decode("fb757dc581730490a1d7a00deea65e9b1936924caaea8f44d476014856b68736")
-                .unwrap();
-
-        // expected hex output
-        let expected_output =
-            hex::decode("07e201a0fb757dc581730490a1d7a00deea65e9b1936924caaea8f44d476014856b68736")
-                .unwrap();
-
-        let message = Message::Request(Request {
-            id,
-            body: RequestBody::RegisterTopic { ticket },
-        });
-        assert_eq!(message.encode(), expected_output);
-    }
-
     #[test]
     fn ref_test_encode_request_topic_query() {
         // reference input
@@ -767,16 +879,18 @@ mod tests {
     }
 
     #[test]
-    fn ref_test_encode_response_register_topic() {
+    fn ref_test_encode_response_reg_confirmation() {
         // reference input
         let id = 1;
-        let registered = true;
+        let topic = [0; 32]; // all 0's
 
         // expected hex output
-        let expected_output = hex::decode("08c20101").unwrap();
+        let expected_output =
+            hex::decode("08e201a00000000000000000000000000000000000000000000000000000000000000000")
+                .unwrap();
         let message = Message::Response(Response {
             id,
-            body: ResponseBody::RegisterTopic { registered },
+            body: ResponseBody::RegConfirmation { topic },
         });
         assert_eq!(message.encode(), expected_output);
     }
@@ -811,11 +925,32 @@ mod tests {
         assert_eq!(request, decoded);
     }
 
+    #[test]
+    fn encode_decode_get_enr_request() {
+        let request = Message::Request(Request {
+            id: 1,
+            body: RequestBody::GetEnr,
+        });
+        let find_node = Message::Request(Request {
+            id: 1,
+            body: RequestBody::FindNode { distances: vec![0] },
+        });
+
+        // sent as a FINDNODE request at distance 0
+        let encoded = request.clone().encode();
+        assert_eq!(encoded, find_node.encode());
+        let decoded = Message::decode(encoded).unwrap();
+
+        assert_eq!(request, decoded);
+    }
+
     #[test]
     fn encode_decode_find_node_request() {
         let request = Message::Request(Request {
             id: 1,
-            body: RequestBody::FindNode { distance: 1337 },
+            body: RequestBody::FindNode {
+                distances: vec![1337],
+            },
         });
 
         let encoded = request.clone().encode();
@@ -824,6 +959,37 @@ mod tests {
         assert_eq!(request, decoded);
     }
 
+    #[test]
+    fn encode_decode_find_node_distances_request() {
+        let request = Message::Request(Request {
+            id: 1,
+            body: RequestBody::FindNode {
+                distances: vec![0, 255, 256],
+            },
+        });
+
+        let encoded = request.clone().encode();
+        let decoded = Message::decode(encoded).unwrap();
+        assert_eq!(request, decoded);
+
+        // a single distance may also be sent as a list
+        let mut s = RlpStream::new();
+        s.begin_list(2);
+        s.append(&1u64);
+        s.append_list(&[256u64]);
+        let mut encoded = vec![3];
+        encoded.extend_from_slice(&s.drain());
+        assert_eq!(
+            Message::decode(encoded).unwrap(),
+            Message::Request(Request {
+                id: 1,
+                body: RequestBody::FindNode {
+                    distances: vec![256]
+                },
+            })
+        );
+    }
+
     #[test]
     fn encode_decode_nodes_response() {
         let key = CombinedKey::generate_secp256k1();
@@ -858,16 +1024,20 @@ mod tests {
     }
 
     #[test]
-    fn encode_decode_ticket_request() {
-        let request = Message::Request(Request {
-            id: 1,
-            body: RequestBody::Ticket { topic: [17u8; 32] },
-        });
-
-        let encoded = request.clone().encode();
-        let decoded = Message::decode(encoded).unwrap();
+    fn verify_enr_rejects_invalid_signature() {
+        let key = CombinedKey::generate_secp256k1();
+        let enr = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(500)
+            .build(&key)
+            .unwrap();
+        assert!(verify_enr(&enr));
 
-        assert_eq!(request, decoded);
+        // corrupt the signature, which follows the list and signature headers
+        let mut encoded = rlp::encode(&enr);
+        encoded[10] ^= 0xff;
+        let tampered = rlp::decode::<Enr<CombinedKey>>(&encoded).unwrap();
+        assert!(!verify_enr(&tampered));
     }
 
     #[test]
@@ -887,10 +1057,14 @@ mod tests {
     }
 
     #[test]
-    fn encode_decode_register_topic_request() {
+    fn encode_decode_reg_topic_request() {
+        let key = CombinedKey::generate_secp256k1();
+        let enr = EnrBuilder::new("v4").build(&key).unwrap();
         let request = Message::Request(Request {
             id: 1,
-            body: RequestBody::RegisterTopic {
+            body: RequestBody::RegTopic {
+                topic: [17u8; 32],
+                enr,
                 ticket: vec![1, 2, 3, 4, 5],
             },
         });
@@ -902,10 +1076,10 @@ mod tests {
     }
 
     #[test]
-    fn encode_decode_register_topic_response() {
+    fn encode_decode_reg_confirmation_response() {
         let request = Message::Response(Response {
             id: 0,
-            body: ResponseBody::RegisterTopic { registered: true },
+            body: ResponseBody::RegConfirmation { topic: [17u8; 32] },
         });
 
         let encoded = request.clone().encode();
@@ -926,4 +1100,35 @@ mod tests {
 
         assert_eq!(request, decoded);
     }
+
+    #[test]
+    fn encode_decode_talk_request() {
+        let request = Message::Request(Request {
+            id: 1,
+            body: RequestBody::TalkReq {
+                protocol: b"test".to_vec(),
+                request: vec![1, 2, 3],
+            },
+        });
+
+        let encoded = request.clone().encode();
+        let decoded = Message::decode(encoded).unwrap();
+
+        assert_eq!(request, decoded);
+    }
+
+    #[test]
+    fn encode_decode_talk_response() {
+        let request = Message::Response(Response {
+            id: 1,
+            body: ResponseBody::TalkResp {
+                response: vec![1, 2, 3],
+            },
+        });
+
+        let encoded = request.clone().encode();
+        let decoded = Message::decode(encoded).unwrap();
+
+        assert_eq!(request, decoded);
+    }
 }
@@ -13,54 +13,159 @@
 //! supported as the ECDH procedure isn't specified in the specification. Therefore, only
 //! secp256k1 keys are supported currently.
 
-use self::ip_vote::IpVote;
+use self::bootstrap::Bootstrap;
+use self::circuit_breaker::CircuitBreakers;
+use self::crawl::{Crawl, CrawlId};
+use self::findnode_dampener::FindNodeDampener;
+pub(crate) use self::ip_vote::IpVote;
+pub(crate) use self::peer_scores::PeerScores;
+pub use self::query_info::QueryCallback;
 use self::query_info::{QueryInfo, QueryType};
-use crate::error::RequestError;
-use crate::handler::{Handler, HandlerRequest, HandlerResponse};
-use crate::kbucket::{self, ip_limiter, KBucketsTable, NodeStatus};
+use self::scheduler::{Priority, Scheduler};
+pub(crate) use self::topic_table::{Registration, TopicTable, TOPIC_AD_LIFETIME};
+use crate::error::{QueryError, RequestError};
+use crate::handler::{Handler, HandlerRequest, HandlerResponse, SessionInfo};
+use crate::kbucket::{self, ip_limiter, KBucketsTable, NodeStatus, MAX_NODES_PER_BUCKET};
+use crate::metrics::METRICS;
 use crate::node_info::{NodeAddress, NodeContact};
 use crate::query_pool::{
-    FindNodeQueryConfig, PredicateQueryConfig, QueryId, QueryPool, QueryPoolState, TargetKey,
+    FindNodeQueryConfig, PredicateQueryConfig, Query, QueryId, QueryPool, QueryPoolState, TargetKey,
 };
 use crate::rpc;
 use crate::socket::MAX_PACKET_SIZE;
 use crate::Enr;
-use crate::{Discv5Config, Discv5Event};
+use crate::{Discv5Config, Discv5Event, RemovalReason};
 use enr::{CombinedKey, NodeId};
 use fnv::FnvHashMap;
 use futures::prelude::*;
 use log::{debug, error, info, trace, warn};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use rpc::*;
-use std::collections::HashMap;
-use std::net::SocketAddr;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::panic::AssertUnwindSafe;
+use std::sync::{atomic::Ordering, Arc};
 use std::task::Poll;
-use tokio::sync::{mpsc, oneshot};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, Notify};
 use tokio::time::Interval;
 
+/// The minimum time between progress events of a query.
+const QUERY_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The interval at which queries are checked for timeouts when no other events occur.
+const QUERY_TIMEOUT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The maximum multiple of the ping interval that a peer failing to respond to PINGs is backed
+/// off to.
+const MAX_PING_BACKOFF: u32 = 8;
+
+/// The minimum time between requests for the ENR of a peer that advertises a newer one.
+const ENR_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The maximum number of requests queued for the handler while its channel is full.
+const MAX_QUEUED_HANDLER_REQUESTS: usize = 1000;
+
+mod bootstrap;
+mod circuit_breaker;
+mod crawl;
+mod findnode_dampener;
 mod ip_vote;
+mod peer_scores;
 mod query_info;
+mod refresh;
+mod scheduler;
+mod topic_table;
 //TODO: Update service tests
 //mod test;
 
 /// The types of requests to send to the Discv5 service.
 pub enum ServiceRequest {
-    StartQuery(QueryKind, oneshot::Sender<Vec<Enr>>),
-    FindEnr(NodeContact, oneshot::Sender<Option<Enr>>),
+    StartQuery(QueryKind, QueryCallback),
+    /// Requests the ENR of a node. If a maximum age is given and the stored ENR of the node was
+    /// confirmed to be current within it, the stored ENR is returned without a request.
+    FindEnr(NodeContact, Option<Duration>, oneshot::Sender<Option<Enr>>),
+    /// Finds the ENR of a node, requesting it from the node if it is only known from an ongoing
+    /// query.
+    FindEnrById(NodeId, oneshot::Sender<Option<Enr>>),
+    /// Finds the ENR of a node from the routing table or the untrusted ENRs of ongoing queries,
+    /// without sending any requests.
+    LocalEnrOf(NodeId, oneshot::Sender<Option<Enr>>),
+    /// Pings a node, returning its PONG response.
+    Ping(Enr, oneshot::Sender<Result<Pong, RequestError>>),
+    /// Sends a TOPICQUERY request to a node, returning the advertisers of the topic it knows of.
+    TopicQuery(
+        NodeContact,
+        TopicHash,
+        oneshot::Sender<Result<Vec<Enr>, RequestError>>,
+    ),
+    /// Sends a user-built request to a node, returning the raw response.
+    Request(
+        NodeContact,
+        RequestBody,
+        oneshot::Sender<Result<Response, RequestError>>,
+    ),
     RequestEventStream(oneshot::Sender<mpsc::Receiver<Discv5Event>>),
+    /// Requests information about the sessions established by the handler.
+    SessionsInfo(oneshot::Sender<Vec<SessionInfo>>),
+    /// Requests the number of sessions established by the handler.
+    SessionCount(oneshot::Sender<usize>),
+    /// Crawls the network from the given seeds, sending newly discovered ENRs to the channel.
+    Crawl(Vec<Enr>, mpsc::Sender<Enr>),
+    /// A node has been removed from the routing table outside of the service.
+    NodeRemoved(NodeId, RemovalReason),
+    /// Requests the connected peers in the routing table, along with the time they are next
+    /// pinged.
+    ConnectedPeersInfo(oneshot::Sender<Vec<(Enr, NodeStatus, Instant)>>),
+    /// Requests the current votes for our external socket.
+    IpVoteTally(oneshot::Sender<Vec<(SocketAddr, usize)>>),
+    /// Requests the ids of the active queries.
+    ActiveQueryIds(oneshot::Sender<Vec<QueryId>>),
+    /// Cancels a query, returning whether it was active.
+    CancelQuery(QueryId, oneshot::Sender<bool>),
+    /// Sets the TCP socket of the local ENR, returning whether the ENR changed. Connected peers
+    /// are pinged such that they request the new ENR.
+    UpdateEnrTcp(SocketAddr, oneshot::Sender<bool>),
+    /// Refreshes the routing table by querying a random target at each log2 distance.
+    Bootstrap,
 }
 
 use crate::discv5::PERMIT_BAN_LIST;
 
+/// A handler for TALKREQ requests of a registered protocol. It is given the request payload and
+/// the id of the requesting node, and returns the TALKRESP payload.
+pub type TalkHandler = Arc<dyn Fn(Vec<u8>, NodeId) -> Vec<u8> + Send + Sync>;
+
+/// Options overriding the configuration for a single query.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct QueryOptions {
+    /// Overrides the `query_parallelism` of the configuration.
+    pub parallelism: Option<usize>,
+    /// Overrides the `query_peer_timeout` of the configuration.
+    pub peer_timeout: Option<Duration>,
+}
+
+/// The kind of query to start.
 pub enum QueryKind {
     FindNode {
         target_node: NodeId,
+        options: QueryOptions,
+    },
+    /// A `FindNode` query that waits for at least `min_results` peers, until it times out.
+    FindNodeMin {
+        target_node: NodeId,
+        min_results: usize,
+    },
+    /// A `FindNode` query requesting the given log2 distances from each peer.
+    FindNodeDistances {
+        target_node: NodeId,
+        distances: Vec<u64>,
     },
     Predicate {
         target_node: NodeId,
         target_peer_no: usize,
         predicate: Box<dyn Fn(&Enr) -> bool + Send>,
+        options: QueryOptions,
     },
 }
 
@@ -84,28 +189,130 @@ pub struct Service {
     /// query.
     active_requests: FnvHashMap<RequestId, ActiveRequest>,
 
+    /// The ENR requests with callbacks that are in flight, by node. Further requests for the
+    /// same ENR share the response of the request in flight.
+    pending_enr_requests: HashMap<NodeId, RequestId>,
+
     /// Keeps track of the number of responses received from a NODES response.
     active_nodes_responses: HashMap<NodeId, NodesResponse>,
 
     /// A map of votes nodes have made about our external IP address. We accept the majority.
-    ip_votes: Option<IpVote>,
+    ip_votes: Option<Arc<RwLock<IpVote>>>,
+
+    /// The set of nodes that have been added as bootnodes.
+    bootnodes: Arc<RwLock<HashSet<NodeId>>>,
+
+    /// The handlers of the registered TALKREQ protocols.
+    talk_protocols: Arc<Mutex<HashMap<Vec<u8>, TalkHandler>>>,
+
+    /// The advertisers of topics, returned to TOPICQUERY requests.
+    topic_table: Arc<RwLock<TopicTable>>,
+
+    /// The scores of peers, used to prefer reliable peers when starting queries.
+    peer_scores: Arc<RwLock<PeerScores>>,
+
+    /// Notified when the local ENR has been updated outside of the service.
+    local_enr_updated: Arc<Notify>,
+
+    /// Circuit breakers that pause contacting persistently unreachable bootnodes.
+    circuit_breakers: CircuitBreakers,
+
+    /// Dampens responses to repeated FINDNODE requests, if configured.
+    findnode_dampener: Option<FindNodeDampener>,
+
+    /// Crawls of the network that are in progress.
+    crawls: HashMap<CrawlId, Crawl>,
+
+    /// The id of the next crawl to be started.
+    next_crawl_id: CrawlId,
+
+    /// The refresh of the routing table in progress, if any.
+    bootstrap: Option<Bootstrap>,
 
     /// The channel to send messages to the handler.
     handler_send: mpsc::Sender<HandlerRequest>,
 
+    /// The requests to the handler that are waiting for capacity in its channel.
+    handler_queue: Scheduler<HandlerRequest>,
+
+    /// The RPC requests that were dropped as the handler queue was full, which are yet to be
+    /// failed.
+    dropped_requests: Vec<RequestId>,
+
+    /// The channel tasks spawned by the service send their requests to the handler on, such
+    /// that the requests are scheduled along with those of the service.
+    deferred_send: mpsc::Sender<HandlerRequest>,
+
+    /// The requests to the handler from tasks spawned by the service.
+    deferred_recv: mpsc::Receiver<HandlerRequest>,
+
+    /// Whether the handler has been reported as unresponsive, as requests to it have been queued
+    /// for longer than the `handler_unresponsive_timeout`.
+    handler_unresponsive: bool,
+
     /// The channel to receive messages from the handler.
     handler_recv: mpsc::Receiver<HandlerResponse>,
 
     /// The exit channel to shutdown the handler.
-    handler_exit: Option<oneshot::Sender<()>>,
+    handler_exit: Option<oneshot::Sender<oneshot::Sender<()>>>,
 
     discv5_recv: mpsc::Receiver<ServiceRequest>,
 
-    exit: oneshot::Receiver<()>,
+    /// The exit channel to shutdown the service. Completion is confirmed on the provided channel
+    /// once the handler has shutdown.
+    exit: oneshot::Receiver<oneshot::Sender<()>>,
     /// An interval to check and ping all nodes in the routing table.
     ping_heartbeat: Interval,
 
-    event_stream: Option<mpsc::Sender<Discv5Event>>,
+    /// The interval between background refreshes of the routing table.
+    refresh_heartbeat: Interval,
+
+    /// An interval to wake the service, such that queries waiting on no peers are timed out.
+    query_heartbeat: Interval,
+
+    /// The time at which connected peers are next pinged by the heartbeat.
+    next_ping: Instant,
+
+    /// Connected peers that have failed to respond to their latest PINGs, which are pinged less
+    /// often until they respond.
+    ping_backoffs: HashMap<NodeId, PingBackoff>,
+
+    /// The time the ENR of each peer was last requested due to a newer advertised sequence
+    /// number, limiting these requests to one per `ENR_REFRESH_INTERVAL`.
+    enr_refreshes: HashMap<NodeId, Instant>,
+
+    /// The time each disconnected node in the routing table was first seen disconnected, used to
+    /// remove nodes that exceed the `disconnected_node_timeout`.
+    disconnected_since: HashMap<NodeId, Instant>,
+
+    /// The time at which each node waiting to be inserted into a full bucket is due to replace
+    /// the disconnected node it is pending on.
+    pending_evictions: HashMap<NodeId, Instant>,
+
+    /// The time the stored ENR of each peer was last confirmed to be current by the peer, either
+    /// by establishing a session or by advertising the same sequence number.
+    enr_confirmed: HashMap<NodeId, Instant>,
+
+    /// The subscribers of the event stream.
+    event_streams: Vec<EventStream>,
+}
+
+/// A subscriber of the event stream.
+struct EventStream {
+    /// The channel events are sent on.
+    sender: mpsc::Sender<Discv5Event>,
+    /// The latest local ENR update that could not be sent as the channel was full. It is
+    /// delivered once the channel has capacity, such that subscribers always learn of the latest
+    /// local ENR.
+    pending_enr: Option<Enr>,
+}
+
+/// The ping backoff of a connected peer that has failed to respond to PINGs.
+struct PingBackoff {
+    /// The number of consecutive PINGs the peer has failed.
+    failures: u32,
+    /// The number of ping heartbeats to skip before the peer is pinged again.
+    skipped_heartbeats: u32,
 }
 
 /// Active RPC request awaiting a response from the handler.
@@ -116,9 +323,60 @@ struct ActiveRequest {
     pub request_body: RequestBody,
     /// The query ID if the request was related to a query.
     pub query_id: Option<QueryId>,
-    /// Channel callback if this request was from a user level request. The only kind is for an ENR
-    /// request.
-    pub callback: Option<oneshot::Sender<Option<Enr>>>,
+    /// The crawl ID if the request was related to a crawl.
+    pub crawl_id: Option<CrawlId>,
+    /// Channel callback if this request was from a user level request.
+    pub callback: Option<CallbackResponse>,
+    /// When the request was sent. This is taken by the first response to the request.
+    pub sent_at: Option<Instant>,
+}
+
+impl ActiveRequest {
+    /// A request that is not related to a query, crawl or user level request.
+    fn new(contact: NodeContact, request_body: RequestBody) -> Self {
+        ActiveRequest {
+            contact,
+            request_body,
+            query_id: None,
+            crawl_id: None,
+            callback: None,
+            sent_at: None,
+        }
+    }
+}
+
+/// The callback of a user level request.
+enum CallbackResponse {
+    /// A request for a node's ENR, shared by all callers that requested it concurrently.
+    Enr(Vec<oneshot::Sender<Option<Enr>>>),
+    /// A user-built request, which is returned the raw response.
+    Response(oneshot::Sender<Result<Response, RequestError>>),
+    /// A user level PING request.
+    Pong(oneshot::Sender<Result<Pong, RequestError>>),
+    /// A request answered by NODES, which is returned the ENRs of all parts of the response.
+    Nodes(oneshot::Sender<Result<Vec<Enr>, RequestError>>),
+}
+
+impl CallbackResponse {
+    /// Informs the user that the request failed.
+    fn fail(self, error: RequestError) {
+        match self {
+            CallbackResponse::Enr(callbacks) => {
+                for callback in callbacks {
+                    let _ = callback.send(None);
+                }
+            }
+            CallbackResponse::Response(callback) => {
+                let _ = callback.send(Err(error));
+            }
+            CallbackResponse::Pong(callback) => {
+                let _ = callback.send(Err(error));
+            }
+            CallbackResponse::Nodes(callback) => {
+                let _ = callback.send(Err(error));
+            }
+        }
+    }
 }
 
 /// For multiple responses to a FindNodes request, this keeps track of the request count
@@ -139,36 +397,67 @@ impl Default for NodesResponse {
     }
 }
 
+impl NodesResponse {
+    /// Adds the nodes of a response, keeping only the ENR with the highest sequence number of
+    /// each node. Returns `false` if the responses hold more than `max_nodes` unique nodes, in
+    /// which case the excess nodes are dropped.
+    fn add_nodes(&mut self, nodes: Vec<Enr>, max_nodes: usize) -> bool {
+        let mut within_limit = true;
+        for enr in nodes {
+            match self
+                .received_nodes
+                .iter()
+                .position(|received| received.node_id() == enr.node_id())
+            {
+                Some(index) => {
+                    if self.received_nodes[index].seq() < enr.seq() {
+                        self.received_nodes[index] = enr;
+                    }
+                }
+                None if self.received_nodes.len() < max_nodes => self.received_nodes.push(enr),
+                None => within_limit = false,
+            }
+        }
+        within_limit
+    }
+}
+
 impl Service {
     /// Builds the `Service` main struct.
     ///
     /// `local_enr` is the `ENR` representing the local node. This contains node identifying information, such
     /// as IP addresses and ports which we wish to broadcast to other nodes via this discovery
     /// mechanism.
+    #[allow(clippy::too_many_arguments)]
     pub fn spawn(
         local_enr: Arc<RwLock<Enr>>,
         enr_key: Arc<RwLock<CombinedKey>>,
         kbuckets: Arc<RwLock<KBucketsTable<NodeId, Enr>>>,
+        bootnodes: Arc<RwLock<HashSet<NodeId>>>,
+        ip_votes: Option<Arc<RwLock<IpVote>>>,
+        talk_protocols: Arc<Mutex<HashMap<Vec<u8>, TalkHandler>>>,
+        topic_table: Arc<RwLock<TopicTable>>,
+        peer_scores: Arc<RwLock<PeerScores>>,
+        local_enr_updated: Arc<Notify>,
         config: Discv5Config,
         listen_socket: SocketAddr,
-    ) -> (oneshot::Sender<()>, mpsc::Sender<ServiceRequest>) {
-        // process behaviour-level configuration parameters
-        let ip_votes = if config.enr_update {
-            Some(IpVote::new(config.enr_peer_update_min))
-        } else {
-            None
-        };
-
+        socket: Option<std::net::UdpSocket>,
+    ) -> (
+        oneshot::Sender<oneshot::Sender<()>>,
+        mpsc::Sender<ServiceRequest>,
+    ) {
         // build the session service
         let (handler_exit, handler_send, handler_recv) = Handler::spawn(
             local_enr.clone(),
             enr_key.clone(),
             listen_socket,
+            socket,
             config.clone(),
         );
 
         // create the required channels
         let (discv5_send, discv5_recv) = mpsc::channel(30);
+        let (deferred_send, deferred_recv) = mpsc::channel(30);
         let (exit_send, exit) = oneshot::channel();
 
         config
@@ -182,14 +471,46 @@ impl Service {
                     kbuckets,
                     queries: QueryPool::new(config.query_timeout),
                     active_requests: Default::default(),
+                    pending_enr_requests: HashMap::new(),
                     active_nodes_responses: HashMap::new(),
                     ip_votes,
+                    bootnodes,
+                    talk_protocols,
+                    topic_table,
+                    peer_scores,
+                    local_enr_updated,
+                    circuit_breakers: CircuitBreakers::new(
+                        config.bootnode_failure_threshold,
+                        config.bootnode_cooldown,
+                    ),
+                    findnode_dampener: config
+                        .findnode_dampening
+                        .map(|(threshold, window)| FindNodeDampener::new(threshold, window)),
+                    crawls: HashMap::new(),
+                    next_crawl_id: 0,
+                    bootstrap: None,
                     handler_send,
+                    handler_queue: Scheduler::new(MAX_QUEUED_HANDLER_REQUESTS),
+                    dropped_requests: Vec::new(),
+                    deferred_send,
+                    deferred_recv,
+                    handler_unresponsive: false,
                     handler_recv,
                     handler_exit: Some(handler_exit),
                     ping_heartbeat: tokio::time::interval(config.ping_interval),
+                    refresh_heartbeat: tokio::time::interval_at(
+                        (Instant::now() + config.refresh_interval).into(),
+                        config.refresh_interval,
+                    ),
+                    query_heartbeat: tokio::time::interval(QUERY_TIMEOUT_CHECK_INTERVAL),
+                    next_ping: Instant::now(),
+                    ping_backoffs: HashMap::new(),
+                    enr_refreshes: HashMap::new(),
+                    disconnected_since: HashMap::new(),
+                    pending_evictions: HashMap::new(),
+                    enr_confirmed: HashMap::new(),
                     discv5_recv,
-                    event_stream: None,
+                    event_streams: Vec::new(),
                     exit,
                     config: config.clone(),
                 };
@@ -205,122 +526,506 @@ impl Service {
     async fn start(&mut self) {
         loop {
             tokio::select! {
-                _ = &mut self.exit => {
-                    if let Some(exit) = self.handler_exit.take() {
-                        let _ = exit.send(());
+                exit = &mut self.exit => {
+                    // hand over as many queued requests as the handler channel can take
+                    self.flush_handler_queue();
+                    if let Some(handler_exit) = self.handler_exit.take() {
+                        // wait for the handler to send any queued responses
+                        let (done_send, done) = oneshot::channel();
+                        if handler_exit.send(done_send).is_ok() {
+                            let _ = done.await;
+                        }
                         info!("Discv5 Service shutdown");
                     }
+                    if let Ok(done) = exit {
+                        let _ = done.send(());
+                    }
                     return;
                 }
                 Some(service_request) = &mut self.discv5_recv.next() => {
                     match service_request {
                         ServiceRequest::StartQuery(query, callback) => {
+                            if self.queries.iter().count() >= self.config.max_concurrent_queries {
+                                debug!("Maximum number of concurrent queries reached. Query rejected");
+                                callback.respond(Err(QueryError::TooManyConcurrent));
+                                continue;
+                            }
                             match query {
-                                QueryKind::FindNode { target_node } => {
-                                    self.start_findnode_query(target_node, callback);
+                                QueryKind::FindNode { target_node, options } => {
+                                    self.start_findnode_query(QueryType::FindNode(target_node), options, 0, callback);
                                 }
-                                QueryKind::Predicate { target_node, target_peer_no, predicate } => {
-                                    self.start_predicate_query(target_node, target_peer_no, predicate, callback);
+                                QueryKind::FindNodeMin { target_node, min_results } => {
+                                    self.start_findnode_query(QueryType::FindNode(target_node), QueryOptions::default(), min_results, callback);
+                                }
+                                QueryKind::FindNodeDistances { target_node, mut distances } => {
+                                    // request each distance once
+                                    let mut requested = HashSet::new();
+                                    distances.retain(|distance| requested.insert(*distance));
+                                    if distances.is_empty() {
+                                        callback.respond(Ok(Vec::new()));
+                                        continue;
+                                    }
+                                    self.start_findnode_query(QueryType::FindNodeDistances(target_node, distances), QueryOptions::default(), 0, callback);
+                                }
+                                QueryKind::Predicate { target_node, target_peer_no, predicate, options } => {
+                                    self.start_predicate_query(target_node, target_peer_no, predicate, options, callback);
+                                }
+                            }
+                        }
+                        ServiceRequest::FindEnr(node_contact, max_age, callback) => {
+                            match max_age.and_then(|max_age| self.fresh_enr(&node_contact.node_id(), max_age)) {
+                                Some(enr) => {
+                                    let _ = callback.send(Some(enr));
                                 }
+                                None => self.request_enr(node_contact, Some(callback)).await,
                             }
                         }
-                        ServiceRequest::FindEnr(node_contact, callback) => {
-                            self.request_enr(node_contact, Some(callback)).await;
+                        ServiceRequest::FindEnrById(node_id, callback) => {
+                            self.find_enr_by_id(node_id, callback).await;
+                        }
+                        ServiceRequest::LocalEnrOf(node_id, callback) => {
+                            let _ = callback.send(self.find_enr(&node_id));
+                        }
+                        ServiceRequest::Ping(enr, callback) => {
+                            let active_request = ActiveRequest {
+                                callback: Some(CallbackResponse::Pong(callback)),
+                                ..ActiveRequest::new(enr.into(), RequestBody::Ping {
+                                    enr_seq: self.local_enr.read().seq(),
+                                })
+                            };
+                            self.send_rpc_request(active_request).await;
+                        }
+                        ServiceRequest::TopicQuery(contact, topic, callback) => {
+                            let active_request = ActiveRequest {
+                                callback: Some(CallbackResponse::Nodes(callback)),
+                                ..ActiveRequest::new(contact, RequestBody::TopicQuery { topic })
+                            };
+                            self.send_rpc_request(active_request).await;
+                        }
+                        ServiceRequest::Request(contact, request_body, callback) => {
+                            let active_request = ActiveRequest {
+                                callback: Some(CallbackResponse::Response(callback)),
+                                ..ActiveRequest::new(contact, request_body)
+                            };
+                            self.send_rpc_request(active_request).await;
                         }
                         ServiceRequest::RequestEventStream(callback) => {
                             let (event_stream, event_stream_recv) = mpsc::channel(30);
-                            self.event_stream = Some(event_stream);
+                            self.event_streams.push(EventStream {
+                                sender: event_stream,
+                                pending_enr: None,
+                            });
                             if callback.send(event_stream_recv).is_err() {
                                 error!("Failed to return the event stream channel");
                             }
                         }
-                    }
-                }
-                Some(event) = &mut self.handler_recv.next() => {
-                    match event {
-                        HandlerResponse::Established(enr) => {
-                            self.inject_session_established(enr).await;
+                        ServiceRequest::Crawl(seeds, stream) => {
+                            self.start_crawl(seeds, stream).await;
                         }
-                        HandlerResponse::Request(node_address, request) => {
-                                self.handle_rpc_request(node_address, *request).await;
-                            }
-                        HandlerResponse::Response(_, response) => {
-                                self.handle_rpc_response(*response).await;
+                        ServiceRequest::SessionsInfo(callback) => {
+                            // the handler responds to the callback directly
+                            self.send_to_handler(HandlerRequest::SessionsInfo(callback), Priority::Normal);
+                        }
+                        ServiceRequest::SessionCount(callback) => {
+                            // the handler responds to the callback directly
+                            self.send_to_handler(HandlerRequest::SessionCount(callback), Priority::Normal);
+                        }
+                        ServiceRequest::NodeRemoved(node_id, reason) => {
+                            self.send_event(Discv5Event::NodeRemoved { node_id, reason });
+                        }
+                        ServiceRequest::ConnectedPeersInfo(callback) => {
+                            let next_ping = |node_id: &NodeId| {
+                                let skipped = self
+                                    .ping_backoffs
+                                    .get(node_id)
+                                    .map_or(0, |backoff| backoff.skipped_heartbeats);
+                                self.next_ping + self.config.ping_interval * skipped
+                            };
+                            let peers = self
+                                .kbuckets
+                                .write()
+                                .iter()
+                                .filter(|entry| entry.status == NodeStatus::Connected)
+                                .map(|entry| {
+                                    let next_ping = next_ping(entry.node.key.preimage());
+                                    (entry.node.value.clone(), entry.status, next_ping)
+                                })
+                                .collect();
+                            let _ = callback.send(peers);
+                        }
+                        ServiceRequest::IpVoteTally(callback) => {
+                            let tally = self
+                                .ip_votes
+                                .as_ref()
+                                .map(|ip_votes| ip_votes.write().tally())
+                                .unwrap_or_default();
+                            let _ = callback.send(tally);
+                        }
+                        ServiceRequest::ActiveQueryIds(callback) => {
+                            let _ = callback.send(self.queries.iter().map(Query::id).collect());
+                        }
+                        ServiceRequest::CancelQuery(query_id, callback) => {
+                            let _ = callback.send(self.cancel_query(query_id));
+                        }
+                        ServiceRequest::UpdateEnrTcp(socket_addr, callback) => {
+                            let updated = self.update_enr_tcp(socket_addr);
+                            let _ = callback.send(updated);
+                            if updated {
+                                let enr = self.local_enr.read().clone();
+                                self.send_event(Discv5Event::EnrUpdated { enr });
+                                self.ping_connected_peers().await;
                             }
-                        HandlerResponse::WhoAreYou(whoareyou_ref) => {
-                            // check what our latest known ENR is for this node.
-                            if let Some(known_enr) = self.find_enr(&whoareyou_ref.0.node_id) {
-                                self.handler_send.send(HandlerRequest::WhoAreYou(whoareyou_ref, Some(known_enr))).await.unwrap_or_else(|_| ());
+                        }
+                        ServiceRequest::Bootstrap => {
+                            if self.bootstrap.is_some() {
+                                debug!("Bootstrap already in progress");
                             } else {
-                                // do not know of this peer
-                                debug!("NodeId unknown, requesting ENR. {}", whoareyou_ref.0);
-                                self.handler_send.send(HandlerRequest::WhoAreYou(whoareyou_ref, None)).await.unwrap_or_else(|_| ());
+                                self.bootstrap = Some(Bootstrap::new(self.local_enr.read().node_id()));
+                                self.advance_bootstrap();
                             }
                         }
-                        HandlerResponse::RequestFailed(request_id, error) => {
-                            trace!("RPC Request failed: id: {}, error {:?}", request_id, error);
-                            self.rpc_failure(request_id, error).await;
-                        }
                     }
                 }
-                event = Service::bucket_maintenance_poll(&self.kbuckets) => {
-                    self.send_event(event);
+                Some(event) = &mut self.handler_recv.next() => {
+                    self.process_handler_response(event).await;
+                }
+                applied_pending = Service::bucket_maintenance_poll(&self.kbuckets) => {
+                    let evicted = applied_pending.evicted.map(|n| n.key.into_preimage());
+                    self.send_event(Discv5Event::NodeInserted {
+                        node_id: applied_pending.inserted.into_preimage(),
+                        replaced: evicted,
+                    });
+                    if let Some(node_id) = evicted {
+                        self.send_event(Discv5Event::NodeRemoved {
+                            node_id,
+                            reason: RemovalReason::Evicted,
+                        });
+                    }
                 }
                 query_event = Service::query_event_poll(&mut self.queries) => {
                     match query_event {
                         QueryEvent::Waiting(query_id, node_id, request_body) => {
                             self.send_rpc_query(query_id, node_id, request_body).await;
                         }
-                        // Note: Currently the distinction between a timed-out query and a finished
-                        // query is superfluous, however it may be useful in future versions.
-                        QueryEvent::Finished(query) | QueryEvent::TimedOut(query) => {
-                            let id = query.id();
-                            let mut result = query.into_result();
-                            // obtain the ENR's for the resulting nodes
-                            let mut found_enrs = Vec::new();
-                            for node_id in result.closest_peers.into_iter() {
-                                if let Some(position) = result.target.untrusted_enrs.iter().position(|enr| enr.node_id() == node_id) {
-                                    let enr = result.target.untrusted_enrs.swap_remove(position);
-                                    found_enrs.push(enr);
-                                } else if let Some(enr) = self.find_enr(&node_id) {
-                                    // look up from the routing table
-                                    found_enrs.push(enr);
-                                }
-                                else {
-                                    warn!("ENR not present in queries results");
-                                }
-                            }
-                            if result.target.callback.send(found_enrs).is_err() {
-                                warn!("Callback dropped for query {}. Results dropped", *id);
-                            }
+                        QueryEvent::Finished(query) => {
+                            self.query_finished(query, false).await;
+                        }
+                        QueryEvent::TimedOut(query) => {
+                            self.query_finished(query, true).await;
                         }
                     }
                 }
+                _ = self.local_enr_updated.notified() => {
+                    // the local ENR has been updated outside of the service, connected peers are
+                    // pinged such that they request the new ENR
+                    let enr = self.local_enr.read().clone();
+                    self.send_event(Discv5Event::EnrUpdated { enr });
+                    self.ping_connected_peers().await;
+                }
+                index = Service::event_stream_ready_poll(&mut self.event_streams), if self.event_streams.iter().any(|stream| stream.pending_enr.is_some()) => {
+                    self.send_pending_enr(index);
+                }
+                crawl_id = crawl::next_resumable(&mut self.crawls), if self.crawls.values().any(Crawl::is_paused) => {
+                    self.resume_crawl(crawl_id).await;
+                }
+                _ = self.query_heartbeat.next() => {
+                    // the queries are polled on the next iteration, timing out any that expired
+                    self.check_handler_responsive();
+                    self.apply_pending_evictions();
+                }
                 _ = self.ping_heartbeat.next() => {
+                    self.next_ping = Instant::now() + self.config.ping_interval;
+                    PERMIT_BAN_LIST.write().purge_expired();
+                    if let Some(dampener) = self.findnode_dampener.as_mut() {
+                        dampener.prune();
+                    }
                     self.ping_connected_peers().await;
+                    self.advance_ping_backoffs();
+                    self.remove_stale_nodes();
+                    self.prune_peer_state();
+                }
+                _ = self.refresh_heartbeat.next() => {
+                    self.refresh_routing_table();
+                }
+                Some(request) = self.deferred_recv.next() => {
+                    self.send_to_handler(request, Priority::Normal);
+                }
+                _ = future::ready(()), if !self.dropped_requests.is_empty() => {
+                    for id in std::mem::take(&mut self.dropped_requests) {
+                        self.rpc_failure(id, RequestError::ChannelFailed("Handler queue full".into())).await;
+                    }
+                }
+                _ = Service::handler_ready_poll(&mut self.handler_send), if !self.handler_queue.is_empty() => {
+                    self.flush_handler_queue();
                 }
             }
         }
     }
 
-    /// Internal function that starts a query.
-    fn start_findnode_query(&mut self, target_node: NodeId, callback: oneshot::Sender<Vec<Enr>>) {
+    /// Returns the ENRs of the resulting peers of a finished query to the caller. If configured,
+    /// the ENRs of resulting peers that are no longer known are requested before returning.
+    ///
+    /// A query that found no peers is reported as an error if it timed out or none of the contacted
+    /// peers responded. The result is also reported on the event stream.
+    async fn query_finished(&mut self, query: Box<Query<QueryInfo, NodeId, Enr>>, timed_out: bool) {
+        let id = query.id();
+        let mut result = query.into_result();
+        let closest_peers: Vec<NodeId> = result.closest_peers.collect();
+        self.send_event(Discv5Event::FindNodeResult {
+            key: result.target.key().into_preimage(),
+            closer_peers: closest_peers.clone(),
+            query_id: id,
+        });
+        match result.target.callback {
+            QueryCallback::Bootstrap => {
+                self.bootstrap_query_finished();
+                return;
+            }
+            QueryCallback::Refresh => {
+                self.advance_bootstrap();
+                return;
+            }
+            _ => {}
+        }
+        // a waiting bootstrap may use the freed query slot
+        self.advance_bootstrap();
+        if let QueryCallback::Enr(node_id, callback) = result.target.callback {
+            // the node is not contacted by a query towards itself, but is learnt of from the
+            // responses of its peers
+            let enr = result
+                .target
+                .untrusted_enrs
+                .iter()
+                .find(|enr| enr.node_id() == node_id)
+                .cloned()
+                .or_else(|| self.find_enr(&node_id));
+            match enr {
+                Some(enr) => self.request_enr(enr.into(), Some(callback)).await,
+                None => {
+                    let _ = callback.send(None);
+                }
+            }
+            return;
+        }
+        // a query requiring a minimum number of results reports the shortfall as partial results
+        if closest_peers.is_empty() && result.target.min_results == 0 {
+            let error = if timed_out {
+                Some(QueryError::Timeout)
+            } else if !result.target.contacted_peers.is_empty() && !result.target.responded {
+                Some(QueryError::Unreachable)
+            } else {
+                None
+            };
+            if let Some(error) = error {
+                debug!("Query {} failed: {}", *id, error);
+                if !result.target.callback.respond(Err(error)) {
+                    warn!("Callback dropped for query {}. Results dropped", *id);
+                }
+                return;
+            }
+        }
+        let target_key = result.target.key();
+        let min_results = result.target.min_results;
+        // return the node ids directly if their ENRs are not required
+        let callback = match result.target.callback {
+            QueryCallback::NodeIds(callback) => {
+                if callback.send(Ok(closest_peers)).is_err() {
+                    warn!("Callback dropped for query {}. Results dropped", *id);
+                }
+                return;
+            }
+            callback => callback,
+        };
+        // obtain the ENR's for the resulting nodes
+        let mut found_enrs = Vec::new();
+        let mut missing_enrs = Vec::new();
+        for node_id in closest_peers {
+            if let Some(position) = result
+                .target
+                .untrusted_enrs
+                .iter()
+                .position(|enr| enr.node_id() == node_id)
+            {
+                let enr = result.target.untrusted_enrs.swap_remove(position);
+                found_enrs.push(enr);
+            } else if let Some(enr) = self.find_enr(&node_id) {
+                // look up from the routing table
+                found_enrs.push(enr);
+            } else if let Some(enr) = result.target.contacted_enrs.remove(&node_id) {
+                // request the latest ENR from the node
+                missing_enrs.push(enr);
+            } else {
+                warn!("ENR not present in queries results");
+            }
+        }
+
+        if missing_enrs.is_empty() {
+            if !callback.respond(query_result(found_enrs, min_results)) {
+                warn!("Callback dropped for query {}. Results dropped", *id);
+            }
+            return;
+        }
+
+        debug!(
+            "Fetching {} missing ENRs for query {}",
+            missing_enrs.len(),
+            *id
+        );
+        let timeout = self.config.request_timeout * (self.config.request_retries as u32 + 1);
+        let mut fetches = Vec::with_capacity(missing_enrs.len());
+        for enr in missing_enrs {
+            let (callback_send, callback_recv) = oneshot::channel();
+            self.request_enr(enr.into(), Some(callback_send)).await;
+            fetches.push(tokio::time::timeout(timeout, callback_recv));
+        }
+
+        self.config
+            .executor
+            .clone()
+            .expect("Executor must be present")
+            .spawn(Box::pin(async move {
+                for fetched in future::join_all(fetches).await {
+                    match fetched {
+                        Ok(Ok(Some(enr))) => found_enrs.push(enr),
+                        _ => warn!("Could not fetch a missing ENR for query {}", *id),
+                    }
+                }
+                // keep the results ordered by distance to the target
+                found_enrs
+                    .sort_by_key(|enr| target_key.distance(&kbucket::Key::from(enr.node_id())));
+                if !callback.respond(query_result(found_enrs, min_results)) {
+                    warn!("Callback dropped for query {}. Results dropped", *id);
+                }
+            }));
+    }
+
+    /// Processes a single event received from the handler.
+    async fn process_handler_response(&mut self, event: HandlerResponse) {
+        match event {
+            HandlerResponse::Established(enr, socket_addr) => {
+                self.inject_session_established(enr, socket_addr).await;
+            }
+            HandlerResponse::Request(node_address, request) => {
+                self.handle_rpc_request(node_address, *request).await;
+            }
+            HandlerResponse::Response(_, response) => {
+                self.handle_rpc_response(*response).await;
+            }
+            HandlerResponse::WhoAreYou(whoareyou_ref) => {
+                // check what our latest known ENR is for this node.
+                if let Some(known_enr) = self.find_enr(&whoareyou_ref.0.node_id) {
+                    self.send_to_handler(
+                        HandlerRequest::WhoAreYou(whoareyou_ref, Some(known_enr)),
+                        Priority::Normal,
+                    );
+                } else {
+                    // do not know of this peer
+                    debug!("NodeId unknown, requesting ENR. {}", whoareyou_ref.0);
+                    self.send_to_handler(
+                        HandlerRequest::WhoAreYou(whoareyou_ref, None),
+                        Priority::Normal,
+                    );
+                }
+            }
+            HandlerResponse::RequestFailed(request_id, error) => {
+                trace!("RPC Request failed: id: {}, error {:?}", request_id, error);
+                self.rpc_failure(request_id, error).await;
+            }
+            HandlerResponse::SessionEvicted(node_id) => {
+                self.send_event(Discv5Event::SessionEvicted(node_id));
+            }
+            HandlerResponse::SessionExpired(node_address) => {
+                self.send_event(Discv5Event::SessionExpired(node_address.node_id));
+                if self.config.reestablish_expired_sessions {
+                    let key = kbucket::Key::from(node_address.node_id);
+                    let enr = match self.kbuckets.write().entry(&key) {
+                        kbucket::Entry::Present(mut entry, NodeStatus::Connected) => {
+                            Some(entry.value().clone())
+                        }
+                        _ => None,
+                    };
+                    if let Some(enr) = enr {
+                        debug!("Re-establishing expired session with: {}", node_address);
+                        self.send_ping(enr).await;
+                    }
+                }
+            }
+            HandlerResponse::PacketDecodeFailed(source) => {
+                self.send_event(Discv5Event::PacketDecodeFailed { source });
+            }
+            HandlerResponse::HandshakeFailed(node_address, error) => {
+                self.send_event(Discv5Event::HandshakeFailed {
+                    node_address,
+                    error,
+                });
+            }
+        }
+    }
+
+    /// Internal function that starts a query. The query does not finish before finding
+    /// `min_results` peers, unless it times out.
+    fn start_findnode_query(
+        &mut self,
+        query_type: QueryType,
+        options: QueryOptions,
+        min_results: usize,
+        callback: QueryCallback,
+    ) {
+        // A query targeting our own node id can only converge on ourselves. Respond with the
+        // closest peers we know of instead.
+        if query_type == QueryType::FindNode(self.local_enr.read().node_id()) {
+            debug!("Query targets the local node. Returning closest known peers");
+            let found_enrs = self.closest_local_enrs(MAX_NODES_PER_BUCKET, |_| true);
+            let min_results = min_results.min(MAX_NODES_PER_BUCKET);
+            if !callback.respond(query_result(found_enrs, min_results)) {
+                warn!("Callback dropped for self query. Results dropped");
+            }
+            return;
+        }
+
         let target = QueryInfo {
-            query_type: QueryType::FindNode(target_node),
+            query_type,
             untrusted_enrs: Default::default(),
+            contacted_enrs: Default::default(),
+            contacted_peers: Default::default(),
+            closest_distance: None,
+            last_progress: None,
+            responded: false,
+            min_results,
             callback,
         };
 
         // How many times to call the rpc per node.
         // FINDNODE requires multiple iterations as it requests a specific distance.
-        let query_iterations = target.iterations();
+        let query_iterations = target.iterations(self.config.findnode_distance_spread);
 
         let target_key: kbucket::Key<NodeId> = target.key();
-        let known_closest_peers: Vec<kbucket::Key<NodeId>> = {
+        let mut known_closest_peers: Vec<kbucket::Key<NodeId>> = {
             let mut kbuckets = self.kbuckets.write();
             kbuckets.closest_keys(&target_key).collect()
         };
-        let query_config = FindNodeQueryConfig::new_from_config(&self.config);
+        // higher scoring peers are preferred over other peers at the same log2 distance
+        let peer_scores = self.peer_scores.read();
+        let score = |key: &kbucket::Key<NodeId>| peer_scores.score(key.preimage()).unwrap_or(0.0);
+        known_closest_peers.sort_by(|a, b| {
+            target_key
+                .log2_distance(a)
+                .cmp(&target_key.log2_distance(b))
+                .then_with(|| {
+                    score(b)
+                        .partial_cmp(&score(a))
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+        });
+        drop(peer_scores);
+        let mut query_config = FindNodeQueryConfig::new_from_config(&self.config);
+        if let Some(parallelism) = options.parallelism {
+            query_config.parallelism = parallelism.max(1);
+        }
+        if let Some(peer_timeout) = options.peer_timeout {
+            query_config.peer_timeout = peer_timeout;
+        }
+        query_config.min_results = min_results.min(query_config.num_results);
         self.queries.add_findnode_query(
             query_config,
             target,
@@ -335,17 +1040,35 @@ impl Service {
         target_node: NodeId,
         num_nodes: usize,
         predicate: Box<dyn Fn(&Enr) -> bool + Send>,
-        callback: oneshot::Sender<Vec<Enr>>,
+        options: QueryOptions,
+        callback: QueryCallback,
     ) {
+        // A query targeting our own node id can only converge on ourselves. Respond with the
+        // closest peers we know of that satisfy the predicate instead.
+        if target_node == self.local_enr.read().node_id() {
+            debug!("Predicate query targets the local node. Returning closest known peers");
+            let found_enrs = self.closest_local_enrs(num_nodes, &predicate);
+            if !callback.respond(Ok(found_enrs)) {
+                warn!("Callback dropped for self query. Results dropped");
+            }
+            return;
+        }
+
         let target = QueryInfo {
             query_type: QueryType::FindNode(target_node),
             untrusted_enrs: Default::default(),
+            contacted_enrs: Default::default(),
+            contacted_peers: Default::default(),
+            closest_distance: None,
+            last_progress: None,
+            responded: false,
+            min_results: 0,
             callback,
         };
 
         // How many times to call the rpc per node.
         // FINDNODE requires multiple iterations as it requests a specific distance.
-        let query_iterations = target.iterations();
+        let query_iterations = target.iterations(self.config.findnode_distance_spread);
 
         let target_key: kbucket::Key<NodeId> = target.key();
 
@@ -358,6 +1081,12 @@ impl Service {
 
         let mut query_config = PredicateQueryConfig::new_from_config(&self.config);
         query_config.num_results = num_nodes;
+        if let Some(parallelism) = options.parallelism {
+            query_config.parallelism = parallelism.max(1);
+        }
+        if let Some(peer_timeout) = options.peer_timeout {
+            query_config.peer_timeout = peer_timeout;
+        }
         self.queries.add_predicate_query(
             query_config,
             target,
@@ -367,6 +1096,219 @@ impl Service {
         );
     }
 
+    /// Starts as many bootstrap queries as the bootstrap and `max_concurrent_queries` permit.
+    fn advance_bootstrap(&mut self) {
+        let free_slots = self
+            .config
+            .max_concurrent_queries
+            .saturating_sub(self.queries.iter().count());
+        let mut targets = Vec::new();
+        if let Some(bootstrap) = self.bootstrap.as_mut() {
+            while targets.len() < free_slots {
+                match bootstrap.next_target() {
+                    Some(target) => targets.push(target),
+                    None => break,
+                }
+            }
+        }
+        for target in targets {
+            self.start_findnode_query(
+                QueryType::FindNode(target),
+                self.background_query_options(),
+                0,
+                QueryCallback::Bootstrap,
+            );
+        }
+    }
+
+    /// The options of the background queries refreshing the routing table and bootstrapping.
+    fn background_query_options(&self) -> QueryOptions {
+        QueryOptions {
+            parallelism: None,
+            peer_timeout: self.config.background_query_peer_timeout,
+        }
+    }
+
+    /// Starts a query for a random target in a bucket selected by
+    /// `refresh::select_refresh_distance`, if a query slot is free.
+    fn refresh_routing_table(&mut self) {
+        if self.queries.iter().count() >= self.config.max_concurrent_queries {
+            debug!("Skipping routing table refresh. Maximum concurrent queries reached");
+            return;
+        }
+        let entries_per_bucket: Vec<usize> = self
+            .kbuckets
+            .read()
+            .entries_per_bucket()
+            .into_iter()
+            .map(|(connected, disconnected)| connected + disconnected)
+            .collect();
+        let distance =
+            match refresh::select_refresh_distance(&entries_per_bucket, &mut rand::thread_rng()) {
+                Some(distance) => distance,
+                None => {
+                    debug!("Skipping routing table refresh. The routing table is empty");
+                    return;
+                }
+            };
+        let local_key = kbucket::Key::from(self.local_enr.read().node_id());
+        if let Some(target) = local_key.random_at_distance(distance) {
+            debug!("Refreshing the bucket at distance {}", distance);
+            self.start_findnode_query(
+                QueryType::FindNode(target),
+                self.background_query_options(),
+                0,
+                QueryCallback::Refresh,
+            );
+        }
+    }
+
+    /// Removes a query from the pool, failing its callback with `QueryError::Cancelled`. Returns
+    /// whether the query was active. Responses to requests of the query still in flight are
+    /// ignored.
+    fn cancel_query(&mut self, query_id: QueryId) -> bool {
+        let query = match self.queries.remove(query_id) {
+            Some(query) => query,
+            None => return false,
+        };
+        debug!("Query {} cancelled", *query_id);
+        match query.into_result().target.callback {
+            QueryCallback::Bootstrap => self.bootstrap_query_finished(),
+            QueryCallback::Refresh => self.advance_bootstrap(),
+            callback => {
+                // a waiting bootstrap may use the freed query slot
+                self.advance_bootstrap();
+                callback.respond(Err(QueryError::Cancelled));
+            }
+        }
+        true
+    }
+
+    /// Reports the progress of the bootstrap after one of its queries has finished and continues
+    /// the bootstrap.
+    fn bootstrap_query_finished(&mut self) {
+        let (completed, total, finished) = match self.bootstrap.as_mut() {
+            Some(bootstrap) => {
+                bootstrap.on_query_finished();
+                (
+                    bootstrap.completed,
+                    bootstrap.total,
+                    bootstrap.is_finished(),
+                )
+            }
+            None => return,
+        };
+        let table_size = self.kbuckets.write().iter().count();
+        self.send_event(Discv5Event::BootstrapProgress {
+            completed,
+            total,
+            table_size,
+        });
+        if finished {
+            debug!("Bootstrap finished. Routing table size: {}", table_size);
+            self.bootstrap = None;
+        } else {
+            self.advance_bootstrap();
+        }
+    }
+
+    /// Starts a crawl of the network from the given seeds.
+    async fn start_crawl(&mut self, seeds: Vec<Enr>, stream: mpsc::Sender<Enr>) {
+        let crawl_id = self.next_crawl_id;
+        self.next_crawl_id += 1;
+
+        debug!("Starting crawl {} with {} seeds", crawl_id, seeds.len());
+        let local_id = self.local_enr.read().node_id();
+        self.crawls
+            .insert(crawl_id, Crawl::new(local_id, seeds, stream));
+        self.advance_crawl(crawl_id).await;
+    }
+
+    /// Sends the ENRs a paused crawl could not stream, once the stream has capacity again, and
+    /// continues the crawl.
+    async fn resume_crawl(&mut self, crawl_id: CrawlId) {
+        if let Some(crawl) = self.crawls.get_mut(&crawl_id) {
+            if !crawl.deliver() {
+                debug!("Crawl {} stream closed. Ending crawl", crawl_id);
+                self.crawls.remove(&crawl_id);
+                return;
+            }
+        }
+        self.advance_crawl(crawl_id).await;
+    }
+
+    /// Processes the result of a crawl request and continues the crawl. `contact` is `None` if
+    /// the request failed.
+    async fn crawl_response(
+        &mut self,
+        crawl_id: CrawlId,
+        contact: Option<NodeContact>,
+        distance: u64,
+        nodes: Vec<Enr>,
+    ) {
+        if let Some(crawl) = self.crawls.get_mut(&crawl_id) {
+            if !crawl.on_response(contact, distance, nodes) {
+                debug!("Crawl {} stream closed. Ending crawl", crawl_id);
+                self.crawls.remove(&crawl_id);
+                return;
+            }
+        }
+        self.advance_crawl(crawl_id).await;
+    }
+
+    /// Sends crawl requests up to the query parallelism. Ends the crawl once all discovered peers
+    /// have been visited.
+    async fn advance_crawl(&mut self, crawl_id: CrawlId) {
+        loop {
+            let next_request = match self.crawls.get_mut(&crawl_id) {
+                Some(crawl) if crawl.active_requests < self.config.query_parallelism => {
+                    crawl.next_request()
+                }
+                _ => None,
+            };
+            let (enr, distance) = match next_request {
+                Some(next_request) => next_request,
+                None => break,
+            };
+            let active_request = ActiveRequest {
+                crawl_id: Some(crawl_id),
+                ..ActiveRequest::new(
+                    enr.into(),
+                    RequestBody::FindNode {
+                        distances: vec![distance],
+                    },
+                )
+            };
+            self.send_rpc_request(active_request).await;
+        }
+
+        if matches!(self.crawls.get(&crawl_id), Some(crawl) if crawl.is_finished()) {
+            debug!("Crawl {} finished", crawl_id);
+            self.crawls.remove(&crawl_id);
+        }
+    }
+
+    /// Returns up to `num_nodes` ENRs from the routing table that are closest to the local node
+    /// and satisfy the `predicate`, ordered by increasing distance.
+    fn closest_local_enrs(
+        &mut self,
+        num_nodes: usize,
+        predicate: impl Fn(&Enr) -> bool,
+    ) -> Vec<Enr> {
+        let local_key: kbucket::Key<NodeId> = self.local_enr.read().node_id().into();
+        let mut kbuckets = self.kbuckets.write();
+        let closest_keys: Vec<_> = kbuckets.closest_keys(&local_key).collect();
+        closest_keys
+            .iter()
+            .filter_map(|key| match kbuckets.entry(key) {
+                kbucket::Entry::Present(mut entry, _) => Some(entry.value().clone()),
+                _ => None,
+            })
+            .filter(|enr| predicate(enr))
+            .take(num_nodes)
+            .collect()
+    }
+
     /// Returns an ENR if one is known for the given NodeId.
     pub fn find_enr(&mut self, node_id: &NodeId) -> Option<Enr> {
         // check if we know this node id in our routing table
@@ -388,54 +1330,68 @@ impl Service {
         None
     }
 
+    /// Returns the ENR of a node in the routing table. If the node is only known from the
+    /// untrusted ENRs of an ongoing query, its latest ENR is requested from the node. Otherwise
+    /// the node is searched for with a query towards its id.
+    async fn find_enr_by_id(&mut self, node_id: NodeId, callback: oneshot::Sender<Option<Enr>>) {
+        let key = kbucket::Key::from(node_id);
+        if let kbucket::Entry::Present(mut entry, _) = self.kbuckets.write().entry(&key) {
+            let _ = callback.send(Some(entry.value().clone()));
+            return;
+        }
+
+        if let Some(enr) = self.find_enr(&node_id) {
+            debug!("Requesting the ENR of untrusted node: {}", node_id);
+            self.request_enr(enr.into(), Some(callback)).await;
+        } else if self.queries.iter().count() >= self.config.max_concurrent_queries {
+            debug!("Maximum number of concurrent queries reached. ENR search rejected");
+            let _ = callback.send(None);
+        } else {
+            debug!("Searching for the ENR of unknown node: {}", node_id);
+            self.start_findnode_query(
+                QueryType::FindNode(node_id),
+                QueryOptions::default(),
+                0,
+                QueryCallback::Enr(node_id, callback),
+            );
+        }
+    }
+
     /// Processes an RPC request from a peer. Requests respond to the received socket address,
     /// rather than the IP of the known ENR.
     async fn handle_rpc_request(&mut self, node_address: NodeAddress, req: Request) {
         let id = req.id;
         match req.body {
-            RequestBody::FindNode { distance } => {
-                // if the distance is 0 send our local ENR
-                if distance == 0 {
-                    let response = Response {
-                        id,
-                        body: ResponseBody::Nodes {
-                            total: 1,
-                            nodes: vec![self.local_enr.read().clone()],
-                        },
-                    };
-                    debug!("Sending our ENR to node: {}", node_address);
-                    self.handler_send
-                        .send(HandlerRequest::Response(node_address, Box::new(response)))
-                        .await
-                        .unwrap_or_else(|_| ());
-                } else {
-                    self.send_nodes_response(node_address, id, distance).await;
-                }
+            RequestBody::GetEnr => {
+                let response = Response {
+                    id,
+                    body: ResponseBody::Nodes {
+                        total: 1,
+                        nodes: vec![self.local_enr.read().clone()],
+                    },
+                };
+                debug!("Sending our ENR to node: {}", node_address);
+                self.send_to_handler(
+                    HandlerRequest::Response(node_address, Box::new(response)),
+                    Priority::Normal,
+                );
+            }
+            RequestBody::FindNode { distances } => {
+                self.send_nodes_response(node_address, id, distances).await;
             }
             RequestBody::Ping { enr_seq } => {
                 // check if we need to update the known ENR
-                let mut to_request_enr = None;
-                match self.kbuckets.write().entry(&node_address.node_id.into()) {
-                    kbucket::Entry::Present(ref mut entry, _) => {
-                        if entry.value().seq() < enr_seq {
-                            let enr = entry.value().clone();
-                            to_request_enr = Some(enr.into());
-                        }
-                    }
-                    kbucket::Entry::Pending(ref mut entry, _) => {
-                        if entry.value().seq() < enr_seq {
-                            let enr = entry.value().clone();
-                            to_request_enr = Some(enr.into());
-                        }
-                    }
+                let known_enr = match self.kbuckets.write().entry(&node_address.node_id.into()) {
+                    kbucket::Entry::Present(ref mut entry, _) => entry.value().clone(),
+                    kbucket::Entry::Pending(ref mut entry, _) => entry.value().clone(),
                     // don't know of the ENR, request the update
                     _ => {
                         // The ENR is no longer in our table, we stop responding to PING's
                         return;
                     }
-                }
-                if let Some(enr) = to_request_enr {
-                    self.request_enr(enr, None).await;
+                };
+                if self.should_refresh_enr(node_address.node_id, known_enr.seq(), enr_seq) {
+                    self.request_enr(known_enr.into(), None).await;
                 }
 
                 // build the PONG response
@@ -449,17 +1405,113 @@ impl Service {
                     },
                 };
                 debug!("Sending PONG response to {}", node_address);
-                self.handler_send
-                    .send(HandlerRequest::Response(node_address, Box::new(response)))
-                    .await
-                    .unwrap_or_else(|_| ());
+                self.send_to_handler(
+                    HandlerRequest::Response(node_address, Box::new(response)),
+                    Priority::Normal,
+                );
+            }
+            RequestBody::TopicQuery { topic } => {
+                let ads = self.topic_table.write().ads(&topic);
+                debug!(
+                    "Sending {} advertisers of topic {} to: {}",
+                    ads.len(),
+                    hex::encode(topic),
+                    node_address
+                );
+                self.send_nodes(node_address, id, ads).await;
+            }
+            RequestBody::RegTopic { topic, enr, ticket } => {
+                // only the sender can be registered as an advertiser
+                if enr.node_id() != node_address.node_id || !verify_enr(&enr) {
+                    warn!(
+                        "REGTOPIC request with an invalid ENR. Ignoring request from: {}",
+                        node_address
+                    );
+                    return;
+                }
+                let body = match self.topic_table.write().register(topic, enr, &ticket) {
+                    Registration::Ticket { ticket, wait_time } => {
+                        debug!("Sending TICKET response to {}", node_address);
+                        ResponseBody::Ticket { ticket, wait_time }
+                    }
+                    Registration::Confirmed => {
+                        debug!(
+                            "Registered {} as an advertiser of topic {}",
+                            node_address,
+                            hex::encode(topic)
+                        );
+                        ResponseBody::RegConfirmation { topic }
+                    }
+                };
+                let response = Response { id, body };
+                self.send_to_handler(
+                    HandlerRequest::Response(node_address, Box::new(response)),
+                    Priority::Normal,
+                );
+            }
+            RequestBody::TalkReq { protocol, request } => {
+                let handler = self.talk_protocols.lock().get(&protocol).cloned();
+                if let Some(handler) = handler {
+                    // the handler is run on its own task, such that a slow handler does not
+                    // stall the service
+                    let mut deferred_send = self.deferred_send.clone();
+                    self.config
+                        .executor
+                        .clone()
+                        .expect("Executor must be present")
+                        .spawn(Box::pin(async move {
+                            let node_id = node_address.node_id;
+                            let response = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                                handler(request, node_id)
+                            }))
+                            .unwrap_or_else(|_| {
+                                warn!(
+                                    "TALKREQ handler for protocol {} panicked",
+                                    hex::encode(&protocol)
+                                );
+                                Vec::new()
+                            });
+                            let response = Response {
+                                id,
+                                body: ResponseBody::TalkResp { response },
+                            };
+                            debug!("Sending TALKRESP response to {}", node_address);
+                            let _ = deferred_send
+                                .send(HandlerRequest::Response(node_address, Box::new(response)))
+                                .await;
+                        }));
+                    return;
+                }
+                // unregistered protocols are given an empty response
+                debug!(
+                    "TALKREQ for unregistered protocol: {} from: {}",
+                    hex::encode(&protocol),
+                    node_address
+                );
+                let response = Response {
+                    id,
+                    body: ResponseBody::TalkResp {
+                        response: Vec::new(),
+                    },
+                };
+                debug!("Sending TALKRESP response to {}", node_address);
+                self.send_to_handler(
+                    HandlerRequest::Response(node_address, Box::new(response)),
+                    Priority::Normal,
+                );
             }
-            _ => {} //TODO: Implement all RPC methods
         }
     }
 
     /// Processes an RPC response from a peer.
     async fn handle_rpc_response(&mut self, response: Response) {
+        METRICS.responses_received.fetch_add(1, Ordering::Relaxed);
+        *METRICS
+            .responses_received_per_type
+            .write()
+            .entry(response.msg_name())
+            .or_default() += 1;
+
         // verify we know of the rpc_id
         let id = response.id;
 
@@ -469,6 +1521,12 @@ impl Service {
                 response.body, active_request.request_body, active_request.contact
             );
             let node_id = active_request.contact.node_id();
+            self.update_bootnode_circuit(&node_id, true);
+            if let Some(sent_at) = active_request.sent_at.take() {
+                let rtt = sent_at.elapsed();
+                self.peer_scores.write().on_response(node_id, rtt);
+                self.send_event(Discv5Event::ResponseLatency { node_id, rtt });
+            }
             if !response.match_request(&active_request.request_body) {
                 warn!(
                     "Node gave an incorrect response type. Ignoring response from: {}",
@@ -476,50 +1534,75 @@ impl Service {
                 );
                 return;
             }
+            // A user-built request is returned the raw response, without further processing.
+            match active_request.callback.take() {
+                Some(CallbackResponse::Response(callback)) => {
+                    let _ = callback.send(Ok(response));
+                    return;
+                }
+                callback => active_request.callback = callback,
+            }
             match response.body {
                 ResponseBody::Nodes { total, mut nodes } => {
+                    if self.pending_enr_requests.get(&node_id) == Some(&id) {
+                        self.pending_enr_requests.remove(&node_id);
+                    }
+
                     // Currently a maximum of 16 peers can be returned. Datagrams have a max
                     // size of 1280 and ENR's have a max size of 300 bytes. There should be no
                     // more than 5 responses, to return 16 peers.
-                    if total > 5 {
-                        warn!("NodesResponse has a total larger than 5, nodes will be truncated");
+                    if total > self.config.max_nodes_responses as u64 {
+                        warn!(
+                            "NodesResponse has a total larger than {}, nodes will be truncated",
+                            self.config.max_nodes_responses
+                        );
                     }
 
-                    let distance_requested = match active_request.request_body {
-                        RequestBody::FindNode { distance } => distance,
-                        _ => unreachable!(),
-                    };
+                    // Drop any ENRs whose signature does not match the record. A peer relaying
+                    // corrupted records is banned.
+                    let before_len = nodes.len();
+                    nodes.retain(verify_enr);
+                    if nodes.len() < before_len {
+                        warn!(
+                            "Peer sent ENRs with invalid signatures. Blacklisting {}",
+                            active_request.contact
+                        );
+                        self.peer_scores.write().on_invalid_response(node_id);
+                        self.ban_peer(&active_request.contact);
+                    }
 
                     // This could be an ENR request from the outer service. If so respond to the
                     // callback and End.
-                    if let Some(callback) = active_request.callback.take() {
+                    let callback = active_request.callback.take();
+                    if let Some(CallbackResponse::Enr(callbacks)) = callback {
                         // Currently only support requesting for ENR's. Verify this is the case.
-                        if distance_requested != 0 {
+                        if active_request.request_body != RequestBody::GetEnr {
                             error!("Retrieved a callback request that wasn't for a peer's ENR");
                             return;
                         }
-                        // This must be for asking for an ENR
                         if nodes.len() > 1 {
                             warn!(
                                 "Peer returned more than one ENR for itself. {}",
                                 active_request.contact
                             );
                         }
-                        callback.send(nodes.pop()).unwrap_or_else(|_| ());
+                        let enr = nodes.pop();
+                        for callback in callbacks {
+                            let _ = callback.send(enr.clone());
+                        }
                         return;
                     }
+                    active_request.callback = callback;
 
                     // Filter out any nodes that are not of the correct distance
                     let peer_key: kbucket::Key<NodeId> = node_id.into();
-                    let distance_requested = match active_request.request_body {
-                        RequestBody::FindNode { distance } => distance,
-                        _ => todo!(),
-                    };
-                    if distance_requested != 0 {
+                    if let RequestBody::FindNode { ref distances } = active_request.request_body {
+                        // the peer's own ENR is at distance 0
                         let before_len = nodes.len();
                         nodes.retain(|enr| {
-                            peer_key.log2_distance(&enr.node_id().clone().into())
-                                == Some(distance_requested)
+                            let distance =
+                                peer_key.log2_distance(&enr.node_id().into()).unwrap_or(0);
+                            distances.contains(&distance)
                         });
                         if nodes.len() < before_len {
                             // Peer sent invalid ENRs. Blacklist the Node
@@ -527,51 +1610,63 @@ impl Service {
                                 "Peer sent invalid ENR. Blacklisting {}",
                                 active_request.contact
                             );
-                            PERMIT_BAN_LIST.write().ban(
-                                active_request
-                                    .contact
-                                    .node_address()
-                                    .expect("Sanitized request"),
-                            );
+                            self.peer_scores.write().on_invalid_response(node_id);
+                            self.ban_peer(&active_request.contact);
                         }
+                    } else if let RequestBody::TopicQuery { .. } = active_request.request_body {
+                        // advertisers of a topic may be at any distance
                     } else {
-                        // requested an ENR update
-                        nodes.retain(|enr| {
-                            peer_key
-                                .log2_distance(&enr.node_id().clone().into())
-                                .is_none()
-                        });
+                        // requested an ENR update, only the peer's own ENR is accepted
+                        nodes.retain(|enr| enr.node_id() == node_id);
                     }
 
                     // handle the case that there is more than one response
-                    if total > 1 {
-                        let mut current_response = self
-                            .active_nodes_responses
+                    let mut current_response = if total > 1 {
+                        self.active_nodes_responses
                             .remove(&node_id)
-                            .unwrap_or_default();
+                            .unwrap_or_default()
+                    } else {
+                        NodesResponse::default()
+                    };
 
+                    // Duplicate nodes are merged, such that a peer cannot inflate its response. A
+                    // peer sending more unique nodes than fit in the buckets of the requested
+                    // distances is banned.
+                    let max_nodes = match active_request.request_body {
+                        RequestBody::FindNode { ref distances } => {
+                            MAX_NODES_PER_BUCKET * distances.len().max(1)
+                        }
+                        _ => MAX_NODES_PER_BUCKET,
+                    };
+                    let within_limit = current_response.add_nodes(nodes, max_nodes);
+                    if !within_limit {
+                        warn!(
+                            "Peer sent more than {} nodes. Blacklisting {}",
+                            max_nodes, active_request.contact
+                        );
+                        self.ban_peer(&active_request.contact);
+                    }
+
+                    if total > 1 {
                         debug!(
                             "Nodes Response: {} of {} received",
                             current_response.count, total
                         );
                         // if there are more requests coming, store the nodes and wait for
                         // another response
-                        if current_response.count < 5 && (current_response.count as u64) < total {
+                        if within_limit
+                            && current_response.count < self.config.max_nodes_responses
+                            && (current_response.count as u64) < total
+                        {
                             current_response.count += 1;
-
-                            current_response.received_nodes.append(&mut nodes);
                             self.active_nodes_responses
                                 .insert(node_id, current_response);
                             self.active_requests.insert(id, active_request);
                             return;
                         }
-
-                        // have received all the Nodes responses we are willing to accept
-                        // ignore duplicates here as they will be handled when adding
-                        // to the DHT
-                        current_response.received_nodes.append(&mut nodes);
-                        nodes = current_response.received_nodes;
                     }
+                    // have received all the Nodes responses we are willing to accept
+                    let nodes = current_response.received_nodes;
 
                     debug!(
                         "Received a nodes response of len: {}, total: {}, from: {}",
@@ -585,44 +1680,86 @@ impl Service {
                     // ensure any mapping is removed in this rare case
                     self.active_nodes_responses.remove(&node_id);
 
-                    self.discovered(&node_id, nodes, active_request.query_id);
+                    if let Some(CallbackResponse::Nodes(callback)) = active_request.callback.take()
+                    {
+                        let _ = callback.send(Ok(nodes));
+                        return;
+                    }
+
+                    if let Some(crawl_id) = active_request.crawl_id {
+                        let distance = match active_request.request_body {
+                            RequestBody::FindNode { ref distances } => distances[0],
+                            _ => unreachable!("Crawls only send FINDNODE requests"),
+                        };
+                        self.crawl_response(
+                            crawl_id,
+                            Some(active_request.contact),
+                            distance,
+                            nodes,
+                        )
+                        .await;
+                    } else {
+                        self.discovered(&active_request.contact, nodes, active_request.query_id);
+                    }
                 }
                 ResponseBody::Ping { enr_seq, ip, port } => {
+                    if let Some(CallbackResponse::Pong(callback)) = active_request.callback.take() {
+                        let _ = callback.send(Ok(Pong { enr_seq, ip, port }));
+                    }
+                    self.ping_backoffs.remove(&node_id);
                     let socket = SocketAddr::new(ip, port);
-                    // perform ENR majority-based update if required.
-                    let local_socket = self.local_enr.read().udp_socket();
-                    if let Some(ref mut ip_votes) = self.ip_votes {
-                        ip_votes.insert(node_id, socket.clone());
-                        if let Some(majority_socket) = ip_votes.majority() {
-                            if Some(majority_socket) != local_socket {
-                                info!("Local UDP socket updated to: {}", majority_socket);
-                                self.send_event(Discv5Event::SocketUpdated(majority_socket));
-                                // Update the UDP socket
-                                if self
-                                    .local_enr
-                                    .write()
-                                    .set_udp_socket(majority_socket, &self.enr_key.read())
-                                    .is_ok()
-                                {
-                                    // alert known peers to our updated enr
-                                    self.ping_connected_peers().await;
-                                }
+                    // perform ENR majority-based update if required. Each address family is
+                    // updated independently.
+                    let mut updated_sockets = Vec::new();
+                    if let Some(ref ip_votes) = self.ip_votes {
+                        let (majority_v4, majority_v6) = {
+                            let mut ip_votes = ip_votes.write();
+                            ip_votes.insert(node_id, socket);
+                            ip_votes.majority()
+                        };
+                        if self.config.enr_update_v4 {
+                            updated_sockets.extend(majority_v4);
+                        }
+                        if self.config.enr_update_v6 {
+                            updated_sockets.extend(majority_v6);
+                        }
+                    }
+                    let mut enr_updated = false;
+                    for majority_socket in updated_sockets {
+                        let local_socket = {
+                            let local_enr = self.local_enr.read();
+                            if majority_socket.is_ipv4() {
+                                local_enr.ip().map(IpAddr::V4).zip(local_enr.udp())
+                            } else {
+                                local_enr.ip6().map(IpAddr::V6).zip(local_enr.udp6())
                             }
+                        };
+                        if local_socket.map(SocketAddr::from) != Some(majority_socket) {
+                            info!("Local UDP socket updated to: {}", majority_socket);
+                            self.send_event(Discv5Event::SocketUpdated(majority_socket));
+                            // Update the UDP socket
+                            enr_updated |= self
+                                .local_enr
+                                .write()
+                                .set_udp_socket(majority_socket, &self.enr_key.read())
+                                .is_ok();
                         }
                     }
+                    if enr_updated {
+                        let enr = self.local_enr.read().clone();
+                        self.send_event(Discv5Event::EnrUpdated { enr });
+                        // alert known peers to our updated enr
+                        self.ping_connected_peers().await;
+                    }
 
                     // check if we need to request a new ENR
                     if let Some(enr) = self.find_enr(&node_id) {
-                        if enr.seq() < enr_seq {
+                        if self.should_refresh_enr(node_id, enr.seq(), enr_seq) {
                             // request an ENR update
                             debug!("Requesting an ENR update from: {}", active_request.contact);
-                            let request_body = RequestBody::FindNode { distance: 0 };
-                            let active_request = ActiveRequest {
-                                contact: active_request.contact,
-                                request_body,
-                                query_id: None,
-                                callback: None,
-                            };
+                            let request_body = RequestBody::GetEnr;
+                            let active_request =
+                                ActiveRequest::new(active_request.contact, request_body);
                             self.send_rpc_request(active_request).await;
                         }
                         self.connection_updated(node_id, Some(enr), NodeStatus::Connected)
@@ -639,6 +1776,62 @@ impl Service {
         }
     }
 
+    /// Returns whether the ENR of a peer should be requested, given the sequence number it
+    /// advertises. The advertised sequence number must be at least `enr_refresh_min_gap` ahead
+    /// of the known one, and the ENR of each peer is requested at most once per
+    /// `ENR_REFRESH_INTERVAL`, such that peers rapidly updating their ENR are not refetched
+    /// constantly. A sequence number no newer than the known one confirms the known ENR.
+    fn should_refresh_enr(&mut self, node_id: NodeId, known_seq: u64, enr_seq: u64) -> bool {
+        if enr_seq <= known_seq {
+            self.enr_confirmed.insert(node_id, Instant::now());
+        }
+        if enr_seq.saturating_sub(known_seq) < self.config.enr_refresh_min_gap.max(1) {
+            return false;
+        }
+        let now = Instant::now();
+        self.enr_refreshes
+            .retain(|_, requested| now.duration_since(*requested) < ENR_REFRESH_INTERVAL);
+        if self.enr_refreshes.contains_key(&node_id) {
+            debug!(
+                "ENR of {} was recently requested, not requesting it again",
+                node_id
+            );
+            return false;
+        }
+        self.enr_refreshes.insert(node_id, now);
+        true
+    }
+
+    /// Returns the ENR of a node in the routing table if it was confirmed to be current within
+    /// `max_age`.
+    fn fresh_enr(&mut self, node_id: &NodeId, max_age: Duration) -> Option<Enr> {
+        let confirmed = *self.enr_confirmed.get(node_id)?;
+        if confirmed.elapsed() > max_age {
+            return None;
+        }
+        let key = kbucket::Key::from(*node_id);
+        match self.kbuckets.write().entry(&key) {
+            kbucket::Entry::Present(mut entry, _) => Some(entry.value().clone()),
+            kbucket::Entry::Pending(mut entry, _) => Some(entry.value().clone()),
+            _ => None,
+        }
+    }
+
+    /// Forgets the ENR confirmations and scores of nodes that are no longer in the routing
+    /// table.
+    fn prune_peer_state(&mut self) {
+        let mut kbuckets = self.kbuckets.write();
+        let table: HashSet<NodeId> = kbuckets
+            .iter()
+            .map(|entry| *entry.node.key.preimage())
+            .collect();
+        self.enr_confirmed
+            .retain(|node_id, _| table.contains(node_id));
+        self.peer_scores
+            .write()
+            .retain(|node_id| table.contains(node_id));
+    }
+
     // Send RPC Requests //
 
     /// Sends a PING request to a node.
@@ -646,23 +1839,23 @@ impl Service {
         let request_body = RequestBody::Ping {
             enr_seq: self.local_enr.read().seq(),
         };
-        let active_request = ActiveRequest {
-            contact: enr.into(),
-            request_body,
-            query_id: None,
-            callback: None,
-        };
+        let active_request = ActiveRequest::new(enr.into(), request_body);
         self.send_rpc_request(active_request).await;
     }
 
     async fn ping_connected_peers(&mut self) {
-        // maintain the ping interval
+        // maintain the ping interval, skipping peers that are backed off
+        let ping_backoffs = &self.ping_backoffs;
         let connected_peers = {
             let mut kbuckets = self.kbuckets.write();
             kbuckets
                 .iter()
                 .filter_map(|entry| {
-                    if entry.status == NodeStatus::Connected {
+                    let backed_off = matches!(
+                        ping_backoffs.get(entry.node.key.preimage()),
+                        Some(backoff) if backoff.skipped_heartbeats > 0
+                    );
+                    if entry.status == NodeStatus::Connected && !backed_off {
                         Some(entry.node.value.clone())
                     } else {
                         None
@@ -682,35 +1875,99 @@ impl Service {
         contact: NodeContact,
         callback: Option<oneshot::Sender<Option<Enr>>>,
     ) {
-        let request_body = RequestBody::FindNode { distance: 0 };
+        let node_id = contact.node_id();
+        // join a request for the same ENR that is already in flight
+        let in_flight_id = self.pending_enr_requests.get(&node_id).copied();
+        let in_flight = in_flight_id.and_then(|id| self.active_requests.get_mut(&id));
+        if let Some(active_request) = in_flight {
+            debug!("ENR request already in flight to: {}", node_id);
+            match (active_request.callback.as_mut(), callback) {
+                (Some(CallbackResponse::Enr(callbacks)), Some(callback)) => {
+                    callbacks.push(callback)
+                }
+                (None, Some(callback)) => {
+                    active_request.callback = Some(CallbackResponse::Enr(vec![callback]))
+                }
+                _ => {}
+            }
+            return;
+        }
         let active_request = ActiveRequest {
-            contact,
-            request_body,
-            query_id: None,
-            callback,
+            callback: callback.map(|callback| CallbackResponse::Enr(vec![callback])),
+            ..ActiveRequest::new(contact, RequestBody::GetEnr)
         };
-        self.send_rpc_request(active_request).await;
+        match self.send_rpc_request(active_request).await {
+            Some(id) => {
+                self.pending_enr_requests.insert(node_id, id);
+            }
+            None => {
+                self.pending_enr_requests.remove(&node_id);
+            }
+        }
     }
 
-    /// Sends a NODES response, given a list of found ENR's. This function splits the nodes up
-    /// into multiple responses to ensure the response stays below the maximum packet size.
-    async fn send_nodes_response(&mut self, node_address: NodeAddress, rpc_id: u64, distance: u64) {
-        let nodes: Vec<Enr> = {
-            let mut kbuckets = self.kbuckets.write();
-            kbuckets
-                .nodes_by_distance(distance)
-                .into_iter()
-                .filter_map(|entry| {
-                    if entry.node.key.preimage() != &node_address.node_id {
-                        Some(entry.node.value.clone())
-                    } else {
-                        None
-                    }
-                })
-                .collect()
+    /// Sends a NODES response with the nodes of the routing table at the requested distances,
+    /// where a distance of 0 includes the local ENR.
+    async fn send_nodes_response(
+        &mut self,
+        node_address: NodeAddress,
+        rpc_id: u64,
+        mut distances: Vec<u64>,
+    ) {
+        // each node is at a single distance, so the nodes are unique once the distances are. The
+        // order of the requested distances is kept, as nodes are taken from them in order.
+        let mut requested = HashSet::new();
+        distances.retain(|distance| requested.insert(*distance));
+        let passive = self.config.passive_mode;
+        if let Some(dampener) = self.findnode_dampener.as_mut().filter(|_| !passive) {
+            distances.retain(|distance| !dampener.on_request(node_address.node_id, *distance));
+        }
+        let dampened = distances.is_empty();
+        let nodes: Vec<Enr> = if passive {
+            trace!("Passive mode. Withholding nodes from: {}", node_address);
+            Vec::new()
+        } else if dampened {
+            debug!("Dampening repeated FINDNODE request from: {}", node_address);
+            METRICS
+                .dampened_findnode_responses
+                .fetch_add(1, Ordering::Relaxed);
+            Vec::new()
+        } else {
+            // a response holds at most a bucket's worth of nodes, such that a small request
+            // cannot be answered with the whole routing table
+            let max_enr_size = self.config.max_enr_size;
+            let mut nodes = Vec::new();
+            for distance in distances {
+                if nodes.len() >= MAX_NODES_PER_BUCKET {
+                    break;
+                }
+                if distance == 0 {
+                    nodes.push(self.local_enr.read().clone());
+                    continue;
+                }
+                let mut kbuckets = self.kbuckets.write();
+                nodes.extend(kbuckets.nodes_by_distance(distance).into_iter().filter_map(
+                    |entry| {
+                        if entry.node.key.preimage() != &node_address.node_id
+                            && entry.node.value.encode().len() <= max_enr_size
+                        {
+                            Some(entry.node.value.clone())
+                        } else {
+                            None
+                        }
+                    },
+                ));
+            }
+            nodes.truncate(MAX_NODES_PER_BUCKET);
+            nodes
         };
-        // if there are no nodes, send an empty response
-        if nodes.is_empty() {
+        // if there are no nodes, send an empty response unless configured not to
+        if nodes.is_empty() && !dampened && !passive && !self.config.answer_empty_findnode {
+            trace!(
+                "Not responding to FINDNODE with no nodes from: {}",
+                node_address.node_id
+            );
+        } else if nodes.is_empty() {
             let response = Response {
                 id: rpc_id,
                 body: ResponseBody::Nodes {
@@ -722,58 +1979,90 @@ impl Service {
                 "Sending empty FINDNODES response to: {}",
                 node_address.node_id
             );
-            self.handler_send
-                .send(HandlerRequest::Response(node_address, Box::new(response)))
-                .await
-                .unwrap_or_else(|_| ());
+            self.send_to_handler(
+                HandlerRequest::Response(node_address, Box::new(response)),
+                Priority::Normal,
+            );
         } else {
-            // build the NODES response
-            let mut to_send_nodes: Vec<Vec<Enr>> = Vec::new();
-            let mut total_size = 0;
-            let mut rpc_index = 0;
-            to_send_nodes.push(Vec::new());
-            for enr in nodes.into_iter() {
-                let entry_size = enr.encode().len();
-                // Responses assume that a session is established. Thus, on top of the encoded
-                // ENR's the packet should be a regular message. A regular message has a tag (32
-                // bytes), and auth_tag (12 bytes) and the NODES response has an ID (8 bytes) and a total (8 bytes).
-                // The encryption adds the HMAC (16 bytes) and can be at most 16 bytes larger so the total packet size can be at most 92 (given AES_GCM).
-                if entry_size + total_size < MAX_PACKET_SIZE - 92 {
-                    total_size += entry_size;
-                    trace!("Adding ENR {}", enr);
-                    to_send_nodes[rpc_index].push(enr);
-                } else {
-                    total_size = entry_size;
-                    to_send_nodes.push(vec![enr]);
-                    rpc_index += 1;
-                }
+            self.send_nodes(node_address, rpc_id, nodes).await;
+        }
+    }
+
+    /// Sends the given ENRs in as many NODES responses as are required to keep each response
+    /// below the maximum packet size.
+    async fn send_nodes(&mut self, node_address: NodeAddress, rpc_id: u64, nodes: Vec<Enr>) {
+        // build the NODES response
+        let mut to_send_nodes: Vec<Vec<Enr>> = Vec::new();
+        let mut total_size = 0;
+        let mut rpc_index = 0;
+        to_send_nodes.push(Vec::new());
+        for enr in nodes.into_iter() {
+            let entry_size = enr.encode().len();
+            // Responses assume that a session is established. Thus, on top of the encoded
+            // ENR's the packet should be a regular message. A regular message has a tag (32
+            // bytes), and auth_tag (12 bytes) and the NODES response has an ID (8 bytes) and a total (8 bytes).
+            // The encryption adds the HMAC (16 bytes) and can be at most 16 bytes larger so the total packet size can be at most 92 (given AES_GCM).
+            if entry_size + total_size < MAX_PACKET_SIZE - 92 {
+                total_size += entry_size;
+                trace!("Adding ENR {}", enr);
+                to_send_nodes[rpc_index].push(enr);
+            } else {
+                total_size = entry_size;
+                to_send_nodes.push(vec![enr]);
+                rpc_index += 1;
             }
+        }
 
-            let responses: Vec<Response> = to_send_nodes
-                .into_iter()
-                .map(|nodes| Response {
-                    id: rpc_id,
-                    body: ResponseBody::Nodes {
-                        total: (rpc_index + 1) as u64,
-                        nodes,
-                    },
-                })
-                .collect();
+        let responses: Vec<Response> = to_send_nodes
+            .into_iter()
+            .map(|nodes| Response {
+                id: rpc_id,
+                body: ResponseBody::Nodes {
+                    total: (rpc_index + 1) as u64,
+                    nodes,
+                },
+            })
+            .collect();
+
+        if let (Some(delay), true) = (self.config.inter_packet_delay, responses.len() > 1) {
+            // space out the responses without stalling the service, sending them through the
+            // handler queue of the service
+            let mut deferred_send = self.deferred_send.clone();
+            self.config
+                .executor
+                .clone()
+                .expect("Executor must be present")
+                .spawn(Box::pin(async move {
+                    for (index, response) in responses.into_iter().enumerate() {
+                        if index > 0 {
+                            tokio::time::delay_for(delay).await;
+                        }
+                        trace!(
+                            "Sending FINDNODES response to: {}. Response: {} ",
+                            node_address,
+                            response
+                        );
+                        let _ = deferred_send
+                            .send(HandlerRequest::Response(
+                                node_address.clone(),
+                                Box::new(response),
+                            ))
+                            .await;
+                    }
+                }));
+            return;
+        }
 
-            for response in responses {
-                trace!(
-                    "Sending FINDNODES response to: {}. Response: {} ",
-                    node_address,
-                    response
-                );
-                self.handler_send
-                    .send(HandlerRequest::Response(
-                        node_address.clone(),
-                        Box::new(response),
-                    ))
-                    .await
-                    .unwrap_or_else(|_| ());
-            }
+        for response in responses {
+            trace!(
+                "Sending FINDNODES response to: {}. Response: {} ",
+                node_address,
+                response
+            );
+            self.send_to_handler(
+                HandlerRequest::Response(node_address.clone(), Box::new(response)),
+                Priority::Normal,
+            );
         }
     }
 
@@ -784,22 +2073,149 @@ impl Service {
         return_peer: NodeId,
         request_body: RequestBody,
     ) {
-        // find the ENR associated with the query
-        if let Some(enr) = self.find_enr(&return_peer) {
+        // find the ENR associated with the query, falling back to the ENR the query already
+        // contacted in case the peer has since been removed from the routing table
+        let enr = self.find_enr(&return_peer).or_else(|| {
+            self.queries
+                .get_mut(query_id)
+                .and_then(|query| query.target().contacted_enrs.get(&return_peer).cloned())
+        });
+        if let Some(enr) = enr {
+            if let Some(query) = self.queries.get_mut(query_id) {
+                let target = query.target_mut();
+                target.contacted_peers.insert(return_peer);
+                if self.config.fetch_missing_result_enrs {
+                    target.contacted_enrs.insert(return_peer, enr.clone());
+                }
+            }
             let active_request = ActiveRequest {
-                contact: enr.into(),
-                request_body,
                 query_id: Some(query_id),
-                callback: None,
+                ..ActiveRequest::new(enr.into(), request_body)
             };
             self.send_rpc_request(active_request).await;
         } else {
+            // the ENR may have been evicted from the untrusted ENRs of the query
             error!("Query {} requested an unknown ENR", *query_id);
+            if let Some(query) = self.queries.get_mut(query_id) {
+                query.on_failure(&return_peer);
+            }
+        }
+    }
+
+    /// Sends a request to the handler. If the handler channel is full, the request is queued and
+    /// sent once the channel has capacity, ahead of queued requests of a lower priority.
+    fn send_to_handler(&mut self, request: HandlerRequest, priority: Priority) {
+        // keep the order of queued requests
+        if !self.handler_queue.is_empty() {
+            self.queue_for_handler(request, priority);
+            return;
+        }
+        match self.handler_send.try_send(request) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(request)) => {
+                trace!("Handler channel full, queueing request");
+                self.queue_for_handler(request, priority);
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {
+                error!("Handler channel closed, request dropped");
+            }
+        }
+    }
+
+    /// Queues a request for the handler. If the queue is full and a request is dropped, an RPC
+    /// request among it is failed.
+    fn queue_for_handler(&mut self, request: HandlerRequest, priority: Priority) {
+        if let Some((priority, dropped)) = self.handler_queue.push(priority, request) {
+            warn!(
+                "Handler queue full, dropped a request of priority {:?}",
+                priority
+            );
+            if let HandlerRequest::Request(_, request) = dropped {
+                self.dropped_requests.push(request.id);
+            }
+        }
+    }
+
+    /// Reports the handler as unresponsive once requests to it have been queued for longer than
+    /// the `handler_unresponsive_timeout`, which would otherwise only surface as requests and
+    /// queries timing out.
+    fn check_handler_responsive(&mut self) {
+        match self.handler_queue.queued_since() {
+            Some(since) => {
+                let stalled_for = since.elapsed();
+                if !self.handler_unresponsive
+                    && stalled_for >= self.config.handler_unresponsive_timeout
+                {
+                    let queued = self.handler_queue.len();
+                    warn!(
+                        "Handler unresponsive for {:?}. Queued requests: {}",
+                        stalled_for, queued
+                    );
+                    self.handler_unresponsive = true;
+                    self.send_event(Discv5Event::HandlerUnresponsive {
+                        queued,
+                        stalled_for,
+                    });
+                }
+            }
+            None => {
+                if self.handler_unresponsive {
+                    info!("Handler is responsive again");
+                    self.handler_unresponsive = false;
+                }
+            }
+        }
+    }
+
+    /// Sends the queued requests to the handler, highest priority first, until its channel is
+    /// full.
+    fn flush_handler_queue(&mut self) {
+        while let Some((priority, request)) = self.handler_queue.pop() {
+            match self.handler_send.try_send(request) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(request)) => {
+                    self.handler_queue.push_front(priority, request);
+                    return;
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => {
+                    self.handler_queue.clear();
+                    return;
+                }
+            }
         }
     }
 
     /// Sends generic RPC requests. Each request gets added to known outputs, awaiting a response.
-    async fn send_rpc_request(&mut self, active_request: ActiveRequest) {
+    async fn send_rpc_request(&mut self, mut active_request: ActiveRequest) -> Option<RequestId> {
+        // Don't contact bootnodes whose circuit is open
+        let node_id = active_request.contact.node_id();
+        if self.bootnodes.read().contains(&node_id) && !self.circuit_breakers.allow(&node_id) {
+            debug!(
+                "Bootnode circuit is open. Request not sent to: {}",
+                active_request.contact
+            );
+            self.fail_unsent_request(active_request, RequestError::CircuitOpen);
+            return None;
+        }
+
+        // user requests are sent ahead of background maintenance if the handler is congested
+        let priority = if active_request.callback.is_some() {
+            Priority::High
+        } else if let Some(query_id) = active_request.query_id {
+            match self
+                .queries
+                .get_mut(query_id)
+                .map(|query| &query.target().callback)
+            {
+                Some(QueryCallback::Bootstrap) | Some(QueryCallback::Refresh) => Priority::Low,
+                _ => Priority::High,
+            }
+        } else if active_request.crawl_id.is_some() {
+            Priority::Normal
+        } else {
+            Priority::Low
+        };
+
         // Generate a random rpc_id which is matched per node id
         let id: u64 = rand::random();
         let request: Request = Request {
@@ -807,83 +2223,227 @@ impl Service {
             body: active_request.request_body.clone(),
         };
         let contact = active_request.contact.clone();
+        active_request.sent_at = Some(Instant::now());
         self.active_requests.insert(id, active_request);
         debug!("Sending RPC {} to node: {}", request, contact);
+        METRICS.requests_sent.fetch_add(1, Ordering::Relaxed);
+        *METRICS
+            .requests_sent_per_type
+            .write()
+            .entry(request.msg_name())
+            .or_default() += 1;
+
+        self.send_to_handler(
+            HandlerRequest::Request(contact, Box::new(request)),
+            priority,
+        );
+        Some(id)
+    }
+
+    /// Fails a request that was never sent to the handler, informing the user, query or crawl.
+    fn fail_unsent_request(&mut self, active_request: ActiveRequest, error: RequestError) {
+        if let Some(callback) = active_request.callback {
+            callback.fail(error);
+        } else if let Some(crawl_id) = active_request.crawl_id {
+            // the crawl is advanced by the caller
+            if let Some(crawl) = self.crawls.get_mut(&crawl_id) {
+                crawl.active_requests = crawl.active_requests.saturating_sub(1);
+            }
+        } else if let Some(query_id) = active_request.query_id {
+            if let Some(query) = self.queries.get_mut(query_id) {
+                query.on_failure(&active_request.contact.node_id());
+            }
+        }
+    }
 
-        self.handler_send
-            .send(HandlerRequest::Request(contact, Box::new(request)))
-            .await
-            .unwrap_or_else(|_| ());
+    /// Records the outcome of a request in the circuit breaker of a bootnode.
+    fn update_bootnode_circuit(&mut self, node_id: &NodeId, success: bool) {
+        if !self.bootnodes.read().contains(node_id) {
+            return;
+        }
+        if success {
+            self.circuit_breakers.on_success(node_id);
+        } else if self.circuit_breakers.on_failure(node_id) {
+            warn!(
+                "Bootnode unreachable. Pausing contact for {:?}. Node: {}",
+                self.config.bootnode_cooldown, node_id
+            );
+        }
+        METRICS
+            .open_bootnode_circuits
+            .store(self.circuit_breakers.open_circuits(), Ordering::Relaxed);
     }
 
     fn send_event(&mut self, event: Discv5Event) {
-        if let Some(stream) = self.event_stream.as_mut() {
-            if let Err(mpsc::error::TrySendError::Closed(_)) = stream.try_send(event) {
-                // If the stream has been dropped prevent future attempts to send events
-                self.event_stream = None;
+        // send the event to all subscribers, removing those whose stream has been dropped
+        self.event_streams
+            .retain_mut(|stream| match stream.sender.try_send(event.clone()) {
+                Ok(()) => {
+                    if let Discv5Event::EnrUpdated { .. } = event {
+                        stream.pending_enr = None;
+                    }
+                    true
+                }
+                // ENR updates are coalesced rather than dropped when the stream is full
+                Err(mpsc::error::TrySendError::Full(Discv5Event::EnrUpdated { enr })) => {
+                    stream.pending_enr = Some(enr);
+                    true
+                }
+                Err(mpsc::error::TrySendError::Full(_)) => true,
+                Err(mpsc::error::TrySendError::Closed(_)) => false,
+            });
+    }
+
+    /// Sends the pending ENR update of the subscriber at `index`, whose stream has capacity or
+    /// has been dropped.
+    fn send_pending_enr(&mut self, index: usize) {
+        let stream = &mut self.event_streams[index];
+        if let Some(enr) = stream.pending_enr.take() {
+            match stream.sender.try_send(Discv5Event::EnrUpdated { enr }) {
+                Err(mpsc::error::TrySendError::Full(Discv5Event::EnrUpdated { enr })) => {
+                    stream.pending_enr = Some(enr);
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => {
+                    self.event_streams.remove(index);
+                }
+                _ => {}
             }
         }
     }
 
+    /// Bans a misbehaving peer and its IP for the configured `ban_duration`. As with
+    /// `Discv5::ban_until`, the peer is removed from the routing table.
+    fn ban_peer(&mut self, contact: &NodeContact) {
+        let node_address = match contact.node_address() {
+            Ok(node_address) => node_address,
+            Err(_) => return,
+        };
+        let node_id = node_address.node_id;
+        PERMIT_BAN_LIST.write().ban_until(
+            node_address,
+            self.config
+                .ban_duration
+                .map(|duration| Instant::now() + duration),
+        );
+        if self.kbuckets.write().remove(&kbucket::Key::from(node_id)) {
+            self.send_event(Discv5Event::NodeRemoved {
+                node_id,
+                reason: RemovalReason::Banned,
+            });
+        }
+    }
+
     /// Processes discovered peers from a query.
-    fn discovered(&mut self, source: &NodeId, enrs: Vec<Enr>, query_id: Option<QueryId>) {
+    fn discovered(&mut self, contact: &NodeContact, mut enrs: Vec<Enr>, query_id: Option<QueryId>) {
+        let source = &contact.node_id();
+        // drop ENRs exceeding the size limit
+        let max_enr_size = self.config.max_enr_size;
+        let before_len = enrs.len();
+        enrs.retain(|enr| enr.encode().len() <= max_enr_size);
+        if enrs.len() < before_len {
+            warn!(
+                "Peer sent {} ENRs exceeding the maximum size. Peer: {}",
+                before_len - enrs.len(),
+                contact
+            );
+            if self.config.ban_oversized_enrs {
+                self.ban_peer(contact);
+            }
+        }
+
         let local_id = self.local_enr.read().node_id();
         let other_enr_iter = enrs.iter().filter(|p| p.node_id() != local_id);
 
         for enr_ref in other_enr_iter.clone() {
             // If any of the discovered nodes are in the routing table, and there contains an older ENR, update it.
             // If there is an event stream send the Discovered event
-            self.send_event(Discv5Event::Discovered(enr_ref.clone()));
+            if self.config.report_discovered_raw {
+                self.send_event(Discv5Event::DiscoveredRaw {
+                    enr: enr_ref.clone(),
+                    raw: enr_ref.encode(),
+                });
+            } else {
+                self.send_event(Discv5Event::Discovered(enr_ref.clone()));
+            }
 
-            // ignore peers that don't pass the able filter
-            if (self.config.table_filter)(enr_ref) {
+            // ignore peers that don't pass the table filter or that can't be contacted, if required
+            if (self.config.table_filter)(enr_ref)
+                && (!self.config.require_contactable || enr_ref.udp_socket().is_some())
+            {
                 let key = kbucket::Key::from(enr_ref.node_id());
                 if !self.config.ip_limit
                     || self
                         .kbuckets
                         .read()
-                        .check(&key, enr_ref, { |v, o, l| ip_limiter(v, &o, l) })
+                        .check(&key, enr_ref, |v, o, l| ip_limiter(v, &o, l))
                 {
-                    match self.kbuckets.write().entry(&key) {
+                    let conflict = match self.kbuckets.write().entry(&key) {
                         kbucket::Entry::Present(mut entry, _) => {
-                            if entry.value().seq() < enr_ref.seq() {
-                                trace!("ENR updated: {}", enr_ref);
-                                *entry.value() = enr_ref.clone();
-                            }
+                            update_stored_enr(entry.value(), enr_ref, source)
                         }
                         kbucket::Entry::Pending(mut entry, _) => {
-                            if entry.value().seq() < enr_ref.seq() {
-                                trace!("ENR updated: {}", enr_ref);
-                                *entry.value() = enr_ref.clone();
-                            }
+                            update_stored_enr(entry.value(), enr_ref, source)
                         }
-                        kbucket::Entry::Absent(_entry) => {}
-                        _ => {}
+                        _ => None,
+                    };
+                    if let Some(event) = conflict {
+                        self.send_event(event);
                     }
                 }
             }
         }
 
         // if this is part of a query, update the query
+        let mut progress = None;
+        let max_untrusted_enrs = self.config.max_untrusted_enrs_per_query;
         if let Some(query_id) = query_id {
             if let Some(query) = self.queries.get_mut(query_id) {
                 let mut peer_count = 0;
                 for enr_ref in other_enr_iter.clone() {
-                    if query
+                    query
                         .target_mut()
-                        .untrusted_enrs
-                        .iter()
-                        .position(|e| e.node_id() == enr_ref.node_id())
-                        .is_none()
-                    {
-                        query.target_mut().untrusted_enrs.push(enr_ref.clone());
-                    }
+                        .add_untrusted_enr(enr_ref.clone(), max_untrusted_enrs);
                     peer_count += 1;
                 }
                 debug!("{} peers found for query id {:?}", peer_count, query_id);
-                query.on_success(source, &other_enr_iter.cloned().collect::<Vec<_>>())
+                query.target_mut().responded = true;
+                // only the peers whose ENR was retained can be contacted by the query
+                let retained = other_enr_iter
+                    .clone()
+                    .filter(|enr| {
+                        query
+                            .target()
+                            .untrusted_enrs
+                            .iter()
+                            .any(|retained| retained.node_id() == enr.node_id())
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
+                query.on_success(source, &retained);
+
+                // report the progress of the query, at most once per interval
+                let target_key = query.target().key();
+                let target = query.target_mut();
+                let closest_distance = other_enr_iter
+                    .filter_map(|enr| target_key.log2_distance(&enr.node_id().into()))
+                    .chain(target.closest_distance)
+                    .min();
+                target.closest_distance = closest_distance;
+                let now = Instant::now();
+                if !matches!(target.last_progress, Some(last) if now < last + QUERY_PROGRESS_INTERVAL)
+                {
+                    target.last_progress = Some(now);
+                    progress = Some(Discv5Event::QueryProgress {
+                        query_id,
+                        peers_contacted: target.contacted_peers.len(),
+                        closest_distance,
+                    });
+                }
             }
         }
+        if let Some(event) = progress {
+            self.send_event(event);
+        }
     }
 
     /// Update the connection status of a node in the routing table.
@@ -900,12 +2460,17 @@ impl Service {
                 return;
             }
 
+            if self.config.require_contactable && enr.udp_socket().is_none() {
+                debug!("Ignoring ENR without a UDP socket. Node: {}", node_id);
+                return;
+            }
+
             // should the ENR be inserted or updated to a value that would exceed the IP limit ban
             if self.config.ip_limit
                 && !self
                     .kbuckets
                     .read()
-                    .check(&key, enr, { |v, o, l| ip_limiter(v, &o, l) })
+                    .check(&key, enr, |v, o, l| ip_limiter(v, &o, l))
             {
                 // if the node status is connected and it would exceed the ip ban, consider it
                 // disconnected to be pruned.
@@ -922,6 +2487,11 @@ impl Service {
                 }
                 if old_status != new_status {
                     entry.update(new_status);
+                    event_to_send = Some(Discv5Event::ConnectionStateChanged {
+                        node_id,
+                        old: old_status,
+                        new: new_status,
+                    });
                 }
             }
             kbucket::Entry::Pending(mut entry, old_status) => {
@@ -930,6 +2500,11 @@ impl Service {
                 }
                 if old_status != new_status {
                     entry.update(new_status);
+                    event_to_send = Some(Discv5Event::ConnectionStateChanged {
+                        node_id,
+                        old: old_status,
+                        new: new_status,
+                    });
                 }
             }
             kbucket::Entry::Absent(entry) => {
@@ -947,7 +2522,16 @@ impl Service {
                             }
                             kbucket::InsertResult::Full => (),
                             kbucket::InsertResult::Pending { disconnected } => {
-                                ping_peer = Some(*disconnected.preimage());
+                                let pending_on = *disconnected.preimage();
+                                ping_peer = Some(pending_on);
+                                self.pending_evictions.insert(
+                                    node_id,
+                                    Instant::now() + self.config.pending_eviction_timeout,
+                                );
+                                event_to_send = Some(Discv5Event::NodePending {
+                                    inserting: node_id,
+                                    pending_on,
+                                });
                             }
                         }
                     }
@@ -956,6 +2540,13 @@ impl Service {
             _ => {}
         }
 
+        if let Some(Discv5Event::ConnectionStateChanged { new, .. }) = event_to_send {
+            if new == NodeStatus::Disconnected {
+                self.disconnected_since.insert(node_id, Instant::now());
+            } else {
+                self.disconnected_since.remove(&node_id);
+            }
+        }
         if let Some(event) = event_to_send {
             self.send_event(event);
         }
@@ -966,11 +2557,151 @@ impl Service {
         }
     }
 
+    /// Removes nodes from the routing table that have been disconnected for longer than the
+    /// `disconnected_node_timeout`. Nodes are timed from when they were disconnected, or from
+    /// when they were first seen disconnected if they were added to the table as such.
+    fn remove_stale_nodes(&mut self) {
+        let timeout = match self.config.disconnected_node_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        let now = Instant::now();
+        let disconnected: Vec<NodeId> = self
+            .kbuckets
+            .write()
+            .iter()
+            .filter(|entry| entry.status == NodeStatus::Disconnected)
+            .map(|entry| *entry.node.key.preimage())
+            .collect();
+        // nodes that are no longer in the table or have since connected are forgotten
+        let mut disconnected_since = HashMap::new();
+        let mut stale = Vec::new();
+        for node_id in disconnected {
+            let since = self
+                .disconnected_since
+                .get(&node_id)
+                .copied()
+                .unwrap_or(now);
+            if now.duration_since(since) >= timeout {
+                stale.push(node_id);
+            } else {
+                disconnected_since.insert(node_id, since);
+            }
+        }
+        self.disconnected_since = disconnected_since;
+
+        for node_id in stale {
+            if self.kbuckets.write().remove(&kbucket::Key::from(node_id)) {
+                debug!(
+                    "Removing node disconnected for longer than {:?}: {}",
+                    timeout, node_id
+                );
+                self.send_event(Discv5Event::NodeRemoved {
+                    node_id,
+                    reason: RemovalReason::Stale,
+                });
+            }
+        }
+    }
+
+    /// Sets the TCP socket of the local ENR, returning whether the ENR changed. The update is
+    /// rejected if the resulting ENR would exceed the `max_enr_size`, or if the IP of the socket
+    /// differs from the IP the ENR advertises. Only the port is set for an unspecified IP.
+    fn update_enr_tcp(&mut self, socket_addr: SocketAddr) -> bool {
+        // the lock is held throughout, such that concurrent updates of the local ENR are not lost
+        let mut local_enr = self.local_enr.write();
+        let (local_ip, local_port, port_key) = if socket_addr.is_ipv4() {
+            (local_enr.ip().map(IpAddr::V4), local_enr.tcp(), "tcp")
+        } else {
+            (local_enr.ip6().map(IpAddr::V6), local_enr.tcp6(), "tcp6")
+        };
+        // the ENR holds a single IP per family, which is shared with the UDP socket. An
+        // unspecified IP, such as the bind address of a listener, only updates the port.
+        let ip = socket_addr.ip();
+        if !ip.is_unspecified() && matches!(local_ip, Some(local_ip) if local_ip != ip) {
+            warn!(
+                "Could not update the local TCP socket: {} does not match the local IP",
+                socket_addr
+            );
+            return false;
+        }
+        let port_only = ip.is_unspecified() || local_ip.is_some();
+        if port_only && local_port == Some(socket_addr.port()) {
+            return false;
+        }
+
+        let mut enr = local_enr.clone();
+        let result = if port_only {
+            enr.insert(
+                port_key,
+                socket_addr.port().to_be_bytes().to_vec(),
+                &self.enr_key.read(),
+            )
+            .map(|_| ())
+        } else {
+            enr.set_tcp_socket(socket_addr, &self.enr_key.read())
+        };
+        if let Err(e) = result {
+            warn!("Could not update the local TCP socket: {:?}", e);
+            return false;
+        }
+        if enr.size() > self.config.max_enr_size {
+            warn!("Could not update the local TCP socket: the ENR would exceed the maximum size");
+            return false;
+        }
+        info!("Local TCP socket updated to: {}", socket_addr);
+        *local_enr = enr;
+        true
+    }
+
+    /// Inserts nodes pending on a full bucket once their `pending_eviction_timeout` has elapsed.
+    /// The routing table only applies pending nodes when their bucket is accessed, so without
+    /// this a pending node could wait indefinitely. Nodes that were inserted are reported by the
+    /// routing table's applied pending entries.
+    fn apply_pending_evictions(&mut self) {
+        if self.pending_evictions.is_empty() {
+            return;
+        }
+        let now = Instant::now();
+        let mut kbuckets = self.kbuckets.write();
+        self.pending_evictions.retain(|node_id, replace_at| {
+            if *replace_at > now {
+                return true;
+            }
+            // accessing the bucket applies its pending node, unless the disconnected node has
+            // since responded
+            let _ = kbuckets.entry(&kbucket::Key::from(*node_id));
+            false
+        });
+    }
+
     /// The equivalent of libp2p `inject_connected()` for a udp session. We have no stream, but a
     /// session key-pair has been negotiated.
-    async fn inject_session_established(&mut self, enr: Enr) {
+    async fn inject_session_established(&mut self, enr: Enr, socket_addr: SocketAddr) {
         let node_id = enr.node_id();
+        if !(self.config.session_filter)(&enr, &socket_addr) {
+            debug!("Session rejected by the session filter. Node: {}", node_id);
+            let node_address = NodeAddress {
+                socket_addr,
+                node_id,
+            };
+            self.send_to_handler(HandlerRequest::DropSession(node_address), Priority::Normal);
+            // fail the requests awaiting the peer, such that responses racing the dropped session
+            // are ignored
+            let request_ids: Vec<RequestId> = self
+                .active_requests
+                .iter()
+                .filter(|(_, request)| request.contact.node_id() == node_id)
+                .map(|(id, _)| *id)
+                .collect();
+            for id in request_ids {
+                self.rpc_failure(id, RequestError::SessionRejected).await;
+            }
+            return;
+        }
         debug!("Session established with Node: {}", node_id);
+        self.enr_confirmed.insert(node_id, Instant::now());
+        self.send_event(Discv5Event::SessionEstablished(enr.clone(), socket_addr));
         self.connection_updated(node_id.clone(), Some(enr.clone()), NodeStatus::Connected)
             .await;
         // send an initial ping and start the ping interval
@@ -981,19 +2712,53 @@ impl Service {
     /// specified).
     async fn rpc_failure(&mut self, id: RequestId, error: RequestError) {
         trace!("RPC Error removing request. Reason: {:?}, id {}", error, id);
+        if error == RequestError::Timeout {
+            METRICS.requests_timed_out.fetch_add(1, Ordering::Relaxed);
+        }
         if let Some(active_request) = self.active_requests.remove(&id) {
+            let node_id = active_request.contact.node_id();
+            if self.pending_enr_requests.get(&node_id) == Some(&id) {
+                self.pending_enr_requests.remove(&node_id);
+            }
+            if error == RequestError::Timeout {
+                self.peer_scores.write().on_timeout(node_id);
+            }
             // If this is initiated by the user, return an error on the callback. All callbacks
             // support a request error.
             if let Some(callback) = active_request.callback {
-                callback.send(None).unwrap_or_else(|_| ());
+                callback.fail(error);
                 return;
             }
 
-            let node_id = active_request.contact.node_id();
+            self.update_bootnode_circuit(&node_id, false);
+
+            // continue a crawl with any partially received nodes
+            if let Some(crawl_id) = active_request.crawl_id {
+                let nodes = self
+                    .active_nodes_responses
+                    .remove(&node_id)
+                    .map(|nodes_response| nodes_response.received_nodes)
+                    .unwrap_or_default();
+                let distance = match active_request.request_body {
+                    RequestBody::FindNode { ref distances } => distances[0],
+                    _ => unreachable!("Crawls only send FINDNODE requests"),
+                };
+                self.crawl_response(crawl_id, None, distance, nodes).await;
+                return;
+            }
+
+            // connected peers that fail to respond to a PING are backed off before being
+            // disconnected
+            if let RequestBody::Ping { .. } = active_request.request_body {
+                if self.backoff_ping(&node_id) {
+                    return;
+                }
+            }
+
             match active_request.request_body {
                 // if a failed FindNodes request, ensure we haven't partially received packets. If
                 // so, process the partially found nodes
-                RequestBody::FindNode { .. } => {
+                RequestBody::FindNode { .. } | RequestBody::GetEnr => {
                     if let Some(nodes_response) = self.active_nodes_responses.remove(&node_id) {
                         if !nodes_response.received_nodes.is_empty() {
                             warn!(
@@ -1003,7 +2768,7 @@ impl Service {
                             // if it's a query mark it as success, to process the partial
                             // collection of peers
                             self.discovered(
-                                &node_id,
+                                &active_request.contact,
                                 nodes_response.received_nodes,
                                 active_request.query_id,
                             );
@@ -1042,24 +2807,98 @@ impl Service {
                 }
             }
 
+            let key = kbucket::Key::from(node_id);
+            let was_connected = matches!(
+                self.kbuckets.write().entry(&key),
+                kbucket::Entry::Present(_, NodeStatus::Connected)
+            );
             self.connection_updated(node_id, None, NodeStatus::Disconnected)
                 .await;
+            if was_connected {
+                self.send_event(Discv5Event::NodeRemoved {
+                    node_id,
+                    reason: RemovalReason::RequestFailed,
+                });
+            }
+        }
+    }
+
+    /// Records a failed PING to a connected peer, doubling the peer's ping interval up to
+    /// `MAX_PING_BACKOFF`. Returns false if the peer has reached the `ping_failure_limit` and
+    /// should be disconnected.
+    fn backoff_ping(&mut self, node_id: &NodeId) -> bool {
+        let key = kbucket::Key::from(*node_id);
+        if !matches!(
+            self.kbuckets.write().entry(&key),
+            kbucket::Entry::Present(_, NodeStatus::Connected)
+        ) {
+            return false;
+        }
+        let failures = self
+            .ping_backoffs
+            .get(node_id)
+            .map_or(0, |backoff| backoff.failures)
+            + 1;
+        if failures >= self.config.ping_failure_limit {
+            self.ping_backoffs.remove(node_id);
+            return false;
         }
+        let multiplier = 2u32.saturating_pow(failures).min(MAX_PING_BACKOFF);
+        debug!(
+            "Node: {} failed {} PINGs, pinging every {} heartbeats",
+            node_id, failures, multiplier
+        );
+        self.ping_backoffs.insert(
+            *node_id,
+            PingBackoff {
+                failures,
+                skipped_heartbeats: multiplier - 1,
+            },
+        );
+        true
+    }
+
+    /// Counts down the heartbeats backed off peers skip, and forgets the backoff of peers that
+    /// are no longer connected.
+    fn advance_ping_backoffs(&mut self) {
+        let kbuckets = &self.kbuckets;
+        self.ping_backoffs.retain(|node_id, backoff| {
+            backoff.skipped_heartbeats = backoff.skipped_heartbeats.saturating_sub(1);
+            matches!(
+                kbuckets.write().entry(&kbucket::Key::from(*node_id)),
+                kbucket::Entry::Present(_, NodeStatus::Connected)
+            )
+        });
+    }
+
+    /// A future that resolves with the index of a subscriber with a pending ENR update once its
+    /// stream has capacity, or has been dropped.
+    async fn event_stream_ready_poll(event_streams: &mut [EventStream]) -> usize {
+        future::poll_fn(move |cx| {
+            for (index, stream) in event_streams.iter_mut().enumerate() {
+                if stream.pending_enr.is_some() && stream.sender.poll_ready(cx).is_ready() {
+                    return Poll::Ready(index);
+                }
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// A future that resolves once the handler channel has capacity, or has closed.
+    async fn handler_ready_poll(handler_send: &mut mpsc::Sender<HandlerRequest>) {
+        let _ = future::poll_fn(move |cx| handler_send.poll_ready(cx)).await;
     }
 
     /// A future that maintains the routing table and inserts nodes when required. This returns the
-    /// `Discv5Event::NodeInserted` variant if a new node has been inserted into the routing table.
+    /// applied pending entry if a new node has been inserted into the routing table.
     async fn bucket_maintenance_poll(
         kbuckets: &Arc<RwLock<KBucketsTable<NodeId, Enr>>>,
-    ) -> Discv5Event {
+    ) -> kbucket::AppliedPending<NodeId, Enr> {
         future::poll_fn(move |_cx| {
             // Drain applied pending entries from the routing table.
             if let Some(entry) = kbuckets.write().take_applied_pending() {
-                let event = Discv5Event::NodeInserted {
-                    node_id: entry.inserted.into_preimage(),
-                    replaced: entry.evicted.map(|n| n.key.into_preimage()),
-                };
-                return Poll::Ready(event);
+                return Poll::Ready(entry);
             }
             Poll::Pending
         })
@@ -1106,3 +2945,169 @@ enum QueryEvent {
     /// The query has completed successfully.
     Finished(Box<crate::query_pool::Query<QueryInfo, NodeId, Enr>>),
 }
+
+/// Returns the peers found by a query, reporting them as `QueryError::Partial` if fewer than
+/// `min_results` were found.
+fn query_result(found_enrs: Vec<Enr>, min_results: usize) -> Result<Vec<Enr>, QueryError> {
+    if found_enrs.len() < min_results {
+        Err(QueryError::Partial(found_enrs))
+    } else {
+        Ok(found_enrs)
+    }
+}
+
+/// Updates the stored ENR of a node with an ENR of the node discovered from `source`, if the
+/// discovered ENR has a higher sequence number.
+///
+/// Two ENRs with the same sequence number but different contents conflict. The stored ENR is
+/// kept, unless only the discovered ENR has a valid signature or it was sent by the node itself,
+/// which is authoritative for its own record. A `Discv5Event::EnrConflict` is returned for a
+/// conflict.
+fn update_stored_enr(stored: &mut Enr, discovered: &Enr, source: &NodeId) -> Option<Discv5Event> {
+    if stored.seq() < discovered.seq() {
+        trace!("ENR updated: {}", discovered);
+        *stored = discovered.clone();
+        return None;
+    }
+    if stored.seq() > discovered.seq() || stored.encode() == discovered.encode() {
+        return None;
+    }
+
+    let replace = rpc::verify_enr(discovered)
+        && (!rpc::verify_enr(stored) || *source == discovered.node_id());
+    warn!(
+        "Conflicting ENRs with sequence number {} for node: {}. Source: {}, replaced: {}",
+        stored.seq(),
+        stored.node_id(),
+        source,
+        replace
+    );
+    let (kept, rejected) = if replace {
+        let rejected = std::mem::replace(stored, discovered.clone());
+        (stored.clone(), rejected)
+    } else {
+        (stored.clone(), discovered.clone())
+    };
+    Some(Discv5Event::EnrConflict {
+        node_id: kept.node_id(),
+        source: *source,
+        kept,
+        rejected,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enr::EnrBuilder;
+
+    fn build_enr(key: &CombinedKey, seq: u64) -> Enr {
+        EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(9000)
+            .seq(seq)
+            .build(key)
+            .unwrap()
+    }
+
+    #[test]
+    fn nodes_response_merges_duplicates() {
+        let key_a = CombinedKey::generate_secp256k1();
+        let key_b = CombinedKey::generate_secp256k1();
+        let mut response = NodesResponse::default();
+
+        // duplicates within and across responses are counted once
+        assert!(response.add_nodes(
+            vec![build_enr(&key_a, 1), build_enr(&key_a, 1)],
+            MAX_NODES_PER_BUCKET
+        ));
+        assert!(response.add_nodes(
+            vec![build_enr(&key_a, 1), build_enr(&key_b, 1)],
+            MAX_NODES_PER_BUCKET
+        ));
+        assert_eq!(response.received_nodes.len(), 2);
+
+        // the highest sequence number is kept, regardless of the order received
+        assert!(response.add_nodes(vec![build_enr(&key_a, 3)], MAX_NODES_PER_BUCKET));
+        assert!(response.add_nodes(
+            vec![build_enr(&key_a, 2), build_enr(&key_b, 2)],
+            MAX_NODES_PER_BUCKET
+        ));
+        let seqs: Vec<_> = response.received_nodes.iter().map(Enr::seq).collect();
+        assert_eq!(seqs, vec![3, 2]);
+    }
+
+    #[test]
+    fn nodes_response_limits_unique_nodes() {
+        let keys: Vec<_> = (0..=MAX_NODES_PER_BUCKET)
+            .map(|_| CombinedKey::generate_secp256k1())
+            .collect();
+        let mut response = NodesResponse::default();
+        let enrs: Vec<_> = keys.iter().map(|key| build_enr(key, 1)).collect();
+        assert!(response.add_nodes(
+            enrs[..MAX_NODES_PER_BUCKET - 1].to_vec(),
+            MAX_NODES_PER_BUCKET
+        ));
+        // a repeated node does not count towards the limit
+        assert!(response.add_nodes(enrs[..MAX_NODES_PER_BUCKET].to_vec(), MAX_NODES_PER_BUCKET));
+        assert!(!response.add_nodes(enrs.clone(), MAX_NODES_PER_BUCKET));
+        assert_eq!(
+            response.received_nodes,
+            enrs[..MAX_NODES_PER_BUCKET].to_vec()
+        );
+
+        // responses to requests for several distances may hold a bucket's worth of each
+        let mut response = NodesResponse::default();
+        assert!(response.add_nodes(enrs.clone(), 2 * MAX_NODES_PER_BUCKET));
+        assert_eq!(response.received_nodes, enrs);
+    }
+
+    #[test]
+    fn update_stored_enr_resolves_equal_seq_conflicts() {
+        let enr_key = CombinedKey::generate_secp256k1();
+        let enr_a = EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(9000)
+            .build(&enr_key)
+            .unwrap();
+        let enr_b = EnrBuilder::new("v4")
+            .ip("127.0.0.2".parse().unwrap())
+            .udp(9000)
+            .build(&enr_key)
+            .unwrap();
+        assert_eq!(enr_a.seq(), enr_b.seq());
+        let node_id = enr_a.node_id();
+        let other_source = NodeId::random();
+
+        // identical ENRs are not a conflict
+        let mut stored = enr_a.clone();
+        assert!(update_stored_enr(&mut stored, &enr_a, &other_source).is_none());
+
+        // the stored ENR is kept if another node sends a conflicting ENR
+        match update_stored_enr(&mut stored, &enr_b, &other_source) {
+            Some(Discv5Event::EnrConflict { kept, rejected, .. }) => {
+                assert_eq!(kept, enr_a);
+                assert_eq!(rejected, enr_b);
+            }
+            _ => panic!("Expected a conflict"),
+        }
+        assert_eq!(stored, enr_a);
+
+        // the node itself is authoritative for its own ENR
+        match update_stored_enr(&mut stored, &enr_b, &node_id) {
+            Some(Discv5Event::EnrConflict { kept, rejected, .. }) => {
+                assert_eq!(kept, enr_b);
+                assert_eq!(rejected, enr_a);
+            }
+            _ => panic!("Expected a conflict"),
+        }
+        assert_eq!(stored, enr_b);
+
+        // a conflicting ENR with an invalid signature is never accepted
+        let mut encoded = rlp::encode(&enr_a);
+        encoded[10] ^= 0xff;
+        let tampered = rlp::decode::<Enr>(&encoded).unwrap();
+        assert!(update_stored_enr(&mut stored, &tampered, &node_id).is_some());
+        assert_eq!(stored, enr_b);
+    }
+}
@@ -0,0 +1,56 @@
+use crate::kbucket::Key;
+use enr::NodeId;
+
+/// The maximum number of bootstrap queries that are run at a time, leaving capacity for user
+/// queries.
+pub(crate) const BOOTSTRAP_PARALLELISM: usize = 3;
+
+/// A refresh of the routing table, running a `FINDNODE` query towards a random target at each
+/// log2 distance from the local node.
+pub(crate) struct Bootstrap {
+    /// The targets that remain to be queried. The furthest targets are queried first.
+    pending: Vec<NodeId>,
+    /// The number of queries that are currently running.
+    active_queries: usize,
+    /// The number of queries that have finished.
+    pub completed: usize,
+    /// The total number of queries run by the bootstrap.
+    pub total: usize,
+}
+
+impl Bootstrap {
+    pub fn new(local_id: NodeId) -> Self {
+        let local_key = Key::from(local_id);
+        let pending: Vec<NodeId> = (1..=256)
+            .filter_map(|distance| local_key.random_at_distance(distance))
+            .collect();
+        Bootstrap {
+            total: pending.len(),
+            pending,
+            active_queries: 0,
+            completed: 0,
+        }
+    }
+
+    /// Returns the target of the next query to start, if any remain and fewer than
+    /// `BOOTSTRAP_PARALLELISM` are running.
+    pub fn next_target(&mut self) -> Option<NodeId> {
+        if self.active_queries >= BOOTSTRAP_PARALLELISM {
+            return None;
+        }
+        let target = self.pending.pop()?;
+        self.active_queries += 1;
+        Some(target)
+    }
+
+    /// Records that a bootstrap query has finished.
+    pub fn on_query_finished(&mut self) {
+        self.active_queries = self.active_queries.saturating_sub(1);
+        self.completed += 1;
+    }
+
+    /// Returns whether every query of the bootstrap has finished.
+    pub fn is_finished(&self) -> bool {
+        self.pending.is_empty() && self.active_queries == 0
+    }
+}
@@ -0,0 +1,145 @@
+use enr::NodeId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The state of a single circuit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CircuitState {
+    /// The node is contacted as normal. Holds the number of consecutive failures.
+    Closed(usize),
+    /// The node is not contacted until the given instant.
+    Open(Instant),
+    /// The cooldown has elapsed and a single trial request has been permitted.
+    HalfOpen,
+}
+
+/// Circuit breakers for bootnodes that are persistently unreachable.
+///
+/// After `failure_threshold` consecutive failures a bootnode's circuit is opened and the node is
+/// not contacted for `cooldown`. Once the cooldown has elapsed a single request is permitted. If
+/// it succeeds the circuit is closed, otherwise it is re-opened for another cooldown.
+pub(crate) struct CircuitBreakers {
+    /// The current state of each bootnode circuit that has observed a failure.
+    circuits: HashMap<NodeId, CircuitState>,
+    /// The number of consecutive failures before a circuit is opened.
+    failure_threshold: usize,
+    /// The duration a circuit remains open before a trial request is permitted.
+    cooldown: Duration,
+}
+
+impl CircuitBreakers {
+    pub fn new(failure_threshold: usize, cooldown: Duration) -> Self {
+        CircuitBreakers {
+            circuits: HashMap::new(),
+            failure_threshold,
+            cooldown,
+        }
+    }
+
+    /// Returns whether a request may be sent to the node. If the node's cooldown has elapsed,
+    /// this permits a single trial request.
+    pub fn allow(&mut self, node_id: &NodeId) -> bool {
+        match self.circuits.get_mut(node_id) {
+            None | Some(CircuitState::Closed(_)) => true,
+            Some(CircuitState::HalfOpen) => false,
+            Some(state) => {
+                if let CircuitState::Open(until) = state {
+                    if *until <= Instant::now() {
+                        *state = CircuitState::HalfOpen;
+                        return true;
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    /// Records a successful interaction with the node, closing its circuit.
+    pub fn on_success(&mut self, node_id: &NodeId) {
+        self.circuits.remove(node_id);
+    }
+
+    /// Records a failed interaction with the node. Returns `true` if this opened the circuit.
+    pub fn on_failure(&mut self, node_id: &NodeId) -> bool {
+        let open_until = Instant::now() + self.cooldown;
+        let state = self
+            .circuits
+            .entry(*node_id)
+            .or_insert(CircuitState::Closed(0));
+        match state {
+            CircuitState::Closed(failures) => {
+                *failures += 1;
+                if *failures >= self.failure_threshold {
+                    *state = CircuitState::Open(open_until);
+                    return true;
+                }
+                false
+            }
+            CircuitState::HalfOpen => {
+                *state = CircuitState::Open(open_until);
+                true
+            }
+            CircuitState::Open(_) => false,
+        }
+    }
+
+    /// Returns the number of circuits that are currently open or awaiting a trial request.
+    pub fn open_circuits(&self) -> usize {
+        self.circuits
+            .values()
+            .filter(|state| !matches!(state, CircuitState::Closed(_)))
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_opens_after_threshold() {
+        let mut breakers = CircuitBreakers::new(3, Duration::from_secs(60));
+        let node_id = NodeId::random();
+
+        assert!(!breakers.on_failure(&node_id));
+        assert!(!breakers.on_failure(&node_id));
+        assert!(breakers.allow(&node_id));
+        assert!(breakers.on_failure(&node_id));
+
+        assert!(!breakers.allow(&node_id));
+        assert_eq!(breakers.open_circuits(), 1);
+    }
+
+    #[test]
+    fn test_success_resets_failures() {
+        let mut breakers = CircuitBreakers::new(2, Duration::from_secs(60));
+        let node_id = NodeId::random();
+
+        assert!(!breakers.on_failure(&node_id));
+        breakers.on_success(&node_id);
+        assert!(!breakers.on_failure(&node_id));
+        assert!(breakers.allow(&node_id));
+        assert_eq!(breakers.open_circuits(), 0);
+    }
+
+    #[test]
+    fn test_half_open_permits_single_trial() {
+        let mut breakers = CircuitBreakers::new(1, Duration::from_secs(0));
+        let node_id = NodeId::random();
+
+        assert!(breakers.on_failure(&node_id));
+        // the cooldown has elapsed, a single trial is permitted
+        assert!(breakers.allow(&node_id));
+        assert!(!breakers.allow(&node_id));
+
+        // a failed trial re-opens the circuit
+        assert!(breakers.on_failure(&node_id));
+        assert!(breakers.allow(&node_id));
+
+        // a successful trial closes the circuit
+        breakers.on_success(&node_id);
+        assert!(breakers.allow(&node_id));
+        assert!(breakers.allow(&node_id));
+        assert_eq!(breakers.open_circuits(), 0);
+    }
+}
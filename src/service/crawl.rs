@@ -0,0 +1,220 @@
+use crate::node_info::NodeContact;
+use crate::Enr;
+use enr::NodeId;
+use futures::future::{self, Future};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::task::Poll;
+use tokio::sync::mpsc;
+
+/// The distances requested from each peer visited during a crawl, in order. Most of the network
+/// lives in the furthest buckets of any given peer, so these yield the most unique ENRs per
+/// request.
+pub(crate) const CRAWL_DISTANCES: [u64; 3] = [256, 255, 254];
+
+/// Identifies a crawl in progress.
+pub(crate) type CrawlId = usize;
+
+/// A crawl of the network.
+///
+/// Unlike a query, a crawl does not converge on a target. Every peer that is discovered is
+/// visited once, requesting each of the `CRAWL_DISTANCES` in turn, and every newly discovered ENR
+/// is streamed to the user. No further peers are requested while the stream is full, such that a
+/// slow consumer slows the crawl down rather than having ENRs buffered without bound.
+pub(crate) struct Crawl {
+    /// The peers that remain to be requested along with the index of the next distance in
+    /// `CRAWL_DISTANCES` to request from them.
+    pending: VecDeque<(Enr, usize)>,
+    /// The peers that have been seen during the crawl.
+    seen: HashSet<NodeId>,
+    /// The number of peers that are currently being requested.
+    pub active_requests: usize,
+    /// Discovered ENRs that could not yet be sent as the stream is full.
+    undelivered: VecDeque<Enr>,
+    /// The channel newly discovered ENRs are sent to.
+    stream: mpsc::Sender<Enr>,
+}
+
+impl Crawl {
+    pub fn new(local_id: NodeId, seeds: Vec<Enr>, stream: mpsc::Sender<Enr>) -> Self {
+        let mut crawl = Crawl {
+            pending: VecDeque::new(),
+            seen: HashSet::new(),
+            active_requests: 0,
+            undelivered: VecDeque::new(),
+            stream,
+        };
+        crawl.seen.insert(local_id);
+        for enr in seeds {
+            if crawl.seen.insert(enr.node_id()) && enr.udp_socket().is_some() {
+                crawl.pending.push_back((enr, 0));
+            }
+        }
+        crawl
+    }
+
+    /// Returns the next peer and distance to request. No peer is requested while the stream is
+    /// full.
+    pub fn next_request(&mut self) -> Option<(Enr, u64)> {
+        if self.is_paused() {
+            return None;
+        }
+        let (enr, index) = self.pending.pop_front()?;
+        self.active_requests += 1;
+        Some((enr, CRAWL_DISTANCES[index]))
+    }
+
+    /// Records the result of a request for peers at `distance`. `contact` is the peer that
+    /// responded, or `None` if the request failed, in which case the peer is not requested
+    /// again. Peers that have not been seen before are streamed and queued to be visited.
+    ///
+    /// Returns `false` if the stream has been closed, in which case the crawl should end.
+    pub fn on_response(
+        &mut self,
+        contact: Option<NodeContact>,
+        distance: u64,
+        enrs: Vec<Enr>,
+    ) -> bool {
+        self.active_requests = self.active_requests.saturating_sub(1);
+
+        // request the next distance from the peer before visiting further peers
+        if let Some(NodeContact::Enr(enr)) = contact {
+            if let Some(index) = CRAWL_DISTANCES.iter().position(|d| *d == distance) {
+                if index + 1 < CRAWL_DISTANCES.len() {
+                    self.pending.push_front((*enr, index + 1));
+                }
+            }
+        }
+
+        for enr in enrs {
+            if !self.seen.insert(enr.node_id()) {
+                continue;
+            }
+            if enr.udp_socket().is_some() {
+                self.pending.push_back((enr.clone(), 0));
+            }
+            self.undelivered.push_back(enr);
+        }
+        self.deliver()
+    }
+
+    /// Sends the undelivered ENRs to the stream until it is full.
+    ///
+    /// Returns `false` if the stream has been closed, in which case the crawl should end.
+    pub fn deliver(&mut self) -> bool {
+        while let Some(enr) = self.undelivered.pop_front() {
+            match self.stream.try_send(enr) {
+                Ok(()) => {}
+                Err(mpsc::error::TrySendError::Full(enr)) => {
+                    self.undelivered.push_front(enr);
+                    break;
+                }
+                Err(mpsc::error::TrySendError::Closed(_)) => return false,
+            }
+        }
+        true
+    }
+
+    /// Returns whether the crawl is waiting for the stream to have capacity.
+    pub fn is_paused(&self) -> bool {
+        !self.undelivered.is_empty()
+    }
+
+    /// Returns whether all discovered peers have been visited and streamed.
+    pub fn is_finished(&self) -> bool {
+        self.active_requests == 0 && self.pending.is_empty() && self.undelivered.is_empty()
+    }
+}
+
+/// Resolves to the id of a paused crawl whose stream has capacity again, or has been closed.
+pub(crate) fn next_resumable(
+    crawls: &mut HashMap<CrawlId, Crawl>,
+) -> impl Future<Output = CrawlId> + '_ {
+    future::poll_fn(move |cx| {
+        for (crawl_id, crawl) in crawls.iter_mut() {
+            if crawl.is_paused() && crawl.stream.poll_ready(cx).is_ready() {
+                return Poll::Ready(*crawl_id);
+            }
+        }
+        Poll::Pending
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enr::{CombinedKey, EnrBuilder};
+
+    fn build_enr(port: u16) -> Enr {
+        EnrBuilder::new("v4")
+            .ip("127.0.0.1".parse().unwrap())
+            .udp(port)
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_crawl_requests_each_distance_in_turn() {
+        let (stream, mut stream_recv) = mpsc::channel(10);
+        let seed = build_enr(9000);
+        let mut crawl = Crawl::new(NodeId::random(), vec![seed.clone()], stream);
+
+        for distance in CRAWL_DISTANCES.iter() {
+            let (enr, requested) = crawl.next_request().unwrap();
+            assert_eq!(enr, seed);
+            assert_eq!(requested, *distance);
+            assert!(crawl.next_request().is_none());
+            assert!(crawl.on_response(Some(enr.into()), requested, vec![seed.clone()]));
+        }
+        // the seed has already been seen and is not reported
+        assert!(stream_recv.try_recv().is_err());
+        assert!(crawl.is_finished());
+    }
+
+    #[test]
+    fn test_crawl_reports_new_peers_once() {
+        let (stream, mut stream_recv) = mpsc::channel(10);
+        let mut crawl = Crawl::new(NodeId::random(), vec![build_enr(9000)], stream);
+        let discovered = build_enr(9001);
+
+        let (enr, distance) = crawl.next_request().unwrap();
+        let enrs = vec![discovered.clone(), discovered.clone()];
+        assert!(crawl.on_response(Some(enr.into()), distance, enrs));
+        assert_eq!(stream_recv.try_recv().unwrap(), discovered);
+        assert!(stream_recv.try_recv().is_err());
+
+        // the remaining distances of the seed are requested before the new peer
+        assert_eq!(crawl.next_request().unwrap().1, CRAWL_DISTANCES[1]);
+        assert_eq!(crawl.next_request().unwrap().0, discovered);
+    }
+
+    #[test]
+    fn test_crawl_ends_when_stream_closed() {
+        let (stream, stream_recv) = mpsc::channel(10);
+        let mut crawl = Crawl::new(NodeId::random(), vec![build_enr(9000)], stream);
+        drop(stream_recv);
+
+        let (enr, distance) = crawl.next_request().unwrap();
+        assert!(!crawl.on_response(Some(enr.into()), distance, vec![build_enr(9001)]));
+    }
+
+    #[test]
+    fn test_crawl_pauses_while_stream_full() {
+        let (stream, mut stream_recv) = mpsc::channel(1);
+        let mut crawl = Crawl::new(NodeId::random(), vec![build_enr(9000)], stream);
+        let discovered = vec![build_enr(9001), build_enr(9002)];
+
+        let (enr, distance) = crawl.next_request().unwrap();
+        assert!(crawl.on_response(Some(enr.into()), distance, discovered.clone()));
+
+        // the second ENR does not fit in the stream, so no further peers are requested
+        assert!(crawl.is_paused());
+        assert!(crawl.next_request().is_none());
+        assert!(!crawl.is_finished());
+
+        assert_eq!(stream_recv.try_recv().unwrap(), discovered[0]);
+        assert!(crawl.deliver());
+        assert!(!crawl.is_paused());
+        assert_eq!(stream_recv.try_recv().unwrap(), discovered[1]);
+        assert!(crawl.next_request().is_some());
+    }
+}
@@ -0,0 +1,79 @@
+use enr::NodeId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Tracks repeated identical FINDNODE requests from peers.
+///
+/// A peer that requests the same distance more than `threshold` times within `window` is likely
+/// stuck in a query loop. Responses to such requests are dampened, to avoid amplifying the loop.
+pub(crate) struct FindNodeDampener {
+    /// The start of the current window and the number of requests received within it, per peer
+    /// and requested distance.
+    requests: HashMap<(NodeId, u64), (Instant, usize)>,
+    /// The number of identical requests permitted within a window before responses are dampened.
+    threshold: usize,
+    /// The duration of the window in which identical requests are counted.
+    window: Duration,
+}
+
+impl FindNodeDampener {
+    pub fn new(threshold: usize, window: Duration) -> Self {
+        FindNodeDampener {
+            requests: HashMap::new(),
+            threshold,
+            window,
+        }
+    }
+
+    /// Records a FINDNODE request. Returns `true` if the response to the request should be
+    /// dampened.
+    pub fn on_request(&mut self, node_id: NodeId, distance: u64) -> bool {
+        let now = Instant::now();
+        let (start, count) = self.requests.entry((node_id, distance)).or_insert((now, 0));
+        if now.duration_since(*start) >= self.window {
+            *start = now;
+            *count = 0;
+        }
+        *count += 1;
+        *count > self.threshold
+    }
+
+    /// Removes the records of windows that have elapsed.
+    pub fn prune(&mut self) {
+        let now = Instant::now();
+        let window = self.window;
+        self.requests
+            .retain(|_, (start, _)| now.duration_since(*start) < window);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repeated_requests_are_dampened() {
+        let mut dampener = FindNodeDampener::new(2, Duration::from_secs(60));
+        let node_id = NodeId::random();
+
+        assert!(!dampener.on_request(node_id, 256));
+        assert!(!dampener.on_request(node_id, 256));
+        assert!(dampener.on_request(node_id, 256));
+
+        // other distances and peers are counted separately
+        assert!(!dampener.on_request(node_id, 255));
+        assert!(!dampener.on_request(NodeId::random(), 256));
+    }
+
+    #[test]
+    fn test_elapsed_window_resets_count() {
+        let mut dampener = FindNodeDampener::new(1, Duration::from_secs(0));
+        let node_id = NodeId::random();
+
+        assert!(!dampener.on_request(node_id, 256));
+        assert!(!dampener.on_request(node_id, 256));
+
+        dampener.prune();
+        assert!(dampener.requests.is_empty());
+    }
+}
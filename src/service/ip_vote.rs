@@ -7,10 +7,13 @@ use std::time::{Duration, Instant};
 /// The timeout before a report/vote expires. Currently set to a 5 minute window.
 const PING_VOTE_TIMEOUT: u64 = 300;
 
-/// A collection of IP:Ports for our node reported from external peers.
-pub(crate) struct IpVote {
-    /// The current collection of IP:Port votes.
-    votes: HashMap<NodeId, (SocketAddr, Instant)>,
+/// A collection of IP:Ports for our node reported from external peers. IPv4 and IPv6 votes are
+/// tallied separately, such that a peer may vote for one socket of each family.
+pub struct IpVote {
+    /// The current collection of IPv4 IP:Port votes.
+    votes_v4: HashMap<NodeId, (SocketAddr, Instant)>,
+    /// The current collection of IPv6 IP:Port votes.
+    votes_v6: HashMap<NodeId, (SocketAddr, Instant)>,
     /// The minimum number of votes required before an IP/PORT is accepted.
     minimum_threshold: usize,
 }
@@ -22,13 +25,19 @@ impl IpVote {
             panic!("Setting enr_peer_update_min to a value less than 2 will cause issues with discovery with peers behind NAT");
         }
         IpVote {
-            votes: HashMap::new(),
+            votes_v4: HashMap::new(),
+            votes_v6: HashMap::new(),
             minimum_threshold,
         }
     }
 
     pub fn insert(&mut self, key: NodeId, socket: SocketAddr) {
-        self.votes.insert(
+        let votes = if socket.is_ipv4() {
+            &mut self.votes_v4
+        } else {
+            &mut self.votes_v6
+        };
+        votes.insert(
             key,
             (
                 socket,
@@ -37,27 +46,51 @@ impl IpVote {
         );
     }
 
-    /// Returns the majority `SocketAddr` if it exists. If there are not enough votes to meet the threshold this returns None.
-    pub fn majority(&mut self) -> Option<SocketAddr> {
-        // remove any expired votes
-        let instant = Instant::now();
-        self.votes.retain(|_, v| v.1 > instant);
-
-        // count votes, take majority
-        let mut ip_count: FnvHashMap<SocketAddr, usize> = FnvHashMap::default();
-        for (socket, _) in self.votes.values() {
-            *ip_count.entry(*socket).or_insert_with(|| 0) += 1;
-        }
+    /// Returns the majority IPv4 and IPv6 `SocketAddr`s if they exist. If there are not enough
+    /// votes for a family to meet the threshold its majority is None.
+    pub fn majority(&mut self) -> (Option<SocketAddr>, Option<SocketAddr>) {
+        self.remove_expired();
+        let threshold = self.minimum_threshold;
+        let majority = |votes| {
+            // find the maximum socket addr
+            count_votes(votes)
+                .into_iter()
+                .filter(|v| v.1 >= threshold)
+                .max_by_key(|v| v.1)
+                .map(|v| v.0)
+        };
+        (majority(&self.votes_v4), majority(&self.votes_v6))
+    }
 
-        // find the maximum socket addr
-        ip_count
+    /// Returns each voted `SocketAddr` of both families along with its number of votes, ordered by
+    /// the number of votes.
+    pub fn tally(&mut self) -> Vec<(SocketAddr, usize)> {
+        self.remove_expired();
+        let mut tally: Vec<_> = count_votes(&self.votes_v4)
             .into_iter()
-            .filter(|v| v.1 >= self.minimum_threshold)
-            .max_by_key(|v| v.1)
-            .map(|v| v.0)
+            .chain(count_votes(&self.votes_v6))
+            .collect();
+        tally.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        tally
+    }
+
+    /// Removes any expired votes.
+    fn remove_expired(&mut self) {
+        let instant = Instant::now();
+        self.votes_v4.retain(|_, v| v.1 > instant);
+        self.votes_v6.retain(|_, v| v.1 > instant);
     }
 }
 
+/// Counts the votes for each `SocketAddr`.
+fn count_votes(votes: &HashMap<NodeId, (SocketAddr, Instant)>) -> FnvHashMap<SocketAddr, usize> {
+    let mut ip_count: FnvHashMap<SocketAddr, usize> = FnvHashMap::default();
+    for (socket, _) in votes.values() {
+        *ip_count.entry(*socket).or_insert_with(|| 0) += 1;
+    }
+    ip_count
+}
+
 #[cfg(test)]
 mod tests {
     use super::{IpVote, NodeId, SocketAddr};
@@ -81,7 +114,7 @@ mod tests {
         votes.insert(NodeId::random(), socket_3);
         votes.insert(NodeId::random(), socket_3);
 
-        assert_eq!(votes.majority(), Some(socket_2));
+        assert_eq!(votes.majority(), (Some(socket_2), None));
     }
 
     #[test]
@@ -96,7 +129,7 @@ mod tests {
         votes.insert(NodeId::random(), socket_2);
         votes.insert(NodeId::random(), socket_3);
 
-        assert_eq!(votes.majority(), Some(socket_1));
+        assert_eq!(votes.majority(), (Some(socket_1), None));
     }
 
     #[test]
@@ -111,6 +144,36 @@ mod tests {
         votes.insert(NodeId::random(), socket_2);
         votes.insert(NodeId::random(), socket_3);
 
-        assert_eq!(votes.majority(), None);
+        assert_eq!(votes.majority(), (None, None));
+    }
+
+    #[test]
+    fn test_tally() {
+        let mut votes = IpVote::new(2);
+        let socket_1 = SocketAddr::new("127.0.0.1".parse().unwrap(), 1);
+        let socket_2 = SocketAddr::new("127.0.0.1".parse().unwrap(), 2);
+
+        assert!(votes.tally().is_empty());
+        votes.insert(NodeId::random(), socket_1);
+        votes.insert(NodeId::random(), socket_2);
+        votes.insert(NodeId::random(), socket_2);
+
+        assert_eq!(votes.tally(), vec![(socket_2, 2), (socket_1, 1)]);
+    }
+
+    #[test]
+    fn test_families_voted_separately() {
+        let mut votes = IpVote::new(2);
+        let socket_v4 = SocketAddr::new("127.0.0.1".parse().unwrap(), 1);
+        let socket_v6 = SocketAddr::new("::1".parse().unwrap(), 1);
+
+        // each peer votes for a socket of each family
+        for _ in 0..2 {
+            let node_id = NodeId::random();
+            votes.insert(node_id, socket_v4);
+            votes.insert(node_id, socket_v6);
+        }
+
+        assert_eq!(votes.majority(), (Some(socket_v4), Some(socket_v6)));
     }
 }
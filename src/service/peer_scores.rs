@@ -0,0 +1,89 @@
+use enr::NodeId;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// The weight of a peer's previous score when an outcome is recorded, such that recent outcomes
+/// weigh more than older ones.
+const SCORE_DECAY: f64 = 0.9;
+
+/// The value recorded for a request to a peer that timed out.
+const TIMEOUT_PENALTY: f64 = -1.0;
+
+/// The value recorded for a response containing invalid ENRs.
+const INVALID_RESPONSE_PENALTY: f64 = -5.0;
+
+/// The scores of peers, accumulated from the outcomes of the requests made to them. A response
+/// is worth up to 1, less the longer its round-trip time, while timed out requests and invalid
+/// responses are penalised. Peers that have not been scored have no score.
+#[derive(Debug, Default)]
+pub struct PeerScores {
+    scores: HashMap<NodeId, f64>,
+}
+
+impl PeerScores {
+    /// Returns the score of a peer.
+    pub fn score(&self, node_id: &NodeId) -> Option<f64> {
+        self.scores.get(node_id).copied()
+    }
+
+    /// Records a response from a peer, received `rtt` after the request was sent.
+    pub fn on_response(&mut self, node_id: NodeId, rtt: Duration) {
+        self.record(node_id, 1.0 / (1.0 + rtt.as_secs_f64()));
+    }
+
+    /// Records a request to a peer that timed out.
+    pub fn on_timeout(&mut self, node_id: NodeId) {
+        self.record(node_id, TIMEOUT_PENALTY);
+    }
+
+    /// Records a response from a peer that contained invalid ENRs.
+    pub fn on_invalid_response(&mut self, node_id: NodeId) {
+        self.record(node_id, INVALID_RESPONSE_PENALTY);
+    }
+
+    /// Removes the scores of the peers for which `keep` returns false.
+    pub fn retain(&mut self, mut keep: impl FnMut(&NodeId) -> bool) {
+        self.scores.retain(|node_id, _| keep(node_id));
+    }
+
+    fn record(&mut self, node_id: NodeId, value: f64) {
+        let score = self.scores.entry(node_id).or_insert(0.0);
+        *score = *score * SCORE_DECAY + value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_follow_recent_outcomes() {
+        let mut scores = PeerScores::default();
+        let fast = NodeId::random();
+        let slow = NodeId::random();
+        let unreliable = NodeId::random();
+        assert_eq!(scores.score(&fast), None);
+
+        for _ in 0..5 {
+            scores.on_response(fast, Duration::from_millis(10));
+            scores.on_response(slow, Duration::from_secs(1));
+            scores.on_response(unreliable, Duration::from_millis(10));
+        }
+        scores.on_timeout(unreliable);
+        assert!(scores.score(&fast).unwrap() > scores.score(&slow).unwrap());
+        assert!(scores.score(&fast).unwrap() > scores.score(&unreliable).unwrap());
+
+        // an invalid response outweighs the previous responses
+        scores.on_invalid_response(fast);
+        assert!(scores.score(&fast).unwrap() < 0.0);
+
+        // recovers once the peer responds again
+        for _ in 0..50 {
+            scores.on_response(fast, Duration::from_millis(10));
+        }
+        assert!(scores.score(&fast).unwrap() > scores.score(&unreliable).unwrap());
+
+        scores.retain(|node_id| *node_id != slow);
+        assert_eq!(scores.score(&slow), None);
+    }
+}
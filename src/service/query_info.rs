@@ -1,16 +1,15 @@
+use crate::error::QueryError;
 use crate::kbucket::Key;
-use crate::query_pool::ReturnPeer;
+use crate::query_pool::{ReturnPeer, TargetKey};
 use crate::rpc::RequestBody;
 use crate::Enr;
 use enr::NodeId;
 use sha2::digest::generic_array::GenericArray;
 use smallvec::SmallVec;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
 use tokio::sync::oneshot;
 
-/// The number of distances to request when running a FINDNODE query. The probability that a peer returns
-/// any given target peer is `1 - 0.5**MAX_FINDNODE_REQUESTS`.
-const MAX_FINDNODE_REQUESTS: usize = 3;
-
 /// Information about a query.
 #[derive(Debug)]
 pub struct QueryInfo {
@@ -20,8 +19,64 @@ pub struct QueryInfo {
     /// Temporary ENRs used when trying to reach nodes.
     pub untrusted_enrs: SmallVec<[Enr; 16]>,
 
+    /// The ENRs used to contact peers during the query. These are used to fetch the ENRs of
+    /// resulting peers that are no longer known once the query finishes.
+    pub contacted_enrs: HashMap<NodeId, Enr>,
+
+    /// The peers that have been contacted during the query.
+    pub contacted_peers: HashSet<NodeId>,
+
+    /// The log2 distance to the target of the closest peer found so far.
+    pub closest_distance: Option<u64>,
+
+    /// When progress of the query was last reported, used to throttle the progress events.
+    pub last_progress: Option<Instant>,
+
+    /// Whether any peer has responded to the query.
+    pub responded: bool,
+
+    /// The minimum number of peers the query must find, otherwise the peers found are returned
+    /// as `QueryError::Partial`.
+    pub min_results: usize,
+
     /// A callback channel for the service that requested the query.
-    pub callback: oneshot::Sender<Vec<Enr>>,
+    pub callback: QueryCallback,
+}
+
+/// The channel the result of a query is returned on.
+#[derive(Debug)]
+pub enum QueryCallback {
+    /// Returns the ENRs of the resulting peers.
+    Enrs(oneshot::Sender<Result<Vec<Enr>, QueryError>>),
+    /// Returns the node ids of the resulting peers, without resolving their ENRs.
+    NodeIds(oneshot::Sender<Result<Vec<NodeId>, QueryError>>),
+    /// Returns the ENR of the given node, which is searched for by the query.
+    Enr(NodeId, oneshot::Sender<Option<Enr>>),
+    /// The query is part of a bootstrap of the routing table and its result is not returned.
+    Bootstrap,
+    /// The query is a background refresh of the routing table and its result is not returned.
+    Refresh,
+}
+
+impl QueryCallback {
+    /// Returns the result of a query, reducing the ENRs to their node ids if only these were
+    /// requested. Returns `false` if the receiver has been dropped.
+    pub fn respond(self, result: Result<Vec<Enr>, QueryError>) -> bool {
+        match self {
+            QueryCallback::Enrs(callback) => callback.send(result).is_ok(),
+            QueryCallback::NodeIds(callback) => callback
+                .send(result.map(|enrs| enrs.iter().map(Enr::node_id).collect()))
+                .is_ok(),
+            QueryCallback::Enr(node_id, callback) => callback
+                .send(
+                    result
+                        .ok()
+                        .and_then(|enrs| enrs.into_iter().find(|enr| enr.node_id() == node_id)),
+                )
+                .is_ok(),
+            QueryCallback::Bootstrap | QueryCallback::Refresh => true,
+        }
+    }
 }
 
 /// Additional information about the query.
@@ -29,6 +84,9 @@ pub struct QueryInfo {
 pub enum QueryType {
     /// The user requested a `FIND_PEER` query to be performed. It should be reported when finished.
     FindNode(NodeId),
+    /// The user requested a `FIND_PEER` query towards the target, requesting the given log2
+    /// distances from each peer rather than the distances of the target.
+    FindNodeDistances(NodeId, Vec<u64>),
 }
 
 impl QueryInfo {
@@ -38,39 +96,85 @@ impl QueryInfo {
         return_peer: &ReturnPeer<NodeId>,
     ) -> Result<RequestBody, &'static str> {
         let request = match self.query_type {
-            QueryType::FindNode(ref node_id) => {
-                let distance = findnode_log2distance(node_id, return_peer)
-                    .ok_or_else(|| "Requested a node find itself")?;
-                RequestBody::FindNode { distance }
+            QueryType::FindNode(_) => {
+                let distance = findnode_log2distance(&self.key(), return_peer)
+                    .ok_or("Requested a node find itself")?;
+                RequestBody::FindNode {
+                    distances: vec![distance],
+                }
+            }
+            QueryType::FindNodeDistances(_, ref distances) => {
+                let distance = *return_peer
+                    .iteration
+                    .checked_sub(1)
+                    .and_then(|index| distances.get(index))
+                    .ok_or("Requested an unknown distance")?;
+                if distance == 0 {
+                    RequestBody::GetEnr
+                } else {
+                    RequestBody::FindNode {
+                        distances: vec![distance],
+                    }
+                }
             }
         };
 
         Ok(request)
     }
 
-    pub fn iterations(&self) -> usize {
+    /// The number of requests sent to each peer. A `FindNode` query requests
+    /// `findnode_distance_spread` adjacent distances around the target.
+    pub fn iterations(&self, findnode_distance_spread: u64) -> usize {
         match &self.query_type {
-            QueryType::FindNode(_) => MAX_FINDNODE_REQUESTS,
+            QueryType::FindNode(_) => findnode_distance_spread as usize,
+            QueryType::FindNodeDistances(_, ref distances) => distances.len(),
+        }
+    }
+
+    /// Adds an ENR discovered during the query to the untrusted ENRs, unless one is already held
+    /// for the node. If more than `max_untrusted_enrs` are held, the ENR furthest from the target
+    /// by log2 distance is dropped, preferring to keep ENRs that were discovered first.
+    pub fn add_untrusted_enr(&mut self, enr: Enr, max_untrusted_enrs: usize) {
+        if self
+            .untrusted_enrs
+            .iter()
+            .any(|e| e.node_id() == enr.node_id())
+        {
+            return;
+        }
+        self.untrusted_enrs.push(enr);
+        if self.untrusted_enrs.len() > max_untrusted_enrs {
+            let target_key = self.key();
+            // the last of equally distant ENRs is the most recently discovered
+            if let Some((furthest, _)) = self
+                .untrusted_enrs
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, enr)| target_key.log2_distance(&enr.node_id().into()))
+            {
+                self.untrusted_enrs.remove(furthest);
+            }
         }
     }
 }
 
-impl crate::query_pool::TargetKey<NodeId> for QueryInfo {
+impl TargetKey<NodeId> for QueryInfo {
     fn key(&self) -> Key<NodeId> {
         match self.query_type {
-            QueryType::FindNode(ref node_id) => {
+            QueryType::FindNode(ref node_id) | QueryType::FindNodeDistances(ref node_id, _) => {
                 Key::new_raw(*node_id, *GenericArray::from_slice(&node_id.raw()))
             }
         }
     }
 }
 
-/// Calculates the log2 distance for a destination peer given a target and current iteration.
+/// Calculates the log2 distance for a destination peer given the key of the target and the current
+/// iteration.
 ///
 /// As the iteration increases, FINDNODE requests adjacent distances from the exact peer distance.
 ///
 /// As an example, if the target has a distance of 12 from the remote peer, the sequence of distances that are sent for increasing iterations would be [12, 13, 11, 14, 10, .. ].
-fn findnode_log2distance(target: &NodeId, return_peer: &ReturnPeer<NodeId>) -> Option<u64> {
+fn findnode_log2distance(target: &Key<NodeId>, return_peer: &ReturnPeer<NodeId>) -> Option<u64> {
     let iteration = return_peer.iteration as u64;
     if iteration > 127 {
         // invoke and endless loop - coding error
@@ -79,7 +183,7 @@ fn findnode_log2distance(target: &NodeId, return_peer: &ReturnPeer<NodeId>) -> O
 
     let dst_key: Key<NodeId> = return_peer.key.clone().into();
 
-    let distance = dst_key.log2_distance(&target.clone().into())?;
+    let distance = dst_key.log2_distance(target)?;
 
     let mut result_list = vec![distance];
     let mut difference = 1;
@@ -103,7 +207,7 @@ mod tests {
 
     #[test]
     fn test_log2distance() {
-        let target = NodeId::new(&[0u8; 32]);
+        let target = Key::from(NodeId::new(&[0u8; 32]));
         let mut destination = [0u8; 32];
         destination[10] = 1; // gives a log2 distance of 169
         let destination = NodeId::new(&destination);
@@ -122,9 +226,96 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_find_node_iterations() {
+        let (callback, _) = oneshot::channel();
+        let query_info = QueryInfo {
+            query_type: QueryType::FindNode(NodeId::random()),
+            untrusted_enrs: Default::default(),
+            contacted_enrs: Default::default(),
+            contacted_peers: Default::default(),
+            closest_distance: None,
+            last_progress: None,
+            responded: false,
+            min_results: 0,
+            callback: QueryCallback::Enrs(callback),
+        };
+        // a FINDNODE query requests one distance per iteration
+        assert_eq!(query_info.iterations(1), 1);
+        assert_eq!(query_info.iterations(5), 5);
+    }
+
+    #[test]
+    fn test_find_node_distances_request() {
+        let (callback, _) = oneshot::channel();
+        let query_info = QueryInfo {
+            query_type: QueryType::FindNodeDistances(NodeId::random(), vec![256, 200]),
+            untrusted_enrs: Default::default(),
+            contacted_enrs: Default::default(),
+            contacted_peers: Default::default(),
+            closest_distance: None,
+            last_progress: None,
+            responded: false,
+            min_results: 0,
+            callback: QueryCallback::Enrs(callback),
+        };
+        assert_eq!(query_info.iterations(3), 2);
+
+        let key = NodeId::random();
+        for (iteration, distance) in [(1, Some(256)), (2, Some(200)), (3, None)] {
+            let return_peer = ReturnPeer { key, iteration };
+            let request = query_info.rpc_request(&return_peer).ok();
+            assert_eq!(
+                request,
+                distance.map(|distance| RequestBody::FindNode {
+                    distances: vec![distance]
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_untrusted_enrs_limit() {
+        use enr::{CombinedKey, EnrBuilder};
+
+        let (callback, _) = oneshot::channel();
+        let mut query_info = QueryInfo {
+            query_type: QueryType::FindNode(NodeId::random()),
+            untrusted_enrs: Default::default(),
+            contacted_enrs: Default::default(),
+            contacted_peers: Default::default(),
+            closest_distance: None,
+            last_progress: None,
+            responded: false,
+            min_results: 0,
+            callback: QueryCallback::Enrs(callback),
+        };
+        let target_key = query_info.key();
+        let enrs: Vec<Enr> = (0..20)
+            .map(|_| {
+                EnrBuilder::new("v4")
+                    .build(&CombinedKey::generate_secp256k1())
+                    .unwrap()
+            })
+            .collect();
+        for enr in enrs.iter() {
+            query_info.add_untrusted_enr(enr.clone(), 5);
+            query_info.add_untrusted_enr(enr.clone(), 5);
+        }
+        assert_eq!(query_info.untrusted_enrs.len(), 5);
+
+        // only the closest ENRs to the target are kept
+        let distance = |enr: &Enr| target_key.log2_distance(&enr.node_id().into());
+        let mut distances: Vec<_> = enrs.iter().map(distance).collect();
+        distances.sort();
+        let mut kept: Vec<_> = query_info.untrusted_enrs.iter().map(distance).collect();
+        kept.sort();
+        assert_eq!(kept, distances[..5].to_vec());
+    }
+
     #[test]
     fn test_log2distance_lower() {
-        let target = NodeId::new(&[0u8; 32]);
+        let target = Key::from(NodeId::new(&[0u8; 32]));
         let mut destination = [0u8; 32];
         destination[31] = 8; // gives a log2 distance of 5
         let destination = NodeId::new(&destination);
@@ -146,7 +337,7 @@ mod tests {
 
     #[test]
     fn test_log2distance_upper() {
-        let target = NodeId::new(&[0u8; 32]);
+        let target = Key::from(NodeId::new(&[0u8; 32]));
         let mut destination = [0u8; 32];
         destination[0] = 8; // gives a log2 distance of 252
         let destination = NodeId::new(&destination);
@@ -0,0 +1,61 @@
+use crate::kbucket::MAX_NODES_PER_BUCKET;
+use rand::Rng;
+
+/// Selects the log2 distance of the bucket to refresh, biased towards the emptiest buckets.
+///
+/// `entries_per_bucket` holds the number of entries in each bucket, where the bucket at index `i`
+/// holds the nodes at log2 distance `i + 1`. Buckets closer than the closest occupied bucket are
+/// expected to be empty, so only this bucket and those further away are considered. Each bucket
+/// is weighted by its number of free slots plus one, such that full buckets are still refreshed
+/// occasionally. Returns `None` if every bucket is empty.
+pub(crate) fn select_refresh_distance(
+    entries_per_bucket: &[usize],
+    rng: &mut impl Rng,
+) -> Option<u64> {
+    let closest_occupied = entries_per_bucket.iter().position(|entries| *entries > 0)?;
+    let weights: Vec<usize> = entries_per_bucket[closest_occupied..]
+        .iter()
+        .map(|entries| MAX_NODES_PER_BUCKET.saturating_sub(*entries) + 1)
+        .collect();
+    let mut choice = rng.gen_range(0, weights.iter().sum::<usize>());
+    for (offset, weight) in weights.into_iter().enumerate() {
+        if choice < weight {
+            return Some((closest_occupied + offset + 1) as u64);
+        }
+        choice -= weight;
+    }
+    unreachable!("The choice is within the total weight")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_core::SeedableRng;
+
+    #[test]
+    fn empty_table() {
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(1);
+        assert_eq!(select_refresh_distance(&[0; 256], &mut rng), None);
+    }
+
+    #[test]
+    fn biased_towards_empty_buckets() {
+        let mut rng = rand_xorshift::XorShiftRng::seed_from_u64(1);
+        // the buckets at distances 254 and 256 are full, 255 is empty
+        let mut entries_per_bucket = [0; 256];
+        entries_per_bucket[253] = MAX_NODES_PER_BUCKET;
+        entries_per_bucket[255] = MAX_NODES_PER_BUCKET;
+
+        let mut counts = [0usize; 3];
+        for _ in 0..1000 {
+            let distance = select_refresh_distance(&entries_per_bucket, &mut rng).unwrap();
+            // closer buckets than the closest occupied bucket are never selected
+            assert!(distance >= 254);
+            counts[distance as usize - 254] += 1;
+        }
+        // the empty bucket has 17 times the weight of a full bucket
+        assert!(counts[1] > counts[0] * 5);
+        assert!(counts[1] > counts[2] * 5);
+        assert!(counts[0] > 0 && counts[2] > 0);
+    }
+}
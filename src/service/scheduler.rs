@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// The priority of a request to the handler. When the handler channel is congested, queued
+/// requests are sent in order of priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Priority {
+    /// Background maintenance, such as heartbeat pings and refreshes of the routing table.
+    Low,
+    /// Responses to peers and other handler requests.
+    Normal,
+    /// Requests made on behalf of the user, such as the RPCs of user queries.
+    High,
+}
+
+/// Queues the requests to the handler that could not be sent as its channel is full. Requests
+/// are taken highest priority first, and in the order they were queued within a priority. Once
+/// the queue holds `capacity` requests, the most recently queued request of the lowest priority
+/// is dropped first.
+pub(crate) struct Scheduler<T> {
+    /// The queued requests of each priority, indexed by priority.
+    queues: [VecDeque<T>; 3],
+    /// The maximum number of queued requests.
+    capacity: usize,
+    /// When a request was queued while the queue was empty. This is cleared once `pop` finds
+    /// the queue empty, such that requests returned with `push_front` do not reset it.
+    queued_since: Option<Instant>,
+}
+
+impl<T> Scheduler<T> {
+    pub fn new(capacity: usize) -> Self {
+        Scheduler {
+            queues: [VecDeque::new(), VecDeque::new(), VecDeque::new()],
+            capacity,
+            queued_since: None,
+        }
+    }
+
+    /// Returns whether no requests are queued.
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+
+    /// Returns the number of queued requests.
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    /// Returns since when requests have been queued without the queue being drained.
+    pub fn queued_since(&self) -> Option<Instant> {
+        self.queued_since
+    }
+
+    /// Queues a request behind all requests of the same or a higher priority. If the queue is
+    /// full, the most recently queued request of the lowest priority is dropped and returned,
+    /// which is the given request if no request of a lower priority is queued.
+    pub fn push(&mut self, priority: Priority, request: T) -> Option<(Priority, T)> {
+        if self.len() >= self.capacity {
+            let lowest = [Priority::Low, Priority::Normal, Priority::High]
+                .iter()
+                .copied()
+                .find(|priority| !self.queues[*priority as usize].is_empty());
+            match lowest {
+                Some(lowest) if lowest < priority => {
+                    let dropped = self.queues[lowest as usize]
+                        .pop_back()
+                        .map(|dropped| (lowest, dropped));
+                    self.queues[priority as usize].push_back(request);
+                    return dropped;
+                }
+                _ => return Some((priority, request)),
+            }
+        }
+        self.queued_since.get_or_insert_with(Instant::now);
+        self.queues[priority as usize].push_back(request);
+        None
+    }
+
+    /// Returns a request that could not be sent to the front of the queue.
+    pub fn push_front(&mut self, priority: Priority, request: T) {
+        self.queued_since.get_or_insert_with(Instant::now);
+        self.queues[priority as usize].push_front(request);
+    }
+
+    /// Takes the next request to be sent.
+    pub fn pop(&mut self) -> Option<(Priority, T)> {
+        let next = [Priority::High, Priority::Normal, Priority::Low]
+            .iter()
+            .find_map(|priority| {
+                self.queues[*priority as usize]
+                    .pop_front()
+                    .map(|request| (*priority, request))
+            });
+        if next.is_none() {
+            self.queued_since = None;
+        }
+        next
+    }
+
+    /// Drops all queued requests.
+    pub fn clear(&mut self) {
+        self.queues.iter_mut().for_each(VecDeque::clear);
+        self.queued_since = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_ordered_by_priority() {
+        let mut scheduler = Scheduler::new(10);
+        assert!(scheduler.is_empty());
+        scheduler.push(Priority::Low, 1);
+        scheduler.push(Priority::High, 2);
+        scheduler.push(Priority::Normal, 3);
+        scheduler.push(Priority::High, 4);
+        scheduler.push_front(Priority::Low, 5);
+
+        let order: Vec<_> = std::iter::from_fn(|| scheduler.pop()).collect();
+        assert_eq!(
+            order,
+            vec![
+                (Priority::High, 2),
+                (Priority::High, 4),
+                (Priority::Normal, 3),
+                (Priority::Low, 5),
+                (Priority::Low, 1)
+            ]
+        );
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn queued_since_until_drained() {
+        let mut scheduler = Scheduler::new(10);
+        assert_eq!(scheduler.queued_since(), None);
+        scheduler.push(Priority::Normal, 1);
+        scheduler.push(Priority::Low, 2);
+        let since = scheduler.queued_since().unwrap();
+        assert_eq!(scheduler.len(), 2);
+
+        // a request returned to the queue does not reset the time
+        let (priority, request) = scheduler.pop().unwrap();
+        scheduler.push_front(priority, request);
+        assert_eq!(scheduler.queued_since(), Some(since));
+
+        while scheduler.pop().is_some() {}
+        assert_eq!(scheduler.queued_since(), None);
+    }
+
+    #[test]
+    fn lowest_priority_dropped_when_full() {
+        let mut scheduler = Scheduler::new(3);
+        assert_eq!(scheduler.push(Priority::Low, 1), None);
+        assert_eq!(scheduler.push(Priority::Normal, 2), None);
+        assert_eq!(scheduler.push(Priority::Low, 3), None);
+
+        // the most recent request of the lowest priority makes way for a higher priority
+        assert_eq!(scheduler.push(Priority::High, 4), Some((Priority::Low, 3)));
+        assert_eq!(
+            scheduler.push(Priority::Normal, 5),
+            Some((Priority::Low, 1))
+        );
+        // a request is dropped itself if nothing of a lower priority is queued
+        assert_eq!(
+            scheduler.push(Priority::Normal, 6),
+            Some((Priority::Normal, 6))
+        );
+        assert_eq!(scheduler.len(), 3);
+
+        let order: Vec<_> = std::iter::from_fn(|| scheduler.pop()).collect();
+        assert_eq!(
+            order,
+            vec![
+                (Priority::High, 4),
+                (Priority::Normal, 2),
+                (Priority::Normal, 5)
+            ]
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use crate::kbucket::{ip_limiter, MAX_NODES_PER_BUCKET};
+use crate::rpc::TopicHash;
+use crate::Enr;
+use lru_time_cache::LruCache;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The time a topic advertisement is kept for.
+pub(crate) const TOPIC_AD_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+/// The time a ticket can be used for once its wait time has passed.
+const TICKET_REDEEM_WINDOW: Duration = Duration::from_secs(30);
+
+/// The maximum number of outstanding tickets. The least recently issued are dropped first.
+const MAX_TICKETS: usize = 1000;
+
+/// The maximum number of advertisements kept per topic, such that all advertisers of a topic
+/// can be returned in a single NODES response.
+const MAX_ADS_PER_TOPIC: usize = MAX_NODES_PER_BUCKET;
+
+/// The maximum number of advertisements kept per topic from a single /24 subnet, such that a
+/// single host cannot take over a topic.
+const MAX_ADS_PER_SUBNET: usize = 2;
+
+/// The advertisers of topics known to the local node, which are returned to TOPICQUERY requests.
+/// Nodes register as advertisers with REGTOPIC requests, using the tickets issued here.
+pub struct TopicTable {
+    /// The advertisers of each topic, along with the time their advertisement expires.
+    ads: HashMap<TopicHash, Vec<(Enr, Instant)>>,
+    /// The time an advertisement is kept for.
+    ad_lifetime: Duration,
+    /// The outstanding tickets of each node and topic, along with the time they become valid.
+    /// Nodes are keyed by their raw id.
+    tickets: LruCache<([u8; 32], TopicHash), (Vec<u8>, Instant)>,
+    /// The time a node must wait before its ticket becomes valid.
+    ticket_wait_time: Duration,
+}
+
+/// The outcome of a REGTOPIC request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Registration {
+    /// The node must wait `wait_time` seconds before registering with the ticket.
+    Ticket { ticket: Vec<u8>, wait_time: u64 },
+    /// The node has been added as an advertiser of the topic.
+    Confirmed,
+}
+
+impl TopicTable {
+    pub fn new(ad_lifetime: Duration, ticket_wait_time: Duration) -> Self {
+        TopicTable {
+            ads: HashMap::new(),
+            ad_lifetime,
+            tickets: LruCache::with_expiry_duration_and_capacity(
+                ticket_wait_time + TICKET_REDEEM_WINDOW,
+                MAX_TICKETS,
+            ),
+            ticket_wait_time,
+        }
+    }
+
+    /// Handles a REGTOPIC request of the node of `enr`.
+    ///
+    /// A node without an outstanding ticket for the topic is issued one. A node presenting its
+    /// ticket once the wait time has passed is added as an advertiser. Otherwise, the outstanding
+    /// ticket is returned with the remaining wait time, such that repeated requests do not reset
+    /// the wait. If the topic already has the maximum number of advertisements from the node's
+    /// subnet, the node is issued a new ticket instead.
+    pub fn register(&mut self, topic: TopicHash, enr: Enr, ticket: &[u8]) -> Registration {
+        self.register_at(topic, enr, ticket, Instant::now())
+    }
+
+    fn register_at(
+        &mut self,
+        topic: TopicHash,
+        enr: Enr,
+        ticket: &[u8],
+        now: Instant,
+    ) -> Registration {
+        let key = (enr.node_id().raw(), topic);
+        let (issued, valid_from) = match self.tickets.peek(&key) {
+            Some((issued, valid_from)) => (issued.clone(), *valid_from),
+            None => {
+                let issued = rand::random::<[u8; 32]>().to_vec();
+                let valid_from = now + self.ticket_wait_time;
+                self.tickets.insert(key, (issued.clone(), valid_from));
+                (issued, valid_from)
+            }
+        };
+
+        if issued == ticket && valid_from <= now {
+            self.tickets.remove(&key);
+            if self.add_ad_at(topic, enr, now) {
+                return Registration::Confirmed;
+            }
+            let issued = rand::random::<[u8; 32]>().to_vec();
+            self.tickets
+                .insert(key, (issued.clone(), now + self.ticket_wait_time));
+            return Registration::Ticket {
+                ticket: issued,
+                wait_time: self.ticket_wait_time.as_secs()
+                    + u64::from(self.ticket_wait_time.subsec_nanos() > 0),
+            };
+        }
+        // the wait time is rounded up, such that the ticket is valid once it has passed
+        let remaining = valid_from.saturating_duration_since(now);
+        Registration::Ticket {
+            ticket: issued,
+            wait_time: remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0),
+        }
+    }
+
+    /// Adds an advertisement of a topic. An existing advertisement of the node is replaced and
+    /// its lifetime renewed. If the topic has the maximum number of advertisements, the oldest is
+    /// removed.
+    ///
+    /// Returns false, and does not add the advertisement, if the topic already has
+    /// `MAX_ADS_PER_SUBNET` advertisements from the /24 subnet of the node.
+    pub fn add_ad(&mut self, topic: TopicHash, enr: Enr) -> bool {
+        self.add_ad_at(topic, enr, Instant::now())
+    }
+
+    fn add_ad_at(&mut self, topic: TopicHash, enr: Enr, now: Instant) -> bool {
+        let ads = self.ads.entry(topic).or_default();
+        ads.retain(|(ad, expires)| ad.node_id() != enr.node_id() && *expires > now);
+        let others: Vec<&Enr> = ads.iter().map(|(ad, _)| ad).collect();
+        if !ip_limiter(&enr, &others, MAX_ADS_PER_SUBNET) {
+            return false;
+        }
+        if ads.len() >= MAX_ADS_PER_TOPIC {
+            ads.remove(0);
+        }
+        ads.push((enr, now + self.ad_lifetime));
+        true
+    }
+
+    /// Returns the advertisers of a topic, removing expired advertisements.
+    pub fn ads(&mut self, topic: &TopicHash) -> Vec<Enr> {
+        self.ads_at(topic, Instant::now())
+    }
+
+    fn ads_at(&mut self, topic: &TopicHash, now: Instant) -> Vec<Enr> {
+        match self.ads.get_mut(topic) {
+            Some(ads) => {
+                ads.retain(|(_, expires)| *expires > now);
+                let enrs = ads.iter().map(|(enr, _)| enr.clone()).collect();
+                if ads.is_empty() {
+                    self.ads.remove(topic);
+                }
+                enrs
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enr::{CombinedKey, EnrBuilder};
+
+    fn build_enr() -> Enr {
+        EnrBuilder::new("v4")
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap()
+    }
+
+    fn build_enr_with_ip(ip: [u8; 4]) -> Enr {
+        EnrBuilder::new("v4")
+            .ip(ip.into())
+            .build(&CombinedKey::generate_secp256k1())
+            .unwrap()
+    }
+
+    #[test]
+    fn ads_are_replaced_and_limited() {
+        let mut table = TopicTable::new(TOPIC_AD_LIFETIME, Duration::from_secs(0));
+        let topic = [1; 32];
+        let enrs: Vec<Enr> = (0..MAX_ADS_PER_TOPIC + 1).map(|_| build_enr()).collect();
+
+        table.add_ad(topic, enrs[0].clone());
+        table.add_ad(topic, enrs[0].clone());
+        assert_eq!(table.ads(&topic), vec![enrs[0].clone()]);
+        assert!(table.ads(&[2; 32]).is_empty());
+
+        // the oldest advertisement is removed
+        for enr in enrs.iter().skip(1) {
+            table.add_ad(topic, enr.clone());
+        }
+        assert_eq!(table.ads(&topic), enrs[1..].to_vec());
+    }
+
+    #[test]
+    fn ads_expire() {
+        let mut table = TopicTable::new(Duration::from_secs(10), Duration::from_secs(0));
+        let topic = [1; 32];
+        let now = Instant::now();
+        assert!(table.add_ad_at(topic, build_enr(), now));
+        assert_eq!(table.ads_at(&topic, now + Duration::from_secs(5)).len(), 1);
+        assert!(table
+            .ads_at(&topic, now + Duration::from_secs(10))
+            .is_empty());
+    }
+
+    #[test]
+    fn ads_are_limited_per_subnet() {
+        let mut table = TopicTable::new(Duration::from_secs(10), Duration::from_secs(0));
+        let topic = [1; 32];
+        let now = Instant::now();
+
+        assert!(table.add_ad_at(topic, build_enr_with_ip([10, 0, 0, 1]), now));
+        assert!(table.add_ad_at(topic, build_enr_with_ip([10, 0, 0, 2]), now));
+        assert!(!table.add_ad_at(topic, build_enr_with_ip([10, 0, 0, 3]), now));
+        assert!(table.add_ad_at(topic, build_enr_with_ip([10, 0, 1, 1]), now));
+        // the limit is per topic
+        assert!(table.add_ad_at([2; 32], build_enr_with_ip([10, 0, 0, 3]), now));
+
+        // expired advertisements no longer count towards the limit
+        let later = now + Duration::from_secs(10);
+        assert!(table.add_ad_at(topic, build_enr_with_ip([10, 0, 0, 3]), later));
+    }
+
+    #[test]
+    fn registration_requires_valid_ticket() {
+        let mut table = TopicTable::new(TOPIC_AD_LIFETIME, Duration::from_millis(1500));
+        let topic = [1; 32];
+        let enr = build_enr();
+        let now = Instant::now();
+
+        // a ticket is issued, and returned again to repeated requests
+        let ticket = match table.register_at(topic, enr.clone(), &[], now) {
+            Registration::Ticket { ticket, wait_time } => {
+                assert_eq!(wait_time, 2);
+                ticket
+            }
+            Registration::Confirmed => panic!("Registered without a ticket"),
+        };
+        match table.register_at(topic, enr.clone(), &ticket, now) {
+            Registration::Ticket {
+                ticket: reissued, ..
+            } => assert_eq!(reissued, ticket),
+            Registration::Confirmed => panic!("Registered before the wait time"),
+        }
+        assert!(table.ads(&topic).is_empty());
+
+        // the ticket is accepted once the wait time has passed
+        let later = now + Duration::from_millis(1500);
+        assert_eq!(
+            table.register_at(topic, enr.clone(), &[0; 32], later),
+            Registration::Ticket {
+                ticket: ticket.clone(),
+                wait_time: 0
+            }
+        );
+        assert_eq!(
+            table.register_at(topic, enr.clone(), &ticket, later),
+            Registration::Confirmed
+        );
+        assert_eq!(table.ads_at(&topic, later), vec![enr]);
+    }
+}
@@ -2,24 +2,33 @@
 pub struct FilterConfig {
     /// Whether the packet filter is enabled or not.
     pub enabled: bool,
+    /// Whether the request limits below are enforced on unsolicited packets, independently of
+    /// `enabled`. Packets above the limits are dropped before being decoded.
+    pub rate_limit_enabled: bool,
     /// The maximum unsolicited requests per second. The average will be maintained such that packets will
     /// be rejected if above this rate. Responses to not add to this tally.
     pub max_requests_per_second: usize,
     /// The maximum number of requests per NodeId per second. This must be less than
-    /// `max_requests_per_second`.
+    /// `max_requests_per_second`. This is currently not enforced, as unsolicited packets are
+    /// limited before the sending node is known.
     pub max_requests_per_node_per_second: Option<f64>,
     /// The maximum requests tolerated per IP per second. This must be less than
     /// `max_requests_per_second`.
     pub max_requests_per_ip_per_second: Option<f64>,
+    /// The maximum number of IPs whose request rate is tracked. The least recently seen IP is
+    /// forgotten when this is exceeded.
+    pub max_tracked_ips: usize,
 }
 
 impl Default for FilterConfig {
     fn default() -> FilterConfig {
         FilterConfig {
             enabled: false,
-            max_requests_per_second: 10,
-            max_requests_per_node_per_second: Some(10.0),
-            max_requests_per_ip_per_second: Some(10.0),
+            rate_limit_enabled: true,
+            max_requests_per_second: 500,
+            max_requests_per_node_per_second: Some(50.0),
+            max_requests_per_ip_per_second: Some(50.0),
+            max_tracked_ips: 1000,
         }
     }
 }
@@ -44,13 +53,20 @@ impl FilterConfigBuilder {
         self
     }
 
+    /// Sets whether the request limits are enforced on unsolicited packets. They are enforced by
+    /// default.
+    pub fn rate_limit(&mut self, enabled: bool) -> &mut Self {
+        self.config.rate_limit_enabled = enabled;
+        self
+    }
+
     /// Set the maximum unsolicited requests per second.
     pub fn max_requests_per_second(&mut self, reqs_per_second: usize) -> &mut Self {
         self.config.max_requests_per_second = reqs_per_second;
         self
     }
 
-    /// Sets the maximum unsolicited requests per node per second.
+    /// Sets the maximum unsolicited requests per node per second. This is currently not enforced.
     pub fn max_requests_per_node_per_second(&mut self, reqs_per_node_per_second: f64) -> &mut Self {
         self.config.max_requests_per_node_per_second = Some(reqs_per_node_per_second);
         self
@@ -62,6 +78,12 @@ impl FilterConfigBuilder {
         self
     }
 
+    /// Sets the maximum number of IPs whose request rate is tracked.
+    pub fn max_tracked_ips(&mut self, max_tracked_ips: usize) -> &mut Self {
+        self.config.max_tracked_ips = max_tracked_ips;
+        self
+    }
+
     pub fn build(&self) -> FilterConfig {
         assert!(
             self.config.max_requests_per_node_per_second
@@ -6,12 +6,14 @@ use std::sync::atomic::Ordering;
 
 mod cache;
 mod config;
+mod rate_limiter;
 
 use crate::discv5::PERMIT_BAN_LIST;
 use crate::metrics::METRICS;
 use cache::ReceivedPacketCache;
 pub use config::{FilterConfig, FilterConfigBuilder};
 use log::debug;
+use rate_limiter::RateLimiter;
 use std::collections::HashMap;
 
 /// The packet filter which decides whether we accept or reject incoming packets.
@@ -25,6 +27,8 @@ pub(crate) struct Filter {
     /// An ordered (by time) collection of seen packets that have passed the first filter check and
     /// have an associated NodeId.
     _packets_received: ReceivedPacketCache<(SocketAddr, Packet)>,
+    /// Enforces the total and per IP request limits.
+    rate_limiter: RateLimiter,
 }
 
 impl Filter {
@@ -39,6 +43,7 @@ impl Filter {
                 config.max_requests_per_second,
                 METRICS.moving_window,
             ),
+            rate_limiter: RateLimiter::new(config),
         }
     }
 
@@ -49,14 +54,13 @@ impl Filter {
             return true;
         }
 
-        if PERMIT_BAN_LIST.read().ban_ips.get(&src.ip()).is_some() {
+        if PERMIT_BAN_LIST.read().is_ip_banned(&src.ip()) {
             debug!("Dropped unsolicited packet from banned src: {:?}", src);
             return false;
         }
 
-        // Add the un-solicited request to the cache
-        // If this is over the maximum requests per ENFORCED_SIZE_TIME, it will be rejected and return false.
-        let result = self.raw_packets_received.insert_reset(src.clone());
+        // Add the un-solicited request to the cache, which is used for the metrics
+        self.raw_packets_received.insert_reset(*src);
 
         // build the metrics
         METRICS
@@ -79,25 +83,8 @@ impl Filter {
         };
         *METRICS.requests_per_ip_per_second.write() = hashmap;
 
-        // run the filters
-        if self.config.enabled {
-            // if there is a restriction per IP, enforce it
-            if let Some(max_requests_per_ip_per_second) = self.config.max_requests_per_ip_per_second
-            {
-                if let Some(requests) = METRICS.requests_per_ip_per_second.read().get(&src.ip()) {
-                    if requests >= &max_requests_per_ip_per_second {
-                        debug!(
-                            "Dropped unsolicited packet from IP rate limit: {:?}",
-                            src.ip()
-                        );
-                        return false;
-                    }
-                }
-            }
-            result // filter based on whether the packet could get added to the cache or not
-        } else {
-            true
-        }
+        // enforce the total and per IP request limits
+        !self.config.rate_limit_enabled || self.rate_limiter.allow(src.ip())
     }
 
     pub fn final_pass(&mut self, _src: &SocketAddr, _packet: &Packet) -> bool {
@@ -0,0 +1,205 @@
+//! A token-bucket rate limiter enforcing the request limits of the `FilterConfig`.
+//!
+//! Every source IP is given its own bucket, along with a single global bucket shared by all
+//! sources. Packets are only accepted if a token can be taken from both, and a packet dropped by
+//! the global limit does not cost its source a token. Each bucket permits a
+//! burst of up to one second's worth of requests. The IP buckets are held in a bounded LRU
+//! cache, such that spoofing many source addresses cannot grow the limiter without bound.
+
+use super::FilterConfig;
+use crate::metrics::METRICS;
+use log::debug;
+use lru_time_cache::LruCache;
+use std::net::IpAddr;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+/// A bucket of tokens that is refilled at a constant rate up to a maximum of one second's worth
+/// of tokens.
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    /// The number of tokens currently available.
+    tokens: f64,
+    /// The last time the bucket was refilled.
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, now: Instant) -> Self {
+        TokenBucket {
+            tokens: burst(rate),
+            last_refill: now,
+        }
+    }
+
+    /// Refills the bucket and takes a single token, returning false if none are available.
+    fn try_take(&mut self, rate: f64, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst(rate));
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns a token taken for a packet that was dropped regardless.
+    fn refund(&mut self) {
+        self.tokens += 1.0;
+    }
+}
+
+/// The maximum number of tokens of a bucket refilled at `rate`.
+fn burst(rate: f64) -> f64 {
+    rate.max(1.0)
+}
+
+/// Limits the rate of unsolicited packets per IP and globally.
+pub(crate) struct RateLimiter {
+    /// The sustained number of packets per second accepted across all sources.
+    max_requests_per_second: f64,
+    /// The sustained number of packets per second accepted from a single IP, if limited.
+    max_requests_per_ip_per_second: Option<f64>,
+    /// The bucket shared by all sources.
+    global: TokenBucket,
+    /// The buckets of recently seen IPs.
+    ips: LruCache<IpAddr, TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &FilterConfig) -> Self {
+        let max_requests_per_second = config.max_requests_per_second as f64;
+        RateLimiter {
+            max_requests_per_second,
+            max_requests_per_ip_per_second: config.max_requests_per_ip_per_second,
+            global: TokenBucket::new(max_requests_per_second, Instant::now()),
+            ips: LruCache::with_capacity(config.max_tracked_ips.max(1)),
+        }
+    }
+
+    /// Returns whether a packet from `ip` is permitted. Dropped packets are counted in the
+    /// metrics.
+    pub fn allow(&mut self, ip: IpAddr) -> bool {
+        self.allow_at(ip, Instant::now())
+    }
+
+    fn allow_at(&mut self, ip: IpAddr, now: Instant) -> bool {
+        if let Some(rate) = self.max_requests_per_ip_per_second {
+            let bucket = self
+                .ips
+                .entry(ip)
+                .or_insert_with(|| TokenBucket::new(rate, now));
+            if !bucket.try_take(rate, now) {
+                debug!("Dropped unsolicited packet from IP rate limit: {:?}", ip);
+                METRICS
+                    .dropped_packets_ip_rate_limit
+                    .fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+        }
+
+        if !self.global.try_take(self.max_requests_per_second, now) {
+            // the packet was not dropped for its source, so it doesn't cost the source a token
+            if let Some(bucket) = self.ips.get_mut(&ip) {
+                bucket.refund();
+            }
+            debug!(
+                "Dropped unsolicited packet from global rate limit: {:?}",
+                ip
+            );
+            METRICS
+                .dropped_packets_global_rate_limit
+                .fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn config() -> FilterConfig {
+        FilterConfig {
+            enabled: true,
+            rate_limit_enabled: true,
+            max_requests_per_second: 3,
+            max_requests_per_node_per_second: None,
+            max_requests_per_ip_per_second: Some(2.0),
+            max_tracked_ips: 2,
+        }
+    }
+
+    #[test]
+    fn test_ip_and_global_limits() {
+        let mut limiter = RateLimiter::new(&config());
+        let now = Instant::now();
+        let first: IpAddr = "10.0.0.1".parse().unwrap();
+        let second: IpAddr = "10.0.0.2".parse().unwrap();
+
+        // each IP may burst up to its limit
+        assert!(limiter.allow_at(first, now));
+        assert!(limiter.allow_at(first, now));
+        assert!(!limiter.allow_at(first, now));
+
+        // the global burst is shared between IPs
+        assert!(limiter.allow_at(second, now));
+        assert!(!limiter.allow_at(second, now));
+
+        // tokens are refilled over time
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.allow_at(first, later));
+        assert!(!limiter.allow_at(first, later));
+    }
+
+    #[test]
+    fn test_ips_bounded() {
+        let mut limiter = RateLimiter::new(&config());
+        let now = Instant::now();
+        for i in 0..10u8 {
+            limiter.allow_at(IpAddr::from([10, 0, 0, i]), now);
+        }
+        assert_eq!(limiter.ips.len(), 2);
+    }
+
+    #[test]
+    fn test_global_drop_keeps_ip_tokens() {
+        let mut limiter = RateLimiter::new(&FilterConfig {
+            max_requests_per_second: 10,
+            ..config()
+        });
+        let now = Instant::now();
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+
+        // exhaust the global burst, then have the IP dropped by the global limit
+        for i in 0..5u8 {
+            assert!(limiter.allow_at(IpAddr::from([10, 0, 1, i]), now));
+            assert!(limiter.allow_at(IpAddr::from([10, 0, 1, i]), now));
+        }
+        assert!(!limiter.allow_at(ip, now));
+        assert!(!limiter.allow_at(ip, now));
+
+        // once the global bucket refills, the IP still has its full burst
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.allow_at(ip, later));
+        assert!(limiter.allow_at(ip, later));
+        assert!(!limiter.allow_at(ip, later));
+    }
+
+    #[test]
+    fn test_no_ip_limit() {
+        let mut limiter = RateLimiter::new(&FilterConfig {
+            max_requests_per_ip_per_second: None,
+            ..config()
+        });
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let now = Instant::now();
+        assert_eq!((0..10).filter(|_| limiter.allow_at(ip, now)).count(), 3);
+    }
+}
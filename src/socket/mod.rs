@@ -1,5 +1,7 @@
 use crate::packet::*;
 use crate::Executor;
+use log::{info, warn};
+use net2::UdpSocketExt;
 use parking_lot::RwLock;
 use recv::*;
 use send::*;
@@ -22,18 +24,26 @@ pub struct SocketConfig {
     pub executor: Box<dyn Executor + Send + Sync>,
     /// The listening socket.
     pub socket_addr: SocketAddr,
+    /// An already bound UDP socket to use, rather than binding to `socket_addr`.
+    pub socket: Option<std::net::UdpSocket>,
     /// Configuration details for the packet filter.
     pub filter_config: FilterConfig,
     pub expected_responses: Arc<RwLock<HashMap<SocketAddr, usize>>>,
     /// The WhoAreYou magic packet.
     pub whoareyou_magic: [u8; MAGIC_LENGTH],
+    /// The size to request for the socket's receive buffer, if any.
+    pub recv_buffer_size: Option<usize>,
+    /// The size to request for the socket's send buffer, if any.
+    pub send_buffer_size: Option<usize>,
 }
 
 /// Creates the UDP socket and handles the exit futures for the send/recv UDP handlers.
 pub struct Socket {
     pub send: mpsc::Sender<OutboundPacket>,
     pub recv: mpsc::Receiver<InboundPacket>,
-    sender_exit: Option<oneshot::Sender<()>>,
+    /// The sources of inbound packets that could not be decoded. Reports are rate-limited.
+    pub decode_failures: mpsc::Receiver<SocketAddr>,
+    sender_exit: Option<oneshot::Sender<oneshot::Sender<()>>>,
     recv_exit: Option<oneshot::Sender<()>>,
 }
 
@@ -41,8 +51,9 @@ impl Socket {
     /// Creates a UDP socket, spawns a send/recv task and returns the channels.
     /// If this struct is dropped, the send/recv tasks will shutdown.
     pub(crate) fn new(config: SocketConfig) -> Self {
-        // set up the UDP socket
-        let socket = {
+        // set up the UDP socket, unless one has been provided
+        let socket_addr = config.socket_addr;
+        let socket = config.socket.unwrap_or_else(|| {
             #[cfg(unix)]
             fn platform_specific(s: &net2::UdpBuilder) -> std::io::Result<()> {
                 net2::unix::UnixUdpBuilderExt::reuse_port(s, true)?;
@@ -58,9 +69,34 @@ impl Socket {
                 .expect("Could not reuse address");
             platform_specific(&builder).expect("Failed to set platform");
             builder
-                .bind(config.socket_addr)
+                .bind(socket_addr)
                 .expect("Could not bind to UDP socket")
-        };
+        });
+        // the OS may clamp the requested sizes, so report the sizes actually granted
+        if let Some(size) = config.recv_buffer_size {
+            match socket
+                .set_recv_buffer_size(size)
+                .and_then(|_| socket.recv_buffer_size())
+            {
+                Ok(granted) => info!(
+                    "UDP receive buffer size requested: {}, granted: {}",
+                    size, granted
+                ),
+                Err(e) => warn!("Could not set the UDP receive buffer size: {}", e),
+            }
+        }
+        if let Some(size) = config.send_buffer_size {
+            match socket
+                .set_send_buffer_size(size)
+                .and_then(|_| socket.send_buffer_size())
+            {
+                Ok(granted) => info!(
+                    "UDP send buffer size requested: {}, granted: {}",
+                    size, granted
+                ),
+                Err(e) => warn!("Could not set the UDP send buffer size: {}", e),
+            }
+        }
         let socket =
             tokio::net::UdpSocket::from_std(socket).expect("Could not instantiate UDP socket");
 
@@ -76,31 +112,42 @@ impl Socket {
             expected_responses: config.expected_responses,
         };
 
-        let (recv, recv_exit) = RecvHandler::spawn(recv_config);
+        let (recv, decode_failures, recv_exit) = RecvHandler::spawn(recv_config);
         // spawn the sender handler
         let (send, sender_exit) = SendHandler::spawn(config.executor.clone(), send_udp);
 
         Socket {
             send,
             recv,
+            decode_failures,
             sender_exit: Some(sender_exit),
             recv_exit: Some(recv_exit),
         }
     }
+
+    /// Shuts down the send/recv handlers, waiting until all queued outbound packets have been
+    /// sent.
+    pub(crate) async fn shutdown(&mut self) {
+        if let Some(exit) = self.recv_exit.take() {
+            let _ = exit.send(());
+        }
+        if let Some(exit) = self.sender_exit.take() {
+            let (done_send, done) = oneshot::channel();
+            if exit.send(done_send).is_ok() {
+                let _ = done.await;
+            }
+        }
+    }
 }
 
 impl std::ops::Drop for Socket {
-    // close the send/recv handlers
+    // close the send/recv handlers, if they have not already been shutdown
     fn drop(&mut self) {
-        self.sender_exit
-            .take()
-            .expect("Exit always exists")
-            .send(())
-            .unwrap_or_else(|_| ());
-        self.recv_exit
-            .take()
-            .expect("Exit always exists")
-            .send(())
-            .unwrap_or_else(|_| ());
+        if let Some(exit) = self.sender_exit.take() {
+            let _ = exit.send(oneshot::channel().0);
+        }
+        if let Some(exit) = self.recv_exit.take() {
+            let _ = exit.send(());
+        }
     }
 }
@@ -3,17 +3,24 @@
 //! Every UDP packet passes a filter before being processed.
 
 use super::filter::{Filter, FilterConfig};
+use crate::metrics::METRICS;
 use crate::packet::*;
 use crate::Executor;
 use log::{debug, trace};
 use parking_lot::RwLock;
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, oneshot};
 
 pub(crate) const MAX_PACKET_SIZE: usize = 1280;
 
+/// The minimum time between reports of packets that could not be decoded, such that a flood of
+/// malformed packets does not flood the application with events.
+const DECODE_FAILURE_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
 /// The object sent back by the Recv handler.
 pub struct InboundPacket {
     /// The originating socket addr.
@@ -46,6 +53,10 @@ pub(crate) struct RecvHandler {
     whoareyou_magic: [u8; MAGIC_LENGTH],
     /// The channel to send the packet handler.
     handler: mpsc::Sender<InboundPacket>,
+    /// The channel to report the sources of packets that could not be decoded.
+    decode_failures: mpsc::Sender<SocketAddr>,
+    /// The time the last decode failure was reported.
+    last_decode_failure_report: Option<Instant>,
     /// Exit channel to shutdown the recv handler.
     exit: oneshot::Receiver<()>,
 }
@@ -54,11 +65,16 @@ impl RecvHandler {
     /// Spawns the `RecvHandler` on a provided executor.
     pub(crate) fn spawn(
         config: RecvHandlerConfig,
-    ) -> (mpsc::Receiver<InboundPacket>, oneshot::Sender<()>) {
+    ) -> (
+        mpsc::Receiver<InboundPacket>,
+        mpsc::Receiver<SocketAddr>,
+        oneshot::Sender<()>,
+    ) {
         let (exit_sender, exit) = oneshot::channel();
 
         // create the channel to send decoded packets to the handler
         let (handler, handler_recv) = mpsc::channel(30);
+        let (decode_failures, decode_failures_recv) = mpsc::channel(10);
 
         let mut recv_handler = RecvHandler {
             recv: config.recv,
@@ -67,6 +83,8 @@ impl RecvHandler {
             whoareyou_magic: config.whoareyou_magic,
             expected_responses: config.expected_responses,
             handler,
+            decode_failures,
+            last_decode_failure_report: None,
             exit,
         };
 
@@ -75,7 +93,7 @@ impl RecvHandler {
             debug!("Recv handler starting");
             recv_handler.start().await;
         }));
-        (handler_recv, exit_sender)
+        (handler_recv, decode_failures_recv, exit_sender)
     }
 
     /// The main future driving the recv handler. This will shutdown when the exit future is fired.
@@ -96,6 +114,8 @@ impl RecvHandler {
     /// Handles in incoming packet. Passes through the filter, decodes and sends to the packet
     /// handler.
     async fn handle_inbound(&mut self, src: SocketAddr, length: usize) {
+        METRICS.bytes_received.fetch_add(length, Ordering::Relaxed);
+
         // Permit all expected responses
         let permitted = self.expected_responses.read().get(&src).is_some();
 
@@ -109,7 +129,12 @@ impl RecvHandler {
         let packet = match Packet::decode(&self.recv_buffer[..length], &self.whoareyou_magic) {
             Ok(p) => p,
             Err(e) => {
-                debug!("Packet decoding failed: {:?}", e); // could not decode the packet, drop it
+                // could not decode the packet, drop it
+                debug!("Packet decoding failed from: {}. Error: {:?}", src, e);
+                METRICS
+                    .dropped_packets_decode_failed
+                    .fetch_add(1, Ordering::Relaxed);
+                self.report_decode_failure(src);
                 return;
             }
         };
@@ -124,4 +149,16 @@ impl RecvHandler {
         // send the filtered decoded packet to the handler.
         self.handler.send(inbound).await.unwrap_or_else(|_| ());
     }
+
+    /// Reports the source of a packet that could not be decoded, at most once per
+    /// `DECODE_FAILURE_REPORT_INTERVAL`.
+    fn report_decode_failure(&mut self, src: SocketAddr) {
+        let now = Instant::now();
+        if matches!(self.last_decode_failure_report, Some(last) if now < last + DECODE_FAILURE_REPORT_INTERVAL)
+        {
+            return;
+        }
+        self.last_decode_failure_report = Some(now);
+        let _ = self.decode_failures.try_send(src);
+    }
 }
@@ -1,8 +1,10 @@
 //! This is a standalone task that encodes and sends Discv5 UDP packets
+use crate::metrics::METRICS;
 use crate::packet::*;
 use crate::Executor;
 use log::{debug, trace};
 use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
 use tokio::sync::{mpsc, oneshot};
 
 pub struct OutboundPacket {
@@ -18,8 +20,9 @@ pub(crate) struct SendHandler {
     send: tokio::net::udp::SendHalf,
     /// The channel to respond to send requests.
     handler_recv: mpsc::Receiver<OutboundPacket>,
-    /// Exit channel to shutdown the handler.
-    exit: oneshot::Receiver<()>,
+    /// Exit channel to shutdown the handler. Completion is confirmed on the provided channel once
+    /// all queued packets have been sent.
+    exit: oneshot::Receiver<oneshot::Sender<()>>,
 }
 
 impl SendHandler {
@@ -29,7 +32,10 @@ impl SendHandler {
     pub(crate) fn spawn(
         executor: Box<dyn Executor>,
         send: tokio::net::udp::SendHalf,
-    ) -> (mpsc::Sender<OutboundPacket>, oneshot::Sender<()>) {
+    ) -> (
+        mpsc::Sender<OutboundPacket>,
+        oneshot::Sender<oneshot::Sender<()>>,
+    ) {
         let (exit_send, exit) = oneshot::channel();
         let (handler_send, handler_recv) = mpsc::channel(30);
 
@@ -52,15 +58,34 @@ impl SendHandler {
         loop {
             tokio::select! {
                 Some(packet) = self.handler_recv.recv() => {
-                    if let Err(e) = self.send.send_to(&packet.packet.encode(), &packet.dst).await {
-                        trace!("Could not send packet. Error: {:?}", e);
-                    }
+                    self.send_packet(packet).await;
                 }
-                _ = &mut self.exit => {
+                exit = &mut self.exit => {
+                    // send any packets that are still queued before shutting down
+                    while let Ok(packet) = self.handler_recv.try_recv() {
+                        self.send_packet(packet).await;
+                    }
                     debug!("Send handler shutdown");
+                    if let Ok(done) = exit {
+                        let _ = done.send(());
+                    }
                     return;
                 }
             }
         }
     }
+
+    /// Encodes and sends a single packet.
+    async fn send_packet(&mut self, packet: OutboundPacket) {
+        match self
+            .send
+            .send_to(&packet.packet.encode(), &packet.dst)
+            .await
+        {
+            Ok(length) => {
+                METRICS.bytes_sent.fetch_add(length, Ordering::Relaxed);
+            }
+            Err(e) => trace!("Could not send packet. Error: {:?}", e),
+        }
+    }
 }